@@ -0,0 +1,152 @@
+use crate::utils::buffer::{PacketBufferRead, PacketBufferWrite};
+use std::io::{Error, ErrorKind};
+
+/// Upper limit of packets in one batch, so a single datagram can't make us allocate forever
+pub const MAX_BATCH_PACKETS: usize = 1024;
+
+/// A single Bedrock packet taken out of (or put into) a batch
+#[derive(Clone, Debug, PartialEq)]
+pub struct GamePacket {
+    /// 10 bit packet id
+    pub id: u16,
+    /// 2 bit id of the split screen player which sent the packet
+    pub sender_sub_client: u8,
+    /// 2 bit id of the split screen player the packet is meant for
+    pub target_sub_client: u8,
+    pub payload: Vec<u8>,
+}
+
+/// Packets bundled into one 0xfe game packet
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Batch {
+    pub packets: Vec<GamePacket>,
+}
+
+impl GamePacket {
+    pub fn new(id: u16, payload: Vec<u8>) -> GamePacket {
+        GamePacket {
+            id,
+            sender_sub_client: 0,
+            target_sub_client: 0,
+            payload,
+        }
+    }
+
+    /// Decodes a packet without its length prefix
+    pub fn decode(binary: &[u8]) -> Result<GamePacket, Error> {
+        let (header, header_len) = binary.read_var_u32(0)?;
+        Ok(GamePacket {
+            id: (header & 0x3ff) as u16,
+            sender_sub_client: ((header >> 10) & 0x03) as u8,
+            target_sub_client: ((header >> 12) & 0x03) as u8,
+            payload: binary[header_len..].to_vec(),
+        })
+    }
+
+    /// Encodes the packet without its length prefix
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        let header = (self.id as u32 & 0x3ff)
+            | ((self.sender_sub_client as u32 & 0x03) << 10)
+            | ((self.target_sub_client as u32 & 0x03) << 12);
+        binary.push_var_u32(header);
+        binary.push_slice(&self.payload);
+
+        binary
+    }
+}
+
+impl Batch {
+    pub fn new(packets: Vec<GamePacket>) -> Batch {
+        Batch { packets }
+    }
+
+    /// Splits the (decompressed and decrypted) content of a game packet into its packets
+    pub fn decode(binary: &[u8]) -> Result<Batch, Error> {
+        let mut packets = Vec::new();
+        let mut offset = 0;
+        while offset < binary.len() {
+            if packets.len() >= MAX_BATCH_PACKETS {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("batch has more than {} packets", MAX_BATCH_PACKETS),
+                ));
+            }
+            let (len, len_len) = binary.read_var_u32(offset)?;
+            offset += len_len;
+            let end = offset
+                .checked_add(len as usize)
+                .filter(|end| *end <= binary.len())
+                .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, format!("packet of {} bytes is truncated", len)))?;
+            if len == 0 {
+                return Err(Error::new(ErrorKind::InvalidData, "batch contains an empty packet"));
+            }
+            packets.push(GamePacket::decode(&binary[offset..end])?);
+            offset = end;
+        }
+        Ok(Batch { packets })
+    }
+
+    /// Encodes the packets, each prefixed with its length
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        for packet in &self.packets {
+            let packet = packet.encode(Vec::new());
+            binary.push_var_u32(packet.len() as u32);
+            binary.push_slice(&packet);
+        }
+
+        binary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut split_screen = GamePacket::new(0x3ff, vec![1, 2, 3]);
+        split_screen.sender_sub_client = 2;
+        split_screen.target_sub_client = 3;
+        let batch = Batch::new(vec![
+            GamePacket::new(0x01, b"login".to_vec()),
+            GamePacket::new(0xc1, Vec::new()),
+            split_screen,
+            GamePacket::new(0x02, vec![0; 300]),
+        ]);
+        let binary = batch.encode(Vec::new());
+        assert_eq!(&binary[..7], &[6, 0x01, b'l', b'o', b'g', b'i', b'n']);
+        // ids above 0x7f take up two bytes of the header
+        assert_eq!(&binary[7..10], &[2, 0xc1, 0x01]);
+        assert_eq!(Batch::decode(&binary).unwrap(), batch);
+        assert_eq!(Batch::decode(&[]).unwrap(), Batch::default());
+    }
+
+    #[test]
+    fn header() {
+        let packet = GamePacket::decode(&[0xc1, 0x77, 0xaa]).unwrap();
+        assert_eq!(packet.id, 0x3c1);
+        assert_eq!(packet.sender_sub_client, 2);
+        assert_eq!(packet.target_sub_client, 3);
+        assert_eq!(packet.payload, vec![0xaa]);
+        assert_eq!(packet.encode(Vec::new()), vec![0xc1, 0x77, 0xaa]);
+    }
+
+    #[test]
+    fn truncated() {
+        let binary = Batch::new(vec![GamePacket::new(0x01, vec![0; 200])]).encode(Vec::new());
+        // the length prefix takes up two bytes
+        assert!(Batch::decode(&binary[..1]).is_err());
+        assert!(Batch::decode(&binary[..binary.len() - 1]).is_err());
+        assert!(Batch::decode(&[0x80]).is_err());
+        assert!(Batch::decode(&[0xff, 0xff, 0xff, 0xff, 0x0f]).is_err());
+        assert!(Batch::decode(&[0x00]).is_err());
+    }
+
+    #[test]
+    fn packet_limit() {
+        let batch = Batch::new(vec![GamePacket::new(0x01, Vec::new()); MAX_BATCH_PACKETS]);
+        assert_eq!(Batch::decode(&batch.encode(Vec::new())).unwrap().packets.len(), MAX_BATCH_PACKETS);
+        let batch = Batch::new(vec![GamePacket::new(0x01, Vec::new()); MAX_BATCH_PACKETS + 1]);
+        assert!(Batch::decode(&batch.encode(Vec::new())).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression as DeflateLevel;
+use std::io::{Error, ErrorKind, Read, Write};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum CompressionAlgorithm {
+    /// raw deflate, called zlib by the game
+    Zlib = 0x00,
+    Snappy = 0x01,
+    None = 0xff,
+}
+
+impl CompressionAlgorithm {
+    pub fn from_u8(byte: u8) -> Option<CompressionAlgorithm> {
+        match byte {
+            0x00 => Some(CompressionAlgorithm::Zlib),
+            0x01 => Some(CompressionAlgorithm::Snappy),
+            0xff => Some(CompressionAlgorithm::None),
+            _ => None,
+        }
+    }
+}
+
+/// How the content of game packets is compressed
+#[derive(Clone, Debug)]
+pub struct Compression {
+    algorithm: CompressionAlgorithm,
+    level: u32,
+    threshold: usize,
+    max_decompressed_size: usize,
+    algorithm_header: bool,
+}
+
+impl Compression {
+    pub fn new(algorithm: CompressionAlgorithm) -> Compression {
+        Compression {
+            algorithm,
+            level: 7,
+            threshold: 256,
+            max_decompressed_size: 8 * 1024 * 1024,
+            algorithm_header: true,
+        }
+    }
+
+    /// Raw deflate without the algorithm byte, as used by clients before 1.20.60
+    pub fn legacy() -> Compression {
+        let mut compression = Compression::new(CompressionAlgorithm::Zlib);
+        compression.set_algorithm_header(false);
+        compression
+    }
+
+    /// Batches as they are, used until the network settings have been negotiated
+    pub fn uncompressed() -> Compression {
+        let mut compression = Compression::new(CompressionAlgorithm::None);
+        compression.set_algorithm_header(false);
+        compression
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: CompressionAlgorithm) {
+        self.algorithm = algorithm;
+    }
+
+    pub fn get_algorithm(&self) -> CompressionAlgorithm {
+        self.algorithm
+    }
+
+    /// Sets the deflate level, from 0 (none) to 9 (best)
+    pub fn set_level(&mut self, level: u32) {
+        self.level = level.min(9);
+    }
+
+    /// Sets the size from which on batches are compressed
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold;
+    }
+
+    pub fn get_threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Sets the size a batch may have after decompression, anything larger is rejected
+    pub fn set_max_decompressed_size(&mut self, max_decompressed_size: usize) {
+        self.max_decompressed_size = max_decompressed_size;
+    }
+
+    /// Sets whether the compressed data is prefixed with the algorithm byte
+    pub fn set_algorithm_header(&mut self, algorithm_header: bool) {
+        self.algorithm_header = algorithm_header;
+    }
+
+    pub fn compress(&self, batch: &[u8]) -> Result<Vec<u8>, Error> {
+        if !self.algorithm_header {
+            // without a header the data always has to be compressed, small batches are just stored
+            return match self.algorithm {
+                CompressionAlgorithm::Zlib => deflate(batch, if batch.len() < self.threshold { 0 } else { self.level }),
+                CompressionAlgorithm::Snappy => snappy(batch),
+                CompressionAlgorithm::None => Ok(batch.to_vec()),
+            };
+        }
+        let algorithm = if batch.len() < self.threshold {
+            CompressionAlgorithm::None
+        } else {
+            self.algorithm
+        };
+        let mut binary = vec![algorithm as u8];
+        match algorithm {
+            CompressionAlgorithm::Zlib => binary.extend(deflate(batch, self.level)?),
+            CompressionAlgorithm::Snappy => binary.extend(snappy(batch)?),
+            CompressionAlgorithm::None => binary.extend_from_slice(batch),
+        }
+        Ok(binary)
+    }
+
+    pub fn decompress(&self, binary: &[u8]) -> Result<Vec<u8>, Error> {
+        if !self.algorithm_header {
+            return match self.algorithm {
+                CompressionAlgorithm::Zlib => self.inflate(binary),
+                CompressionAlgorithm::Snappy => self.unsnap(binary),
+                CompressionAlgorithm::None => {
+                    self.check_size(binary.len())?;
+                    Ok(binary.to_vec())
+                }
+            };
+        }
+        let (header, data) = binary
+            .split_first()
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "compressed batch is empty"))?;
+        match CompressionAlgorithm::from_u8(*header) {
+            Some(CompressionAlgorithm::Zlib) => self.inflate(data),
+            Some(CompressionAlgorithm::Snappy) => self.unsnap(data),
+            Some(CompressionAlgorithm::None) => {
+                self.check_size(data.len())?;
+                Ok(data.to_vec())
+            }
+            None => Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unknown compression algorithm 0x{:02x}", header),
+            )),
+        }
+    }
+
+    fn inflate(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut batch = Vec::new();
+        // read one byte more than allowed to notice oversized batches without inflating all of them
+        DeflateDecoder::new(data)
+            .take(self.max_decompressed_size as u64 + 1)
+            .read_to_end(&mut batch)?;
+        self.check_size(batch.len())?;
+        Ok(batch)
+    }
+
+    fn unsnap(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+        let len = snap::raw::decompress_len(data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        self.check_size(len)?;
+        snap::raw::Decoder::new()
+            .decompress_vec(data)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+
+    fn check_size(&self, len: usize) -> Result<(), Error> {
+        if len > self.max_decompressed_size {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("decompressed batch is larger than {} bytes", self.max_decompressed_size),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn deflate(batch: &[u8], level: u32) -> Result<Vec<u8>, Error> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), DeflateLevel::new(level));
+    encoder.write_all(batch)?;
+    encoder.finish()
+}
+
+fn snappy(batch: &[u8]) -> Result<Vec<u8>, Error> {
+    snap::raw::Encoder::new()
+        .compress_vec(batch)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch() -> Vec<u8> {
+        (0..4096).map(|i| (i % 7) as u8).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        for algorithm in [CompressionAlgorithm::Zlib, CompressionAlgorithm::Snappy, CompressionAlgorithm::None] {
+            let compression = Compression::new(algorithm);
+            let compressed = compression.compress(&batch()).unwrap();
+            assert_eq!(compressed[0], algorithm as u8);
+            assert_eq!(compression.decompress(&compressed).unwrap(), batch());
+        }
+    }
+
+    #[test]
+    fn small_batches_are_not_compressed() {
+        let compression = Compression::new(CompressionAlgorithm::Zlib);
+        let compressed = compression.compress(b"small").unwrap();
+        assert_eq!(compressed, b"\xffsmall");
+        assert_eq!(compression.decompress(&compressed).unwrap(), b"small");
+    }
+
+    #[test]
+    fn without_header() {
+        let compression = Compression::legacy();
+        for batch in [batch(), b"small".to_vec()] {
+            let compressed = compression.compress(&batch).unwrap();
+            assert_eq!(compression.decompress(&compressed).unwrap(), batch);
+        }
+        let compression = Compression::uncompressed();
+        assert_eq!(compression.compress(b"batch").unwrap(), b"batch");
+    }
+
+    #[test]
+    fn oversized_batches_are_rejected() {
+        for algorithm in [CompressionAlgorithm::Zlib, CompressionAlgorithm::Snappy, CompressionAlgorithm::None] {
+            let mut compression = Compression::new(algorithm);
+            let compressed = compression.compress(&batch()).unwrap();
+            compression.set_max_decompressed_size(batch().len());
+            assert!(compression.decompress(&compressed).is_ok());
+            compression.set_max_decompressed_size(batch().len() - 1);
+            assert_eq!(compression.decompress(&compressed).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn zip_bomb() {
+        let mut compression = Compression::new(CompressionAlgorithm::Zlib);
+        let compressed = compression.compress(&vec![0; 16 * 1024 * 1024]).unwrap();
+        assert!(compressed.len() < 64 * 1024);
+        compression.set_max_decompressed_size(1024 * 1024);
+        assert!(compression.decompress(&compressed).is_err());
+    }
+
+    #[test]
+    fn invalid_data() {
+        let compression = Compression::new(CompressionAlgorithm::Zlib);
+        assert!(compression.decompress(&[]).is_err());
+        assert!(compression.decompress(&[0x02, 0x00]).is_err());
+        assert!(compression.decompress(&[0x01, 0xff, 0xff]).is_err());
+    }
+}
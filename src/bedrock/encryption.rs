@@ -0,0 +1,222 @@
+use crate::bedrock::jwt;
+use crate::bedrock::packets::ServerToClientHandshake;
+use crate::utils::buffer::PacketBufferWrite;
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, StreamCipher};
+use aes::Aes256;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use cfb8::{Decryptor, Encryptor};
+use ctr::Ctr32BE;
+use p384::ecdsa::SigningKey;
+use p384::pkcs8::{DecodePublicKey, EncodePublicKey};
+use p384::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::io::{Error, ErrorKind};
+
+/// Cipher mode game packets are encrypted with, which depends on the protocol version
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CipherMode {
+    /// AES-256-CFB8 with the first 16 bytes of the key as IV, used before 1.16.220
+    Cfb8,
+    /// AES-256-CTR with the first 12 bytes of the key and the counter 2 as IV
+    Ctr,
+}
+
+enum Cipher {
+    Cfb8 {
+        encryptor: Box<Encryptor<Aes256>>,
+        decryptor: Box<Decryptor<Aes256>>,
+    },
+    Ctr {
+        encryptor: Box<Ctr32BE<Aes256>>,
+        decryptor: Box<Ctr32BE<Aes256>>,
+    },
+}
+
+/// AES-256 cipher state of an encrypted session
+pub struct Encryption {
+    key: [u8; 32],
+    cipher: Cipher,
+    send_counter: u64,
+    receive_counter: u64,
+}
+
+impl Encryption {
+    pub fn new(key: [u8; 32], mode: CipherMode) -> Encryption {
+        let cipher = match mode {
+            CipherMode::Cfb8 => {
+                let iv = GenericArray::from_slice(&key[0..16]);
+                Cipher::Cfb8 {
+                    encryptor: Box::new(Encryptor::new(GenericArray::from_slice(&key), iv)),
+                    decryptor: Box::new(Decryptor::new(GenericArray::from_slice(&key), iv)),
+                }
+            }
+            CipherMode::Ctr => {
+                let mut iv = [0u8; 16];
+                iv[0..12].copy_from_slice(&key[0..12]);
+                iv[15] = 2;
+                let iv = GenericArray::from_slice(&iv);
+                Cipher::Ctr {
+                    encryptor: Box::new(Ctr32BE::new(GenericArray::from_slice(&key), iv)),
+                    decryptor: Box::new(Ctr32BE::new(GenericArray::from_slice(&key), iv)),
+                }
+            }
+        };
+        Encryption {
+            key,
+            cipher,
+            send_counter: 0,
+            receive_counter: 0,
+        }
+    }
+
+    /// Derives the session key from the ECDH shared secret and the salt of the handshake
+    pub fn from_shared_secret(salt: &[u8], shared_secret: &[u8], mode: CipherMode) -> Encryption {
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(shared_secret);
+        Encryption::new(hasher.finalize().into(), mode)
+    }
+
+    /// Encrypts the content of a game packet and appends its checksum
+    pub fn encrypt(&mut self, payload: &[u8]) -> Vec<u8> {
+        let mut binary = payload.to_vec();
+        binary.push_slice(&self.checksum(self.send_counter, payload));
+        self.send_counter += 1;
+        match &mut self.cipher {
+            Cipher::Cfb8 { encryptor, .. } => {
+                for byte in binary.iter_mut() {
+                    encryptor.encrypt_block_mut(GenericArray::from_mut_slice(std::slice::from_mut(byte)));
+                }
+            }
+            Cipher::Ctr { encryptor, .. } => encryptor.apply_keystream(&mut binary),
+        }
+
+        binary
+    }
+
+    /// Decrypts the content of a game packet and verifies its checksum
+    pub fn decrypt(&mut self, binary: &[u8]) -> Result<Vec<u8>, Error> {
+        if binary.len() < 8 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "encrypted packet is shorter than its checksum"));
+        }
+        let mut payload = binary.to_vec();
+        match &mut self.cipher {
+            Cipher::Cfb8 { decryptor, .. } => {
+                for byte in payload.iter_mut() {
+                    decryptor.decrypt_block_mut(GenericArray::from_mut_slice(std::slice::from_mut(byte)));
+                }
+            }
+            Cipher::Ctr { decryptor, .. } => decryptor.apply_keystream(&mut payload),
+        }
+        let checksum = payload.split_off(payload.len() - 8);
+        if checksum != self.checksum(self.receive_counter, &payload) {
+            return Err(Error::new(ErrorKind::InvalidData, "encrypted packet has an invalid checksum"));
+        }
+        self.receive_counter += 1;
+        Ok(payload)
+    }
+
+    fn checksum(&self, counter: u64, payload: &[u8]) -> [u8; 8] {
+        let mut hasher = Sha256::new();
+        hasher.update(counter.to_le_bytes());
+        hasher.update(payload);
+        hasher.update(self.key);
+        let mut checksum = [0u8; 8];
+        checksum.copy_from_slice(&hasher.finalize()[0..8]);
+        checksum
+    }
+}
+
+/// Starts the key exchange with a client, given the base64 encoded public key from its login.
+/// Returns the cipher state and the ServerToClientHandshake packet which has to be sent unencrypted.
+pub fn handshake(client_public_key: &str, mode: CipherMode) -> Result<(Encryption, ServerToClientHandshake), Error> {
+    let client_public_key = STANDARD
+        .decode(client_public_key)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let client_public_key = PublicKey::from_public_key_der(&client_public_key)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid client public key: {}", e)))?;
+
+    let server_key = SecretKey::random(&mut OsRng);
+    let server_public_key = server_key
+        .public_key()
+        .to_public_key_der()
+        .map_err(Error::other)?;
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let shared_secret = p384::ecdh::diffie_hellman(server_key.to_nonzero_scalar(), client_public_key.as_affine());
+
+    let token = jwt::encode(
+        &json!({
+            "alg": "ES384",
+            "x5u": STANDARD.encode(server_public_key.as_bytes()),
+        }),
+        &json!({
+            "salt": STANDARD.encode(salt),
+        }),
+        &SigningKey::from(server_key),
+    );
+    Ok((
+        Encryption::from_shared_secret(&salt, shared_secret.raw_secret_bytes(), mode),
+        ServerToClientHandshake::create(token),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::{BlockEncrypt, KeyInit};
+
+    fn pair(mode: CipherMode) -> (Encryption, Encryption) {
+        let key = [7u8; 32];
+        (Encryption::new(key, mode), Encryption::new(key, mode))
+    }
+
+    #[test]
+    fn round_trip() {
+        for mode in [CipherMode::Cfb8, CipherMode::Ctr] {
+            let (mut server, mut client) = pair(mode);
+            for payload in [&b"first"[..], &b""[..], &[0xfe; 100][..]] {
+                let encrypted = server.encrypt(payload);
+                assert_eq!(encrypted.len(), payload.len() + 8);
+                assert_eq!(client.decrypt(&encrypted).unwrap(), payload);
+            }
+        }
+    }
+
+    #[test]
+    fn ctr_iv() {
+        let key: [u8; 32] = std::array::from_fn(|i| i as u8);
+        let mut block = [0u8; 16];
+        block[0..12].copy_from_slice(&key[0..12]);
+        block[15] = 2;
+        let mut block = GenericArray::from(block);
+        Aes256::new(GenericArray::from_slice(&key)).encrypt_block(&mut block);
+        // the keystream of CTR is the encrypted counter block, so zeros encrypt to it
+        let encrypted = Encryption::new(key, CipherMode::Ctr).encrypt(&[0; 16]);
+        assert_eq!(&encrypted[0..16], block.as_slice());
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let (mut server, mut client) = pair(CipherMode::Ctr);
+        let mut encrypted = server.encrypt(b"payload");
+        encrypted[0] ^= 1;
+        assert!(client.decrypt(&encrypted).is_err());
+        assert!(client.decrypt(&[0; 7]).is_err());
+    }
+
+    #[test]
+    fn rejects_replay() {
+        let (mut server, mut client) = pair(CipherMode::Cfb8);
+        let first = server.encrypt(b"payload");
+        assert!(client.decrypt(&first).is_ok());
+        // the checksum covers the counter, which has moved on
+        let (mut server, _) = pair(CipherMode::Cfb8);
+        assert!(client.decrypt(&server.encrypt(b"payload")).is_err());
+    }
+}
@@ -0,0 +1,536 @@
+use crate::bedrock::batch::{Batch, GamePacket};
+use crate::bedrock::compression::Compression;
+use crate::bedrock::login::Login;
+use crate::bedrock::packets::{
+    ChunkRadiusUpdated, GamePacketId, LevelChunk, NetworkChunkPublisherUpdate, NetworkSettings, PackInfo,
+    PackStackEntry, Packet, PlayStatus, PlayStatusType, RequestChunkRadius, RequestNetworkSettings,
+    ResourcePackChunkData, ResourcePackChunkRequest, ResourcePackClientResponse, ResourcePackDataInfo,
+    ResourcePackResponse, ResourcePackStack, ResourcePacksInfo, SetLocalPlayerAsInitialized, StartGame,
+};
+use crate::bedrock::registry::PacketRegistry;
+use crate::bedrock::resource_packs::PackType;
+use crate::protocol::PacketId;
+use crate::server::Server;
+use crate::utils::buffer::PacketBufferRead;
+use std::net::SocketAddr;
+
+pub trait GameHandler {
+    /// Handles a 0xfe game packet taken out of a frame
+    fn handle_game_packet(&mut self, packet: &[u8], src: SocketAddr);
+}
+
+impl GameHandler for Server {
+    fn handle_game_packet(&mut self, packet_bytes: &[u8], src: SocketAddr) {
+        let client = match self.clients.get_mut(&src.to_string()) {
+            Some(client) if !client.is_closing() => client,
+            Some(_) => return,
+            None => {
+                debug!("Received game packet from unconnected {}, ignoring", src);
+                return;
+            }
+        };
+        if client.protocol().is_none() {
+            if let Some(request) = network_settings_request(packet_bytes) {
+                self.handle_network_settings_request(&request, src);
+                return;
+            }
+        }
+        let encrypted = client.is_encrypted();
+        let batch = match client.decode_game_packet(packet_bytes) {
+            Ok(batch) => batch,
+            // the cipher of the client is out of step with ours, nothing it sends can be trusted
+            Err(e) if encrypted => {
+                warn!("Failed to decrypt game packet from {}: {}", src, e);
+                self.kick(src, "disconnectionScreen.badPacket");
+                return;
+            }
+            Err(e) => {
+                warn!("Failed to decode game packet from {}: {}", src, e);
+                return;
+            }
+        };
+        for packet in batch.packets {
+            debug!("Received game packet 0x{:02x} from {}", packet.id, src);
+            let registry = match self.session_registry(&packet, src) {
+                Some(registry) => registry,
+                None => return,
+            };
+            let packet = match registry.decode(&packet) {
+                Ok(packet) => packet,
+                Err(e) if packet.id == GamePacketId::Login as u16 => {
+                    warn!("Failed to decode the login of {}: {}", src, e);
+                    self.kick(src, "disconnectionScreen.notAuthorized");
+                    return;
+                }
+                Err(e) => {
+                    warn!("Failed to decode game packet 0x{:02x} from {}: {}", packet.id, src, e);
+                    continue;
+                }
+            };
+            let needs_login = matches!(
+                packet,
+                Packet::ResourcePackClientResponse(_)
+                    | Packet::ResourcePackChunkRequest(_)
+                    | Packet::RequestChunkRadius(_)
+                    | Packet::SetLocalPlayerAsInitialized(_)
+            );
+            if needs_login && !self.clients.get(&src.to_string()).is_some_and(|client| client.is_logged_in()) {
+                warn!("{} sent game packet 0x{:02x} before logging in", src, packet.id());
+                self.kick(src, "disconnectionScreen.notAuthorized");
+                return;
+            }
+            match packet {
+                Packet::Login(login) => self.handle_login(&login, src),
+                Packet::ClientToServerHandshake(_) => self.handle_client_handshake(src),
+                Packet::ResourcePackClientResponse(response) => self.handle_resource_pack_response(&response, src),
+                Packet::ResourcePackChunkRequest(request) => self.handle_resource_pack_chunk_request(&request, src),
+                Packet::RequestChunkRadius(request) => self.handle_chunk_radius_request(&request, src),
+                Packet::SetLocalPlayerAsInitialized(packet) => self.handle_local_player_initialized(&packet, src),
+                _ => debug!("Could not handle game packet 0x{:02x}", packet.id()),
+            }
+            if self.clients.get(&src.to_string()).is_none_or(|client| client.is_closing()) {
+                return;
+            }
+        }
+    }
+}
+
+impl Server {
+    /// Returns the codecs of the session at `src`. The protocol version of a new session is
+    /// taken from its login.
+    fn session_registry(&mut self, packet: &GamePacket, src: SocketAddr) -> Option<&'static PacketRegistry> {
+        let client = self.clients.get(&src.to_string())?;
+        if let Ok(registry) = client.registry() {
+            return Some(registry);
+        }
+        if packet.id != GamePacketId::Login as u16 {
+            debug!(
+                "{} sent game packet 0x{:02x} before its protocol version, ignoring",
+                src,
+                packet.id
+            );
+            return None;
+        }
+        if packet.payload.len() < 4 {
+            warn!("{} sent a login without a protocol version", src);
+            self.remove_client(&src.to_string());
+            return None;
+        }
+        self.accept_protocol(packet.payload.read_u32(0), src)
+    }
+
+    /// Records `protocol` as the version of the session at `src`. Unsupported clients are told
+    /// whether they or the server are outdated and disconnected.
+    fn accept_protocol(&mut self, protocol: u32, src: SocketAddr) -> Option<&'static PacketRegistry> {
+        if self.bedrock_settings.is_supported(protocol) {
+            let client = self.clients.get_mut(&src.to_string())?;
+            client.set_protocol(protocol);
+            debug!("{} uses protocol {}", src, protocol);
+            return client.registry().ok();
+        }
+        let supported: Vec<u32> = PacketRegistry::protocols()
+            .into_iter()
+            .filter(|protocol| self.bedrock_settings.is_supported(*protocol))
+            .collect();
+        // PlayStatus has the same layout in all versions, so it is sent with the closest one
+        let (status, closest) = match supported.iter().find(|supported| **supported > protocol) {
+            Some(oldest_newer) => (PlayStatusType::LoginFailedClient, Some(*oldest_newer)),
+            None => (PlayStatusType::LoginFailedServer, supported.last().copied()),
+        };
+        info!(
+            "{} uses unsupported protocol {}, supported are {:?}",
+            src,
+            protocol,
+            supported
+        );
+        if let Some(closest) = closest {
+            self.clients.get_mut(&src.to_string())?.set_protocol(closest);
+            self.send_play_status(status, src);
+        }
+        self.disconnect(src);
+        None
+    }
+
+    /// Answers with the compression the session uses from now on
+    fn handle_network_settings_request(&mut self, request: &RequestNetworkSettings, src: SocketAddr) {
+        let protocol = request.protocol() as u32;
+        // nothing is compressed before the network settings have been sent
+        self.clients.get_mut(&src.to_string()).unwrap().set_compression(Compression::uncompressed());
+        let registry = match self.accept_protocol(protocol, src) {
+            Some(registry) => registry,
+            None => return,
+        };
+        let mut compression = self.bedrock_settings.get_compression().clone();
+        compression.set_algorithm_header(registry.algorithm_header());
+        let network_settings = NetworkSettings::create(
+            compression.get_threshold().min(u16::MAX as usize) as u16,
+            compression.get_algorithm(),
+        );
+        self.send_game_packets(vec![Packet::NetworkSettings(network_settings)], src);
+        debug!(
+            "Negotiated {:?} compression from {} bytes with {}",
+            compression.get_algorithm(),
+            compression.get_threshold(),
+            src
+        );
+        if let Some(client) = self.clients.get_mut(&src.to_string()) {
+            client.set_compression(compression);
+        }
+    }
+
+    fn handle_login(&mut self, login: &Login, src: SocketAddr) {
+        if self.clients.get(&src.to_string()).is_some_and(|client| client.identity().is_some()) {
+            warn!("{} sent a second login", src);
+            self.kick(src, "disconnectionScreen.notAuthorized");
+            return;
+        }
+        let protocol = self.clients.get(&src.to_string()).and_then(|client| client.protocol());
+        if protocol != Some(login.protocol() as u32) {
+            warn!(
+                "{} logged in with protocol {} after requesting {:?}",
+                src,
+                login.protocol(),
+                protocol
+            );
+            self.kick(src, "disconnectionScreen.notAuthorized");
+            return;
+        }
+        let identity = match login.verify(&self.bedrock_settings) {
+            Ok(identity) => identity,
+            Err(e) => {
+                warn!("{} failed to log in: {}", src, e);
+                self.kick(src, "disconnectionScreen.notAuthorized");
+                return;
+            }
+        };
+        let players = self
+            .clients
+            .iter()
+            .filter(|(addr, client)| **addr != src.to_string() && client.identity().is_some() && !client.is_closing())
+            .count();
+        if players >= self.bedrock_settings.get_max_players() {
+            info!("{} could not log in, the server is full", identity.display_name);
+            self.send_play_status(PlayStatusType::LoginFailedServerFull, src);
+            self.disconnect(src);
+            return;
+        }
+        info!(
+            "{} logged in from {} (xuid: {}, authenticated: {})",
+            identity.display_name,
+            src,
+            identity.xuid,
+            identity.authenticated
+        );
+        let encryption = self.bedrock_settings.get_encryption();
+        let client = self.clients.get_mut(&src.to_string()).unwrap();
+        let identity_public_key = identity.identity_public_key.clone();
+        client.set_identity(identity);
+        if !encryption {
+            self.send_login_success(src);
+            return;
+        }
+        match client.start_encryption(&identity_public_key) {
+            Ok(handshake) => self.send_encapsulated(&handshake, src),
+            Err(e) => {
+                warn!("Failed to start encryption with {}: {}", src, e);
+                self.kick(src, "disconnectionScreen.internalError");
+            }
+        }
+    }
+
+    /// Logs the client in once it answered the ServerToClientHandshake after its login
+    fn handle_client_handshake(&mut self, src: SocketAddr) {
+        let finished = match self.clients.get_mut(&src.to_string()) {
+            Some(client) => client.identity().is_some() && client.finish_encryption(),
+            None => return,
+        };
+        if !finished {
+            warn!("{} sent ClientToServerHandshake without an encryption handshake", src);
+            self.kick(src, "disconnectionScreen.notAuthorized");
+            return;
+        }
+        debug!("{} finished the encryption handshake", src);
+        self.send_login_success(src);
+    }
+
+    /// Lets the client continue with downloading the packs of the server
+    fn send_login_success(&mut self, dst: SocketAddr) {
+        match self.clients.get_mut(&dst.to_string()) {
+            Some(client) => client.set_logged_in(true),
+            None => return,
+        }
+        self.send_play_status(PlayStatusType::LoginSuccess, dst);
+        let packs = self.resource_packs.packs();
+        let pack_infos = |pack_type: PackType| -> Vec<PackInfo> {
+            packs
+                .iter()
+                .filter(|pack| pack.pack_type() == pack_type)
+                .map(|pack| PackInfo {
+                    uuid: pack.uuid().to_string(),
+                    version: pack.version().to_string(),
+                    size: pack.size(),
+                    content_key: String::new(),
+                    sub_pack_name: String::new(),
+                    content_identity: String::new(),
+                    has_scripts: pack.has_scripts(),
+                    rtx_enabled: false,
+                })
+                .collect()
+        };
+        let resource_packs_info = ResourcePacksInfo::create(
+            self.bedrock_settings.get_force_resource_packs(),
+            pack_infos(PackType::Behaviour),
+            pack_infos(PackType::Resources),
+        );
+        self.send_game_packets(vec![Packet::ResourcePacksInfo(resource_packs_info)], dst);
+    }
+
+    fn handle_resource_pack_response(&mut self, response: &ResourcePackClientResponse, src: SocketAddr) {
+        debug!("{} answered the resource packs with {:?}", src, response.status());
+        match response.status() {
+            ResourcePackResponse::Refused if self.bedrock_settings.get_force_resource_packs() => {
+                self.kick(src, "disconnectionScreen.resourcePack");
+            }
+            ResourcePackResponse::Refused | ResourcePackResponse::HaveAllPacks => self.send_resource_pack_stack(src),
+            ResourcePackResponse::SendPacks => {
+                let chunk_size = self.bedrock_settings.get_resource_pack_chunk_size();
+                let mut packets = Vec::new();
+                for pack_id in response.pack_ids() {
+                    let pack = match self.resource_packs.get(pack_id) {
+                        Some(pack) => pack,
+                        None => {
+                            warn!("{} requested unknown pack {}", src, pack_id);
+                            self.kick(src, "disconnectionScreen.resourcePack");
+                            return;
+                        }
+                    };
+                    packets.push(Packet::ResourcePackDataInfo(ResourcePackDataInfo {
+                        pack_id: pack.id(),
+                        max_chunk_size: chunk_size,
+                        chunk_count: pack.chunk_count(chunk_size),
+                        size: pack.size(),
+                        sha256: pack.sha256().to_vec(),
+                        premium: false,
+                        pack_type: pack.pack_type() as u8,
+                    }));
+                }
+                self.send_game_packets(packets, src);
+            }
+            ResourcePackResponse::Completed => {
+                debug!("{} applied the resource packs", src);
+                self.start_game(src);
+            }
+        }
+    }
+
+    fn handle_resource_pack_chunk_request(&mut self, request: &ResourcePackChunkRequest, src: SocketAddr) {
+        let chunk_size = self.bedrock_settings.get_resource_pack_chunk_size();
+        let chunk = self.resource_packs.get(request.pack_id()).and_then(|pack| {
+            let data = pack.chunk(request.chunk_index(), chunk_size)?;
+            Some(ResourcePackChunkData {
+                pack_id: pack.id(),
+                chunk_index: request.chunk_index(),
+                progress: request.chunk_index() as u64 * chunk_size as u64,
+                data: data.to_vec(),
+            })
+        });
+        match chunk {
+            Some(chunk) => {
+                trace!("Sending chunk {} of pack {} to {}", chunk.chunk_index, chunk.pack_id, src);
+                self.send_game_packets(vec![Packet::ResourcePackChunkData(chunk)], src);
+            }
+            None => {
+                warn!("{} requested invalid chunk {} of pack {}", src, request.chunk_index(), request.pack_id());
+                self.kick(src, "disconnectionScreen.resourcePack");
+            }
+        }
+    }
+
+    fn send_resource_pack_stack(&mut self, dst: SocketAddr) {
+        let stack_entries = |pack_type: PackType| -> Vec<PackStackEntry> {
+            self.resource_packs
+                .packs()
+                .iter()
+                .filter(|pack| pack.pack_type() == pack_type)
+                .map(|pack| PackStackEntry {
+                    uuid: pack.uuid().to_string(),
+                    version: pack.version().to_string(),
+                    sub_pack_name: String::new(),
+                })
+                .collect()
+        };
+        let stack = ResourcePackStack::create(
+            self.bedrock_settings.get_force_resource_packs(),
+            stack_entries(PackType::Behaviour),
+            stack_entries(PackType::Resources),
+        );
+        self.send_game_packets(vec![Packet::ResourcePackStack(stack)], dst);
+    }
+
+    /// Puts the player into the world, chunks are sent once the client asks for its view distance
+    fn start_game(&mut self, dst: SocketAddr) {
+        let client = match self.clients.get_mut(&dst.to_string()) {
+            Some(client) => client,
+            None => return,
+        };
+        if client.entity_id().is_some() {
+            debug!("{} already started the game", dst);
+            return;
+        }
+        let entity_id = self.next_entity_id;
+        self.next_entity_id += 1;
+        client.set_entity_id(entity_id);
+        let protocol = client.protocol().unwrap_or_default();
+
+        let spawn = self.spawn();
+        let world = &self.world_settings;
+        // players spawn in the middle of the block, with their eyes above it
+        let position = (spawn.0 as f32 + 0.5, spawn.1 as f32 + 1.62, spawn.2 as f32 + 0.5);
+        let mut start_game = StartGame::create(entity_id, position);
+        start_game.player_gamemode = world.get_gamemode() as i32;
+        start_game.world_gamemode = world.get_gamemode() as i32;
+        start_game.difficulty = world.get_difficulty() as i32;
+        start_game.seed = world.get_seed();
+        start_game.spawn = spawn;
+        start_game.time = world.get_time();
+        start_game.level_id = world.get_name().to_string();
+        start_game.world_name = world.get_name().to_string();
+        start_game.texture_packs_required = self.bedrock_settings.get_force_resource_packs();
+        if protocol < 554 {
+            start_game.block_palette = self.blocks.palette_for(protocol).legacy_entries();
+        }
+        self.send_game_packets(vec![Packet::StartGame(Box::new(start_game))], dst);
+    }
+
+    /// Answers with the allowed view distance and sends the chunks around the spawn, after which
+    /// the player may spawn
+    fn handle_chunk_radius_request(&mut self, request: &RequestChunkRadius, src: SocketAddr) {
+        let spawned = match self.clients.get(&src.to_string()) {
+            Some(client) if client.entity_id().is_some() => client.is_spawned(),
+            _ => {
+                debug!("{} requested a chunk radius before starting the game", src);
+                return;
+            }
+        };
+        let radius = (request.radius().max(1) as u32).min(self.world_settings.get_view_distance());
+        self.send_game_packets(vec![Packet::ChunkRadiusUpdated(ChunkRadiusUpdated::create(radius as i32))], src);
+        if spawned {
+            return;
+        }
+
+        let spawn = self.spawn();
+        let (center_x, center_z) = (spawn.0 >> 4, spawn.2 >> 4);
+        let radius = radius as i32;
+        let mut chunks = Vec::new();
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                if x * x + z * z <= radius * radius {
+                    chunks.push((x, z));
+                }
+            }
+        }
+        chunks.sort_by_key(|(x, z)| x * x + z * z);
+        self.send_game_packets(
+            vec![Packet::NetworkChunkPublisherUpdate(NetworkChunkPublisherUpdate::create(spawn, radius as u32 * 16))],
+            src,
+        );
+        let chunks: Vec<(i32, i32)> = chunks.into_iter().map(|(x, z)| (center_x + x, center_z + z)).collect();
+        for (x, z) in &chunks {
+            self.chunks().request(*x, *z);
+        }
+        if let Some(client) = self.clients.get_mut(&src.to_string()) {
+            client.set_pending_chunks(chunks);
+        }
+        self.send_pending_chunks(src);
+    }
+
+    /// Sends the chunks which were generated since the last call to the clients waiting for them
+    pub fn send_generated_chunks(&mut self) {
+        let generated = match &mut self.chunk_loader {
+            Some(chunk_loader) => chunk_loader.poll(),
+            None => return,
+        };
+        if generated.is_empty() {
+            return;
+        }
+        let waiting: Vec<SocketAddr> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.pending_chunks().iter().any(|chunk| generated.contains(chunk)))
+            .filter_map(|(addr, _)| addr.parse().ok())
+            .collect();
+        for dst in waiting {
+            self.send_pending_chunks(dst);
+        }
+    }
+
+    /// Sends the pending chunks of `dst` which are generated already. The player may spawn once
+    /// all chunks around the spawn have been sent.
+    fn send_pending_chunks(&mut self, dst: SocketAddr) {
+        let (protocol, spawned, pending) = match self.clients.get_mut(&dst.to_string()) {
+            Some(client) if !client.pending_chunks().is_empty() => (
+                client.protocol().unwrap_or_default(),
+                client.is_spawned(),
+                client.pending_chunks().to_vec(),
+            ),
+            _ => return,
+        };
+        let mut level_chunks = Vec::new();
+        let mut remaining = Vec::new();
+        self.chunks();
+        let chunks = self.chunk_loader.as_ref().unwrap();
+        for (x, z) in pending {
+            match chunks.get(x, z) {
+                Some(chunk) => match chunk.network_payload(protocol, &|state| self.blocks.runtime_id(state, protocol)) {
+                    Ok((sub_chunk_count, payload)) => level_chunks.push(LevelChunk::create(x, z, sub_chunk_count, payload)),
+                    Err(e) => error!("Failed to encode chunk {} {} for {}: {}", x, z, dst, e),
+                },
+                None => remaining.push((x, z)),
+            }
+        }
+        let done = remaining.is_empty();
+        // chunks which were dropped from the cache before they could be sent are generated again
+        let chunks = self.chunk_loader.as_mut().unwrap();
+        for &(x, z) in &remaining {
+            chunks.request(x, z);
+        }
+        if let Some(client) = self.clients.get_mut(&dst.to_string()) {
+            client.set_pending_chunks(remaining);
+        }
+        for level_chunk in level_chunks {
+            self.send_game_packets(vec![Packet::LevelChunk(level_chunk)], dst);
+        }
+        if done && !spawned {
+            self.send_play_status(PlayStatusType::PlayerSpawn, dst);
+        }
+    }
+
+    fn handle_local_player_initialized(&mut self, packet: &SetLocalPlayerAsInitialized, src: SocketAddr) {
+        let client = match self.clients.get_mut(&src.to_string()) {
+            Some(client) => client,
+            None => return,
+        };
+        if client.entity_id() != Some(packet.entity_runtime_id()) {
+            debug!("{} initialized entity {} which is not its player", src, packet.entity_runtime_id());
+            return;
+        }
+        client.set_spawned(true);
+        let name = client.identity().map(|identity| identity.display_name.clone()).unwrap_or_default();
+        info!("{} spawned in {}", name, self.world_settings.get_name());
+    }
+
+    pub fn send_play_status(&mut self, status: PlayStatusType, dst: SocketAddr) {
+        self.send_game_packets(vec![Packet::PlayStatus(PlayStatus::create(status))], dst);
+    }
+}
+
+/// Modern clients start with an uncompressed RequestNetworkSettings, older ones with a compressed login
+fn network_settings_request(packet: &[u8]) -> Option<RequestNetworkSettings> {
+    let batch = Batch::decode(packet.strip_prefix(&[PacketId::GamePacket as u8])?).ok()?;
+    match batch.packets.as_slice() {
+        [packet] if packet.id == GamePacketId::RequestNetworkSettings as u16 => {
+            RequestNetworkSettings::decode(&packet.payload).ok()
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,62 @@
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use base64::Engine;
+use p384::ecdsa::signature::{Signer, Verifier};
+use p384::ecdsa::{Signature, SigningKey, VerifyingKey};
+use p384::pkcs8::DecodePublicKey;
+use serde_json::Value;
+use std::io::{Error, ErrorKind};
+
+/// Creates an ES384 signed JWT
+pub fn encode(header: &Value, claims: &Value, key: &SigningKey) -> String {
+    let message = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(header.to_string()),
+        URL_SAFE_NO_PAD.encode(claims.to_string())
+    );
+    let signature: Signature = key.sign(message.as_bytes());
+    format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature.to_bytes()))
+}
+
+/// Decodes header and claims of a JWT without verifying its signature
+pub fn decode(token: &str) -> Result<(Value, Value), Error> {
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        return Err(Error::new(ErrorKind::InvalidData, "jwt does not consist of three parts"));
+    }
+    Ok((decode_part(parts[0])?, decode_part(parts[1])?))
+}
+
+/// Verifies the ES384 signature of a JWT with a base64 encoded DER public key and returns its claims
+pub fn verify(token: &str, public_key: &str) -> Result<Value, Error> {
+    let (header, claims) = decode(token)?;
+    if header["alg"] != "ES384" {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported jwt algorithm {}", header["alg"]),
+        ));
+    }
+    let key = parse_public_key(public_key)?;
+    let split = token.rfind('.').unwrap();
+    let signature = URL_SAFE_NO_PAD
+        .decode(&token[split + 1..])
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let signature = Signature::from_slice(&signature).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    key.verify(&token.as_bytes()[..split], &signature)
+        .map_err(|_| Error::new(ErrorKind::InvalidData, "jwt has an invalid signature"))?;
+    Ok(claims)
+}
+
+pub fn parse_public_key(public_key: &str) -> Result<VerifyingKey, Error> {
+    let der = STANDARD
+        .decode(public_key)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    VerifyingKey::from_public_key_der(&der)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, format!("invalid public key: {}", e)))
+}
+
+fn decode_part(part: &str) -> Result<Value, Error> {
+    let json = URL_SAFE_NO_PAD
+        .decode(part.trim_end_matches('='))
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    serde_json::from_slice(&json).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,327 @@
+use crate::bedrock::jwt;
+use crate::bedrock::BedrockSettings;
+use crate::utils::buffer::{PacketBufferRead, PacketBufferWrite};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{json, Value};
+use std::io::{Error, ErrorKind};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Key Mojang signs the authentication chains of Xbox Live players with
+pub const MOJANG_ROOT_KEY: &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAECRXueJeTDqNRRgJi/vlRufByu/2G0i2Ebt6YMar5QX/R0DIIyrJMcUpruK4QveTfJSTp3Shlq4Gk34cD/4GUWwkv0DVuzeuB+tXija7HBxii03NHDbPAD0AKnLr2wdAp";
+/// Key Mojang used before 1.20.0
+pub const LEGACY_MOJANG_ROOT_KEY: &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAE8ELkixyLcwlZryUQcu1TvPOmI2B7vX83ndnWRUaXm74wFfa5f/lwQNTfrLVHa2PmenpGI6JhIMUJaWZrjmMj90NoKNFSNBuKdm8rYiXsfaz3K36x/1U26HpG0ZxK/V1V";
+
+/// Seconds the clocks of client and server may differ when checking expiry
+const CLOCK_LEEWAY: i64 = 60;
+
+pub struct Login {
+    protocol: i32,
+    chain: Vec<String>,
+    client_data: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Skin {
+    pub id: String,
+    pub image_width: u32,
+    pub image_height: u32,
+    /// RGBA pixels
+    pub data: Vec<u8>,
+}
+
+/// Who the client is, taken from its verified login
+#[derive(Clone, Debug, Default)]
+pub struct Identity {
+    pub display_name: String,
+    /// empty if the chain was not signed by a trusted key
+    pub xuid: String,
+    pub identity: String,
+    /// base64 encoded key the client data was signed with, used for the encryption handshake
+    pub identity_public_key: String,
+    /// whether the chain was signed by a trusted key
+    pub authenticated: bool,
+    pub device_os: i64,
+    pub device_model: String,
+    pub language: String,
+    pub skin: Skin,
+}
+
+impl Login {
+    pub fn create(protocol: i32, chain: Vec<String>, client_data: String) -> Login {
+        Login {
+            protocol,
+            chain,
+            client_data,
+        }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<Login, Error> {
+        if binary.len() < 4 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "login is too short"));
+        }
+        let protocol = binary.read_i32(0);
+        let (request, _) = binary.read_var_bytes(4)?;
+        let (chain, offset) = read_le_string(request, 0)?;
+        let (client_data, _) = read_le_string(request, offset)?;
+
+        let chain: Value = serde_json::from_str(&chain).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        // newer clients wrap the chain into a certificate
+        let chain = match chain["Certificate"].as_str() {
+            Some(certificate) => serde_json::from_str(certificate).map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+            None => chain,
+        };
+        let chain = chain["chain"]
+            .as_array()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "login has no chain"))?
+            .iter()
+            .map(|token| token.as_str().map(|token| token.to_string()))
+            .collect::<Option<Vec<String>>>()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "login chain contains a non-string"))?;
+
+        Ok(Login {
+            protocol,
+            chain,
+            client_data,
+        })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        let chain = json!({ "chain": self.chain }).to_string();
+        let mut request = Vec::new();
+        request.push_u32_le(chain.len() as u32);
+        request.push_slice(chain.as_bytes());
+        request.push_u32_le(self.client_data.len() as u32);
+        request.push_slice(self.client_data.as_bytes());
+        binary.push_i32(self.protocol);
+        binary.push_var_bytes(&request);
+
+        binary
+    }
+
+    pub fn protocol(&self) -> i32 {
+        self.protocol
+    }
+
+    pub fn chain(&self) -> &[String] {
+        &self.chain
+    }
+
+    pub fn client_data(&self) -> &str {
+        &self.client_data
+    }
+
+    /// Verifies every link of the chain and the client data and returns the identity of the client.
+    /// Chains which were not signed by one of the trusted keys are only accepted in offline mode.
+    /// Once a link is signed by a trusted key, extraData is only taken from it and later links.
+    pub fn verify(&self, settings: &BedrockSettings) -> Result<Identity, Error> {
+        if self.chain.is_empty() || self.chain.len() > 3 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("login chain has {} links", self.chain.len()),
+            ));
+        }
+        let mut authenticated = false;
+        let mut current_key: Option<String> = None;
+        let mut extra_data = Value::Null;
+        for token in &self.chain {
+            let (header, _) = jwt::decode(token)?;
+            let signer = header["x5u"]
+                .as_str()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "login chain link has no x5u"))?;
+            if let Some(current_key) = &current_key {
+                if current_key != signer {
+                    return Err(Error::new(ErrorKind::InvalidData, "login chain is broken"));
+                }
+            }
+            let claims = jwt::verify(token, signer)?;
+            check_expiry(&claims, !settings.get_offline_mode())?;
+            if !authenticated && settings.get_trusted_keys().iter().any(|key| key == signer) {
+                // anyone can sign the links before the trusted one
+                authenticated = true;
+                extra_data = Value::Null;
+            }
+            current_key = Some(
+                claims["identityPublicKey"]
+                    .as_str()
+                    .ok_or_else(|| Error::new(ErrorKind::InvalidData, "login chain link has no identityPublicKey"))?
+                    .to_string(),
+            );
+            if claims["extraData"].is_object() {
+                extra_data = claims["extraData"].clone();
+            }
+        }
+        if !authenticated && !settings.get_offline_mode() {
+            return Err(Error::new(ErrorKind::PermissionDenied, "login chain is not signed by a trusted key"));
+        }
+        let identity_public_key = current_key.unwrap();
+        let client_data = jwt::verify(&self.client_data, &identity_public_key)?;
+
+        let display_name = extra_data["displayName"]
+            .as_str()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "login has no display name"))?;
+        Ok(Identity {
+            display_name: display_name.to_string(),
+            xuid: if authenticated { string(&extra_data["XUID"]) } else { String::new() },
+            identity: string(&extra_data["identity"]),
+            identity_public_key,
+            authenticated,
+            device_os: client_data["DeviceOS"].as_i64().unwrap_or_default(),
+            device_model: string(&client_data["DeviceModel"]),
+            language: string(&client_data["LanguageCode"]),
+            skin: Skin {
+                id: string(&client_data["SkinId"]),
+                image_width: client_data["SkinImageWidth"].as_u64().unwrap_or_default() as u32,
+                image_height: client_data["SkinImageHeight"].as_u64().unwrap_or_default() as u32,
+                data: STANDARD
+                    .decode(string(&client_data["SkinData"]))
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+            },
+        })
+    }
+}
+
+/// Reads a string prefixed with its length as little endian u32, returns it and the offset after it
+fn read_le_string(binary: &[u8], start: usize) -> Result<(String, usize), Error> {
+    if binary.len() < start + 4 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "login string is truncated"));
+    }
+    let len = binary.read_u32_le(start) as usize;
+    let string = binary
+        .get(start + 4..start + 4 + len)
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "login string is truncated"))?;
+    let string = String::from_utf8(string.to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok((string, start + 4 + len))
+}
+
+/// Checks nbf and exp of a link, a missing exp is only accepted if `require_expiry` is false
+fn check_expiry(claims: &Value, require_expiry: bool) -> Result<(), Error> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    if let Some(not_before) = claims["nbf"].as_i64() {
+        if not_before > now + CLOCK_LEEWAY {
+            return Err(Error::new(ErrorKind::InvalidData, "login chain link is not valid yet"));
+        }
+    }
+    match claims["exp"].as_i64() {
+        Some(expires) if expires < now - CLOCK_LEEWAY => {
+            Err(Error::new(ErrorKind::InvalidData, "login chain link has expired"))
+        }
+        None if require_expiry => Err(Error::new(ErrorKind::InvalidData, "login chain link has no exp")),
+        _ => Ok(()),
+    }
+}
+
+fn string(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use p384::ecdsa::SigningKey;
+    use p384::pkcs8::EncodePublicKey;
+    use rand::rngs::OsRng;
+
+    fn key() -> SigningKey {
+        SigningKey::random(&mut OsRng)
+    }
+
+    fn public_key(key: &SigningKey) -> String {
+        STANDARD.encode(key.verifying_key().to_public_key_der().unwrap().as_bytes())
+    }
+
+    fn now() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64
+    }
+
+    /// Link signed by `signer` which hands over to `next`
+    fn link(signer: &SigningKey, next: &SigningKey, mut claims: Value) -> String {
+        claims["identityPublicKey"] = json!(public_key(next));
+        if claims.get("exp").is_none() {
+            claims["exp"] = json!(now() + 3600);
+        }
+        jwt::encode(&json!({ "alg": "ES384", "x5u": public_key(signer) }), &claims, signer)
+    }
+
+    fn client_data(key: &SigningKey) -> String {
+        jwt::encode(&json!({ "alg": "ES384", "x5u": public_key(key) }), &json!({ "SkinData": "" }), key)
+    }
+
+    fn extra_data(name: &str) -> Value {
+        json!({ "extraData": { "displayName": name, "XUID": "1", "identity": "id" } })
+    }
+
+    fn settings(trusted: &SigningKey, offline_mode: bool) -> BedrockSettings {
+        let mut settings = BedrockSettings::default();
+        settings.set_trusted_keys(vec![public_key(trusted)]);
+        settings.set_offline_mode(offline_mode);
+        settings
+    }
+
+    #[test]
+    fn trusted_chain() {
+        let (root, intermediate, client) = (key(), key(), key());
+        let chain = vec![link(&root, &intermediate, json!({})), link(&intermediate, &client, extra_data("Steve"))];
+        let login = Login::create(554, chain, client_data(&client));
+        let identity = login.verify(&settings(&root, false)).unwrap();
+        assert!(identity.authenticated);
+        assert_eq!(identity.display_name, "Steve");
+        assert_eq!(identity.xuid, "1");
+        assert_eq!(identity.identity_public_key, public_key(&client));
+    }
+
+    #[test]
+    fn extra_data_before_trusted_link_is_ignored() {
+        let (root, attacker, client) = (key(), key(), key());
+        let chain = vec![link(&attacker, &root, extra_data("Notch")), link(&root, &client, json!({}))];
+        let login = Login::create(554, chain, client_data(&client));
+        assert!(login.verify(&settings(&root, false)).is_err());
+    }
+
+    #[test]
+    fn untrusted_chain_needs_offline_mode() {
+        let (root, client) = (key(), key());
+        let login = Login::create(554, vec![link(&client, &client, extra_data("Steve"))], client_data(&client));
+        assert_eq!(login.verify(&settings(&root, false)).unwrap_err().kind(), ErrorKind::PermissionDenied);
+        let identity = login.verify(&settings(&root, true)).unwrap();
+        assert!(!identity.authenticated);
+        assert!(identity.xuid.is_empty());
+    }
+
+    #[test]
+    fn broken_chain() {
+        let (root, other, client) = (key(), key(), key());
+        let chain = vec![link(&root, &client, json!({})), link(&other, &client, extra_data("Steve"))];
+        let login = Login::create(554, chain, client_data(&client));
+        assert!(login.verify(&settings(&root, false)).is_err());
+    }
+
+    #[test]
+    fn expiry() {
+        let (root, client) = (key(), key());
+        let mut claims = extra_data("Steve");
+        claims["exp"] = json!(now() - 3600);
+        let login = Login::create(554, vec![link(&root, &client, claims)], client_data(&client));
+        assert!(login.verify(&settings(&root, false)).is_err());
+
+        let mut claims = extra_data("Steve");
+        claims["exp"] = Value::Null;
+        let login = Login::create(554, vec![link(&root, &client, claims)], client_data(&client));
+        assert!(login.verify(&settings(&root, false)).is_err());
+        assert!(login.verify(&settings(&root, true)).is_ok());
+    }
+
+    #[test]
+    fn round_trip() {
+        let client = key();
+        let login = Login::create(554, vec![link(&client, &client, extra_data("Steve"))], client_data(&client));
+        let decoded = Login::decode(&login.encode(Vec::new())).unwrap();
+        assert_eq!(decoded.protocol(), 554);
+        assert_eq!(decoded.chain(), login.chain());
+        assert_eq!(decoded.client_data(), login.client_data());
+        assert!(Login::decode(&[0, 0]).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+pub mod batch;
+pub mod compression;
+pub mod encryption;
+pub mod handler;
+pub mod jwt;
+pub mod login;
+pub mod packets;
+pub mod registry;
+pub mod resource_packs;
+
+use crate::bedrock::compression::{Compression, CompressionAlgorithm};
+use crate::bedrock::login::{LEGACY_MOJANG_ROOT_KEY, MOJANG_ROOT_KEY};
+use crate::bedrock::registry::PacketRegistry;
+use crate::bedrock::resource_packs::DEFAULT_CHUNK_SIZE;
+
+pub struct BedrockSettings {
+    protocols: Vec<u32>,
+    motd: String,
+    trusted_keys: Vec<String>,
+    offline_mode: bool,
+    max_players: usize,
+    encryption: bool,
+    compression: Compression,
+    resource_pack_directory: Option<String>,
+    resource_pack_chunk_size: u32,
+    force_resource_packs: bool,
+    block_state_directory: Option<String>,
+}
+
+impl BedrockSettings {
+    pub fn new() -> BedrockSettings {
+        BedrockSettings {
+            protocols: PacketRegistry::protocols(),
+            motd: "Limonite".to_string(),
+            trusted_keys: vec![MOJANG_ROOT_KEY.to_string(), LEGACY_MOJANG_ROOT_KEY.to_string()],
+            offline_mode: false,
+            max_players: 20,
+            encryption: true,
+            compression: Compression::new(CompressionAlgorithm::Zlib),
+            resource_pack_directory: None,
+            resource_pack_chunk_size: DEFAULT_CHUNK_SIZE,
+            force_resource_packs: false,
+            block_state_directory: None,
+        }
+    }
+
+    /// Sets the game protocol versions clients may connect with, versions without a packet
+    /// registry are ignored
+    pub fn set_protocols(&mut self, protocols: Vec<u32>) {
+        self.protocols = protocols;
+    }
+
+    pub fn get_protocols(&self) -> &[u32] {
+        &self.protocols
+    }
+
+    /// Whether clients of `protocol` are accepted
+    pub fn is_supported(&self, protocol: u32) -> bool {
+        self.protocols.contains(&protocol) && PacketRegistry::for_protocol(protocol).is_some()
+    }
+
+    /// Newest accepted protocol version, which is advertised in the server list
+    pub fn get_newest_protocol(&self) -> Option<u32> {
+        self.protocols.iter().copied().filter(|protocol| self.is_supported(*protocol)).max()
+    }
+
+    /// Sets the first line shown in the server list, the world name is shown below it
+    pub fn set_motd(&mut self, motd: String) {
+        self.motd = motd;
+    }
+
+    pub fn get_motd(&self) -> &str {
+        &self.motd
+    }
+
+    /// Sets the base64 encoded public keys login chains have to be signed with
+    pub fn set_trusted_keys(&mut self, trusted_keys: Vec<String>) {
+        self.trusted_keys = trusted_keys;
+    }
+
+    pub fn get_trusted_keys(&self) -> &[String] {
+        &self.trusted_keys
+    }
+
+    /// Sets whether self-signed login chains are accepted
+    pub fn set_offline_mode(&mut self, offline_mode: bool) {
+        self.offline_mode = offline_mode;
+    }
+
+    pub fn get_offline_mode(&self) -> bool {
+        self.offline_mode
+    }
+
+    /// Sets the amount of players allowed to be logged in at the same time. Keep it below the
+    /// RakNet connection limit, so clients joining a full server can still be told why.
+    pub fn set_max_players(&mut self, max_players: usize) {
+        self.max_players = max_players;
+    }
+
+    pub fn get_max_players(&self) -> usize {
+        self.max_players
+    }
+
+    /// Sets whether sessions are encrypted after login
+    pub fn set_encryption(&mut self, encryption: bool) {
+        self.encryption = encryption;
+    }
+
+    pub fn get_encryption(&self) -> bool {
+        self.encryption
+    }
+
+    /// Sets the compression offered to clients which negotiate their network settings
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub fn get_compression(&self) -> &Compression {
+        &self.compression
+    }
+
+    /// Sets the directory .mcpack and .zip packs are loaded from when the server starts
+    pub fn set_resource_pack_directory(&mut self, resource_pack_directory: Option<String>) {
+        self.resource_pack_directory = resource_pack_directory;
+    }
+
+    pub fn get_resource_pack_directory(&self) -> Option<&str> {
+        self.resource_pack_directory.as_deref()
+    }
+
+    /// Sets the size of the chunks packs are downloaded in
+    pub fn set_resource_pack_chunk_size(&mut self, resource_pack_chunk_size: u32) {
+        self.resource_pack_chunk_size = resource_pack_chunk_size.max(1);
+    }
+
+    pub fn get_resource_pack_chunk_size(&self) -> u32 {
+        self.resource_pack_chunk_size
+    }
+
+    /// Sets whether clients have to accept the packs to join
+    pub fn set_force_resource_packs(&mut self, force_resource_packs: bool) {
+        self.force_resource_packs = force_resource_packs;
+    }
+
+    pub fn get_force_resource_packs(&self) -> bool {
+        self.force_resource_packs
+    }
+
+    /// Sets the directory the block states of each protocol version are loaded from when the
+    /// server starts, see `BlockRegistry::load`
+    pub fn set_block_state_directory(&mut self, block_state_directory: Option<String>) {
+        self.block_state_directory = block_state_directory;
+    }
+
+    pub fn get_block_state_directory(&self) -> Option<&str> {
+        self.block_state_directory.as_deref()
+    }
+}
+
+impl Default for BedrockSettings {
+    fn default() -> Self {
+        BedrockSettings::new()
+    }
+}
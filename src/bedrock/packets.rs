@@ -0,0 +1,1304 @@
+use crate::bedrock::batch::GamePacket;
+use crate::bedrock::compression::CompressionAlgorithm;
+use crate::bedrock::login::Login;
+use crate::utils::buffer::{PacketBufferRead, PacketBufferWrite};
+use crate::utils::nbt::{Compound, Encoding, Tag};
+use std::io::{Error, ErrorKind};
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(u16)]
+pub enum GamePacketId {
+    Login = 0x01,
+    PlayStatus = 0x02,
+    Disconnect = 0x05,
+    ResourcePacksInfo = 0x06,
+    ResourcePackStack = 0x07,
+    ResourcePackClientResponse = 0x08,
+    StartGame = 0x0b,
+    LevelChunk = 0x3a,
+    RequestChunkRadius = 0x45,
+    ChunkRadiusUpdated = 0x46,
+    ResourcePackDataInfo = 0x52,
+    ResourcePackChunkData = 0x53,
+    ResourcePackChunkRequest = 0x54,
+    SetLocalPlayerAsInitialized = 0x71,
+    NetworkChunkPublisherUpdate = 0x79,
+    ServerToClientHandshake = 0x03,
+    ClientToServerHandshake = 0x04,
+    NetworkSettings = 0x8f,
+    RequestNetworkSettings = 0xc1,
+}
+
+/// A decoded game packet
+pub enum Packet {
+    Login(Login),
+    PlayStatus(PlayStatus),
+    ServerToClientHandshake(ServerToClientHandshake),
+    ClientToServerHandshake(ClientToServerHandshake),
+    Disconnect(Disconnect),
+    ResourcePacksInfo(ResourcePacksInfo),
+    ResourcePackStack(ResourcePackStack),
+    ResourcePackClientResponse(ResourcePackClientResponse),
+    ResourcePackDataInfo(ResourcePackDataInfo),
+    ResourcePackChunkData(ResourcePackChunkData),
+    ResourcePackChunkRequest(ResourcePackChunkRequest),
+    StartGame(Box<StartGame>),
+    LevelChunk(LevelChunk),
+    RequestChunkRadius(RequestChunkRadius),
+    ChunkRadiusUpdated(ChunkRadiusUpdated),
+    SetLocalPlayerAsInitialized(SetLocalPlayerAsInitialized),
+    NetworkChunkPublisherUpdate(NetworkChunkPublisherUpdate),
+    NetworkSettings(NetworkSettings),
+    RequestNetworkSettings(RequestNetworkSettings),
+    /// packet without a decoder in the registry of the protocol version
+    Raw(GamePacket),
+}
+
+/// Result of a login attempt, also tells the client when it may spawn
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(i32)]
+pub enum PlayStatusType {
+    LoginSuccess = 0,
+    /// the client is older than the server
+    LoginFailedClient = 1,
+    /// the server is older than the client
+    LoginFailedServer = 2,
+    PlayerSpawn = 3,
+    LoginFailedInvalidTenant = 4,
+    LoginFailedVanillaEdu = 5,
+    LoginFailedEduVanilla = 6,
+    LoginFailedServerFull = 7,
+}
+
+pub struct PlayStatus {
+    status: PlayStatusType,
+}
+
+pub struct ServerToClientHandshake {
+    token: String,
+}
+
+pub struct ClientToServerHandshake {}
+
+/// Closes the session, showing `message` on the disconnect screen unless it is hidden
+pub struct Disconnect {
+    hide_disconnect_screen: bool,
+    message: String,
+}
+
+/// A pack offered in ResourcePacksInfo
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackInfo {
+    pub uuid: String,
+    pub version: String,
+    pub size: u64,
+    pub content_key: String,
+    pub sub_pack_name: String,
+    pub content_identity: String,
+    pub has_scripts: bool,
+    /// only sent for resource packs
+    pub rtx_enabled: bool,
+}
+
+/// Lists the packs of the server after login
+pub struct ResourcePacksInfo {
+    pub must_accept: bool,
+    pub has_scripts: bool,
+    pub force_server_packs: bool,
+    pub behaviour_packs: Vec<PackInfo>,
+    pub resource_packs: Vec<PackInfo>,
+}
+
+/// A pack applied in ResourcePackStack
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackStackEntry {
+    pub uuid: String,
+    pub version: String,
+    pub sub_pack_name: String,
+}
+
+/// Order in which the client applies the packs
+pub struct ResourcePackStack {
+    pub must_accept: bool,
+    pub behaviour_packs: Vec<PackStackEntry>,
+    pub resource_packs: Vec<PackStackEntry>,
+    pub base_game_version: String,
+    /// experimental toggles by name, before 1.16.100 only whether any is enabled is sent
+    pub experiments: Vec<(String, bool)>,
+    pub experiments_previously_toggled: bool,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum ResourcePackResponse {
+    Refused = 1,
+    SendPacks = 2,
+    HaveAllPacks = 3,
+    Completed = 4,
+}
+
+/// Answer of the client to ResourcePacksInfo and ResourcePackStack
+pub struct ResourcePackClientResponse {
+    status: ResourcePackResponse,
+    pack_ids: Vec<String>,
+}
+
+/// Announces the download of a pack
+pub struct ResourcePackDataInfo {
+    pub pack_id: String,
+    pub max_chunk_size: u32,
+    pub chunk_count: u32,
+    pub size: u64,
+    pub sha256: Vec<u8>,
+    pub premium: bool,
+    pub pack_type: u8,
+}
+
+pub struct ResourcePackChunkData {
+    pub pack_id: String,
+    pub chunk_index: u32,
+    /// offset of the chunk in the pack
+    pub progress: u64,
+    pub data: Vec<u8>,
+}
+
+pub struct ResourcePackChunkRequest {
+    pack_id: String,
+    chunk_index: u32,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum GameRuleValue {
+    Bool(bool),
+    Int(u32),
+    Float(f32),
+}
+
+/// An item the client should know, with the runtime id it is sent with
+#[derive(Clone, Debug, PartialEq)]
+pub struct ItemEntry {
+    pub name: String,
+    pub runtime_id: i16,
+    pub component_based: bool,
+}
+
+/// Puts the player into the world, after this the client waits for chunks
+pub struct StartGame {
+    pub entity_unique_id: i64,
+    pub entity_runtime_id: u64,
+    pub player_gamemode: i32,
+    pub position: (f32, f32, f32),
+    pub pitch: f32,
+    pub yaw: f32,
+    pub seed: i64,
+    pub dimension: i32,
+    /// 0 is the old limited world, 1 infinite, 2 flat
+    pub generator: i32,
+    pub world_gamemode: i32,
+    pub difficulty: i32,
+    pub spawn: (i32, i32, i32),
+    pub time: i32,
+    pub rain_level: f32,
+    pub lightning_level: f32,
+    pub commands_enabled: bool,
+    pub texture_packs_required: bool,
+    pub game_rules: Vec<(String, GameRuleValue)>,
+    pub default_permission: i32,
+    pub chunk_tick_radius: i32,
+    pub level_id: String,
+    pub world_name: String,
+    pub current_tick: i64,
+    pub enchantment_seed: i32,
+    /// all block states, only sent before 1.16.100
+    pub block_palette: Vec<Compound>,
+    /// names and properties of custom blocks, sent since 1.16.100
+    pub custom_blocks: Vec<(String, Compound)>,
+    pub items: Vec<ItemEntry>,
+    pub multiplayer_correlation_id: String,
+    pub server_authoritative_inventory: bool,
+    /// name of the server software, sent since 1.17.0
+    pub engine: String,
+}
+
+/// Terrain of a chunk column
+pub struct LevelChunk {
+    pub x: i32,
+    pub z: i32,
+    pub sub_chunk_count: u32,
+    /// sub chunks, biomes, border blocks and block entities
+    pub payload: Vec<u8>,
+}
+
+/// View distance the client would like to have
+pub struct RequestChunkRadius {
+    radius: i32,
+}
+
+/// View distance the server allows
+pub struct ChunkRadiusUpdated {
+    radius: i32,
+}
+
+/// Sent by the client once it has spawned
+pub struct SetLocalPlayerAsInitialized {
+    entity_runtime_id: u64,
+}
+
+/// Tells the client around which block it receives chunks, in which radius in blocks
+pub struct NetworkChunkPublisherUpdate {
+    pub position: (i32, i32, i32),
+    pub radius: u32,
+}
+
+/// Compression the session uses after it has been negotiated
+pub struct NetworkSettings {
+    compression_threshold: u16,
+    compression_algorithm: CompressionAlgorithm,
+    client_throttle: bool,
+    client_throttle_threshold: u8,
+    client_throttle_scalar: f32,
+}
+
+/// First packet of clients since 1.19.30, sent uncompressed before their login
+pub struct RequestNetworkSettings {
+    protocol: i32,
+}
+
+impl Packet {
+    pub fn id(&self) -> u16 {
+        match self {
+            Packet::Login(_) => GamePacketId::Login as u16,
+            Packet::PlayStatus(_) => GamePacketId::PlayStatus as u16,
+            Packet::ServerToClientHandshake(_) => GamePacketId::ServerToClientHandshake as u16,
+            Packet::ClientToServerHandshake(_) => GamePacketId::ClientToServerHandshake as u16,
+            Packet::Disconnect(_) => GamePacketId::Disconnect as u16,
+            Packet::ResourcePacksInfo(_) => GamePacketId::ResourcePacksInfo as u16,
+            Packet::ResourcePackStack(_) => GamePacketId::ResourcePackStack as u16,
+            Packet::ResourcePackClientResponse(_) => GamePacketId::ResourcePackClientResponse as u16,
+            Packet::ResourcePackDataInfo(_) => GamePacketId::ResourcePackDataInfo as u16,
+            Packet::ResourcePackChunkData(_) => GamePacketId::ResourcePackChunkData as u16,
+            Packet::ResourcePackChunkRequest(_) => GamePacketId::ResourcePackChunkRequest as u16,
+            Packet::StartGame(_) => GamePacketId::StartGame as u16,
+            Packet::LevelChunk(_) => GamePacketId::LevelChunk as u16,
+            Packet::RequestChunkRadius(_) => GamePacketId::RequestChunkRadius as u16,
+            Packet::ChunkRadiusUpdated(_) => GamePacketId::ChunkRadiusUpdated as u16,
+            Packet::SetLocalPlayerAsInitialized(_) => GamePacketId::SetLocalPlayerAsInitialized as u16,
+            Packet::NetworkChunkPublisherUpdate(_) => GamePacketId::NetworkChunkPublisherUpdate as u16,
+            Packet::NetworkSettings(_) => GamePacketId::NetworkSettings as u16,
+            Packet::RequestNetworkSettings(_) => GamePacketId::RequestNetworkSettings as u16,
+            Packet::Raw(packet) => packet.id,
+        }
+    }
+
+    pub fn encode(&self) -> GamePacket {
+        let payload = match self {
+            Packet::Login(packet) => packet.encode(Vec::new()),
+            Packet::PlayStatus(packet) => packet.encode(Vec::new()),
+            Packet::ServerToClientHandshake(packet) => packet.encode(Vec::new()),
+            Packet::ClientToServerHandshake(packet) => packet.encode(Vec::new()),
+            Packet::Disconnect(packet) => packet.encode(Vec::new()),
+            Packet::ResourcePacksInfo(packet) => packet.encode(Vec::new()),
+            Packet::ResourcePackStack(packet) => packet.encode(Vec::new()),
+            Packet::ResourcePackClientResponse(packet) => packet.encode(Vec::new()),
+            Packet::ResourcePackDataInfo(packet) => packet.encode(Vec::new()),
+            Packet::ResourcePackChunkData(packet) => packet.encode(Vec::new()),
+            Packet::ResourcePackChunkRequest(packet) => packet.encode(Vec::new()),
+            Packet::StartGame(packet) => packet.encode(Vec::new()),
+            Packet::LevelChunk(packet) => packet.encode(Vec::new()),
+            Packet::RequestChunkRadius(packet) => packet.encode(Vec::new()),
+            Packet::ChunkRadiusUpdated(packet) => packet.encode(Vec::new()),
+            Packet::SetLocalPlayerAsInitialized(packet) => packet.encode(Vec::new()),
+            Packet::NetworkChunkPublisherUpdate(packet) => packet.encode(Vec::new()),
+            Packet::NetworkSettings(packet) => packet.encode(Vec::new()),
+            Packet::RequestNetworkSettings(packet) => packet.encode(Vec::new()),
+            Packet::Raw(packet) => return packet.clone(),
+        };
+        GamePacket::new(self.id(), payload)
+    }
+}
+
+impl PlayStatusType {
+    pub fn from_i32(status: i32) -> Option<PlayStatusType> {
+        match status {
+            0 => Some(PlayStatusType::LoginSuccess),
+            1 => Some(PlayStatusType::LoginFailedClient),
+            2 => Some(PlayStatusType::LoginFailedServer),
+            3 => Some(PlayStatusType::PlayerSpawn),
+            4 => Some(PlayStatusType::LoginFailedInvalidTenant),
+            5 => Some(PlayStatusType::LoginFailedVanillaEdu),
+            6 => Some(PlayStatusType::LoginFailedEduVanilla),
+            7 => Some(PlayStatusType::LoginFailedServerFull),
+            _ => None,
+        }
+    }
+}
+
+impl PlayStatus {
+    pub fn create(status: PlayStatusType) -> PlayStatus {
+        PlayStatus { status }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<PlayStatus, Error> {
+        if binary.len() < 4 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "play status is too short"));
+        }
+        let status = binary.read_i32(0);
+        match PlayStatusType::from_i32(status) {
+            Some(status) => Ok(PlayStatus { status }),
+            None => Err(Error::new(ErrorKind::InvalidData, format!("unknown play status {}", status))),
+        }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_i32(self.status as i32);
+
+        binary
+    }
+
+    pub fn status(&self) -> PlayStatusType {
+        self.status
+    }
+}
+
+impl ServerToClientHandshake {
+    /// `token` is the JWT carrying the server public key and salt
+    pub fn create(token: String) -> ServerToClientHandshake {
+        ServerToClientHandshake { token }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<ServerToClientHandshake, Error> {
+        let (token, _) = binary.read_var_string(0)?;
+        Ok(ServerToClientHandshake { token })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_var_string(&self.token);
+
+        binary
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+}
+
+impl ClientToServerHandshake {
+    pub fn create() -> ClientToServerHandshake {
+        ClientToServerHandshake {}
+    }
+
+    pub fn decode(_binary: &[u8]) -> Result<ClientToServerHandshake, Error> {
+        Ok(ClientToServerHandshake {})
+    }
+
+    pub fn encode(&self, binary: Vec<u8>) -> Vec<u8> {
+        binary
+    }
+}
+
+impl Disconnect {
+    pub fn create(message: String) -> Disconnect {
+        Disconnect {
+            hide_disconnect_screen: false,
+            message,
+        }
+    }
+
+    /// Disconnects without showing the disconnect screen
+    pub fn hidden() -> Disconnect {
+        Disconnect {
+            hide_disconnect_screen: true,
+            message: String::new(),
+        }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<Disconnect, Error> {
+        if binary.is_empty() {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "disconnect is empty"));
+        }
+        let hide_disconnect_screen = binary.read_bool(0);
+        let message = if hide_disconnect_screen {
+            String::new()
+        } else {
+            binary.read_var_string(1)?.0
+        };
+        Ok(Disconnect {
+            hide_disconnect_screen,
+            message,
+        })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_bool(self.hide_disconnect_screen);
+        if !self.hide_disconnect_screen {
+            binary.push_var_string(&self.message);
+        }
+
+        binary
+    }
+
+    pub fn hide_disconnect_screen(&self) -> bool {
+        self.hide_disconnect_screen
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl PackInfo {
+    fn decode(binary: &[u8], mut offset: usize, resource_pack: bool) -> Result<(PackInfo, usize), Error> {
+        let start = offset;
+        let (uuid, len) = binary.read_var_string(offset)?;
+        offset += len;
+        let (version, len) = binary.read_var_string(offset)?;
+        offset += len;
+        ensure_len(binary, offset, 8)?;
+        let size = binary.read_u64_le(offset);
+        offset += 8;
+        let (content_key, len) = binary.read_var_string(offset)?;
+        offset += len;
+        let (sub_pack_name, len) = binary.read_var_string(offset)?;
+        offset += len;
+        let (content_identity, len) = binary.read_var_string(offset)?;
+        offset += len;
+        ensure_len(binary, offset, if resource_pack { 2 } else { 1 })?;
+        let has_scripts = binary.read_bool(offset);
+        offset += 1;
+        let rtx_enabled = resource_pack && binary.read_bool(offset);
+        if resource_pack {
+            offset += 1;
+        }
+        let pack_info = PackInfo {
+            uuid,
+            version,
+            size,
+            content_key,
+            sub_pack_name,
+            content_identity,
+            has_scripts,
+            rtx_enabled,
+        };
+        Ok((pack_info, offset - start))
+    }
+
+    fn encode(&self, mut binary: Vec<u8>, resource_pack: bool) -> Vec<u8> {
+        binary.push_var_string(&self.uuid);
+        binary.push_var_string(&self.version);
+        binary.push_u64_le(self.size);
+        binary.push_var_string(&self.content_key);
+        binary.push_var_string(&self.sub_pack_name);
+        binary.push_var_string(&self.content_identity);
+        binary.push_bool(self.has_scripts);
+        if resource_pack {
+            binary.push_bool(self.rtx_enabled);
+        }
+
+        binary
+    }
+}
+
+impl ResourcePacksInfo {
+    pub fn create(must_accept: bool, behaviour_packs: Vec<PackInfo>, resource_packs: Vec<PackInfo>) -> ResourcePacksInfo {
+        ResourcePacksInfo {
+            must_accept,
+            has_scripts: behaviour_packs.iter().chain(&resource_packs).any(|pack| pack.has_scripts),
+            force_server_packs: false,
+            behaviour_packs,
+            resource_packs,
+        }
+    }
+
+    /// Decodes the layout of 1.16.20
+    pub fn decode(binary: &[u8]) -> Result<ResourcePacksInfo, Error> {
+        ResourcePacksInfo::decode_for(binary, 408)
+    }
+
+    /// Decodes the layout of `protocol`, 1.19.30 added forcing server packs and CDN urls
+    pub fn decode_for(binary: &[u8], protocol: u32) -> Result<ResourcePacksInfo, Error> {
+        let flags = if protocol >= 554 { 3 } else { 2 };
+        ensure_len(binary, 0, flags + 2)?;
+        let must_accept = binary.read_bool(0);
+        let has_scripts = binary.read_bool(1);
+        let force_server_packs = protocol >= 554 && binary.read_bool(2);
+        let mut offset = flags;
+        let mut packs = [Vec::new(), Vec::new()];
+        for (index, packs) in packs.iter_mut().enumerate() {
+            ensure_len(binary, offset, 2)?;
+            let count = binary.read_u16_le(offset);
+            offset += 2;
+            for _ in 0..count {
+                let (pack, len) = PackInfo::decode(binary, offset, index == 1)?;
+                offset += len;
+                packs.push(pack);
+            }
+        }
+        let [behaviour_packs, resource_packs] = packs;
+        Ok(ResourcePacksInfo {
+            must_accept,
+            has_scripts,
+            force_server_packs,
+            behaviour_packs,
+            resource_packs,
+        })
+    }
+
+    /// Encodes the layout of 1.16.20
+    pub fn encode(&self, binary: Vec<u8>) -> Vec<u8> {
+        self.encode_for(binary, 408)
+    }
+
+    pub fn encode_for(&self, mut binary: Vec<u8>, protocol: u32) -> Vec<u8> {
+        binary.push_bool(self.must_accept);
+        binary.push_bool(self.has_scripts);
+        if protocol >= 554 {
+            binary.push_bool(self.force_server_packs);
+        }
+        binary.push_u16_le(self.behaviour_packs.len() as u16);
+        for pack in &self.behaviour_packs {
+            binary = pack.encode(binary, false);
+        }
+        binary.push_u16_le(self.resource_packs.len() as u16);
+        for pack in &self.resource_packs {
+            binary = pack.encode(binary, true);
+        }
+        if protocol >= 554 {
+            // packs are not offered on a CDN
+            binary.push_var_u32(0);
+        }
+
+        binary
+    }
+}
+
+impl ResourcePackStack {
+    pub fn create(
+        must_accept: bool,
+        behaviour_packs: Vec<PackStackEntry>,
+        resource_packs: Vec<PackStackEntry>,
+    ) -> ResourcePackStack {
+        ResourcePackStack {
+            must_accept,
+            behaviour_packs,
+            resource_packs,
+            base_game_version: "*".to_string(),
+            experiments: Vec::new(),
+            experiments_previously_toggled: false,
+        }
+    }
+
+    /// Decodes the layout of 1.16.20
+    pub fn decode(binary: &[u8]) -> Result<ResourcePackStack, Error> {
+        ResourcePackStack::decode_for(binary, 408)
+    }
+
+    /// Decodes the layout of `protocol`, 1.16.100 replaced the experimental flag by the list of
+    /// experiments after the base game version. The flag is decoded as an experiment named
+    /// "experimental".
+    pub fn decode_for(binary: &[u8], protocol: u32) -> Result<ResourcePackStack, Error> {
+        ensure_len(binary, 0, 1)?;
+        let must_accept = binary.read_bool(0);
+        let mut offset = 1;
+        let mut packs = [Vec::new(), Vec::new()];
+        for packs in packs.iter_mut() {
+            let (count, len) = binary.read_var_u32(offset)?;
+            offset += len;
+            for _ in 0..count {
+                let mut fields = [String::new(), String::new(), String::new()];
+                for field in fields.iter_mut() {
+                    let (value, len) = binary.read_var_string(offset)?;
+                    offset += len;
+                    *field = value;
+                }
+                let [uuid, version, sub_pack_name] = fields;
+                packs.push(PackStackEntry {
+                    uuid,
+                    version,
+                    sub_pack_name,
+                });
+            }
+        }
+        let [behaviour_packs, resource_packs] = packs;
+        if protocol < 419 {
+            ensure_len(binary, offset, 1)?;
+            let experimental = binary.read_bool(offset);
+            let (base_game_version, _) = binary.read_var_string(offset + 1)?;
+            return Ok(ResourcePackStack {
+                must_accept,
+                behaviour_packs,
+                resource_packs,
+                base_game_version,
+                experiments: if experimental { vec![("experimental".to_string(), true)] } else { Vec::new() },
+                experiments_previously_toggled: false,
+            });
+        }
+        let (base_game_version, len) = binary.read_var_string(offset)?;
+        offset += len;
+        ensure_len(binary, offset, 4)?;
+        let count = binary.read_u32_le(offset);
+        offset += 4;
+        let mut experiments = Vec::new();
+        for _ in 0..count {
+            let (name, len) = binary.read_var_string(offset)?;
+            offset += len;
+            ensure_len(binary, offset, 1)?;
+            experiments.push((name, binary.read_bool(offset)));
+            offset += 1;
+        }
+        ensure_len(binary, offset, 1)?;
+        Ok(ResourcePackStack {
+            must_accept,
+            behaviour_packs,
+            resource_packs,
+            base_game_version,
+            experiments,
+            experiments_previously_toggled: binary.read_bool(offset),
+        })
+    }
+
+    /// Encodes the layout of 1.16.20
+    pub fn encode(&self, binary: Vec<u8>) -> Vec<u8> {
+        self.encode_for(binary, 408)
+    }
+
+    pub fn encode_for(&self, mut binary: Vec<u8>, protocol: u32) -> Vec<u8> {
+        binary.push_bool(self.must_accept);
+        for packs in [&self.behaviour_packs, &self.resource_packs] {
+            binary.push_var_u32(packs.len() as u32);
+            for pack in packs {
+                binary.push_var_string(&pack.uuid);
+                binary.push_var_string(&pack.version);
+                binary.push_var_string(&pack.sub_pack_name);
+            }
+        }
+        if protocol < 419 {
+            binary.push_bool(self.experiments.iter().any(|(_, enabled)| *enabled));
+            binary.push_var_string(&self.base_game_version);
+            return binary;
+        }
+        binary.push_var_string(&self.base_game_version);
+        binary.push_u32_le(self.experiments.len() as u32);
+        for (name, enabled) in &self.experiments {
+            binary.push_var_string(name);
+            binary.push_bool(*enabled);
+        }
+        binary.push_bool(self.experiments_previously_toggled);
+
+        binary
+    }
+}
+
+impl ResourcePackResponse {
+    pub fn from_u8(status: u8) -> Option<ResourcePackResponse> {
+        match status {
+            1 => Some(ResourcePackResponse::Refused),
+            2 => Some(ResourcePackResponse::SendPacks),
+            3 => Some(ResourcePackResponse::HaveAllPacks),
+            4 => Some(ResourcePackResponse::Completed),
+            _ => None,
+        }
+    }
+}
+
+impl ResourcePackClientResponse {
+    pub fn create(status: ResourcePackResponse, pack_ids: Vec<String>) -> ResourcePackClientResponse {
+        ResourcePackClientResponse { status, pack_ids }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<ResourcePackClientResponse, Error> {
+        ensure_len(binary, 0, 3)?;
+        let status = ResourcePackResponse::from_u8(binary[0])
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unknown resource pack response {}", binary[0])))?;
+        let count = binary.read_u16_le(1);
+        let mut offset = 3;
+        let mut pack_ids = Vec::new();
+        for _ in 0..count {
+            let (pack_id, len) = binary.read_var_string(offset)?;
+            offset += len;
+            pack_ids.push(pack_id);
+        }
+        Ok(ResourcePackClientResponse { status, pack_ids })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push(self.status as u8);
+        binary.push_u16_le(self.pack_ids.len() as u16);
+        for pack_id in &self.pack_ids {
+            binary.push_var_string(pack_id);
+        }
+
+        binary
+    }
+
+    pub fn status(&self) -> ResourcePackResponse {
+        self.status
+    }
+
+    /// Ids of the requested packs, "<uuid>_<version>"
+    pub fn pack_ids(&self) -> &[String] {
+        &self.pack_ids
+    }
+}
+
+impl ResourcePackDataInfo {
+    pub fn decode(binary: &[u8]) -> Result<ResourcePackDataInfo, Error> {
+        let (pack_id, mut offset) = binary.read_var_string(0)?;
+        ensure_len(binary, offset, 16)?;
+        let max_chunk_size = binary.read_u32_le(offset);
+        let chunk_count = binary.read_u32_le(offset + 4);
+        let size = binary.read_u64_le(offset + 8);
+        offset += 16;
+        let (sha256, len) = binary.read_var_bytes(offset)?;
+        offset += len;
+        ensure_len(binary, offset, 2)?;
+        Ok(ResourcePackDataInfo {
+            pack_id,
+            max_chunk_size,
+            chunk_count,
+            size,
+            sha256: sha256.to_vec(),
+            premium: binary.read_bool(offset),
+            pack_type: binary[offset + 1],
+        })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_var_string(&self.pack_id);
+        binary.push_u32_le(self.max_chunk_size);
+        binary.push_u32_le(self.chunk_count);
+        binary.push_u64_le(self.size);
+        binary.push_var_bytes(&self.sha256);
+        binary.push_bool(self.premium);
+        binary.push(self.pack_type);
+
+        binary
+    }
+}
+
+impl ResourcePackChunkData {
+    pub fn decode(binary: &[u8]) -> Result<ResourcePackChunkData, Error> {
+        let (pack_id, offset) = binary.read_var_string(0)?;
+        ensure_len(binary, offset, 12)?;
+        let (data, _) = binary.read_var_bytes(offset + 12)?;
+        Ok(ResourcePackChunkData {
+            pack_id,
+            chunk_index: binary.read_u32_le(offset),
+            progress: binary.read_u64_le(offset + 4),
+            data: data.to_vec(),
+        })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_var_string(&self.pack_id);
+        binary.push_u32_le(self.chunk_index);
+        binary.push_u64_le(self.progress);
+        binary.push_var_bytes(&self.data);
+
+        binary
+    }
+}
+
+impl ResourcePackChunkRequest {
+    pub fn create(pack_id: String, chunk_index: u32) -> ResourcePackChunkRequest {
+        ResourcePackChunkRequest { pack_id, chunk_index }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<ResourcePackChunkRequest, Error> {
+        let (pack_id, offset) = binary.read_var_string(0)?;
+        ensure_len(binary, offset, 4)?;
+        Ok(ResourcePackChunkRequest {
+            pack_id,
+            chunk_index: binary.read_u32_le(offset),
+        })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_var_string(&self.pack_id);
+        binary.push_u32_le(self.chunk_index);
+
+        binary
+    }
+
+    pub fn pack_id(&self) -> &str {
+        &self.pack_id
+    }
+
+    pub fn chunk_index(&self) -> u32 {
+        self.chunk_index
+    }
+}
+
+impl StartGame {
+    /// Start of a world with default settings for the player with `entity_id`
+    pub fn create(entity_id: u64, position: (f32, f32, f32)) -> StartGame {
+        StartGame {
+            entity_unique_id: entity_id as i64,
+            entity_runtime_id: entity_id,
+            player_gamemode: 0,
+            position,
+            pitch: 0.0,
+            yaw: 0.0,
+            seed: 0,
+            dimension: 0,
+            generator: 1,
+            world_gamemode: 0,
+            difficulty: 2,
+            spawn: (position.0 as i32, position.1 as i32, position.2 as i32),
+            time: 0,
+            rain_level: 0.0,
+            lightning_level: 0.0,
+            commands_enabled: true,
+            texture_packs_required: false,
+            game_rules: Vec::new(),
+            default_permission: 1,
+            chunk_tick_radius: 4,
+            level_id: String::new(),
+            world_name: String::new(),
+            current_tick: 0,
+            enchantment_seed: 0,
+            // empty network NBT list of compounds
+            block_palette: Vec::new(),
+            custom_blocks: Vec::new(),
+            items: Vec::new(),
+            multiplayer_correlation_id: String::new(),
+            server_authoritative_inventory: false,
+            engine: "Limonite".to_string(),
+        }
+    }
+
+    /// Encodes the layout of 1.16.20
+    pub fn encode(&self, binary: Vec<u8>) -> Vec<u8> {
+        self.encode_for(binary, 408)
+    }
+
+    pub fn encode_for(&self, mut binary: Vec<u8>, protocol: u32) -> Vec<u8> {
+        binary.push_var_i64(self.entity_unique_id);
+        binary.push_var_u64(self.entity_runtime_id);
+        binary.push_var_i32(self.player_gamemode);
+        binary.push_f32_le(self.position.0);
+        binary.push_f32_le(self.position.1);
+        binary.push_f32_le(self.position.2);
+        binary.push_f32_le(self.pitch);
+        binary.push_f32_le(self.yaw);
+
+        // level settings
+        if protocol >= 554 {
+            binary.push_i64_le(self.seed);
+        } else {
+            binary.push_var_i32(self.seed as i32);
+        }
+        binary.push_i16_le(0); // biome type
+        binary.push_var_string(""); // user defined biome
+        binary.push_var_i32(self.dimension);
+        binary.push_var_i32(self.generator);
+        binary.push_var_i32(self.world_gamemode);
+        binary.push_var_i32(self.difficulty);
+        binary.push_var_i32(self.spawn.0);
+        binary.push_var_u32(self.spawn.1 as u32);
+        binary.push_var_i32(self.spawn.2);
+        binary.push_bool(true); // achievements disabled
+        if protocol >= 554 {
+            binary.push_bool(false); // editor world
+        }
+        binary.push_var_i32(self.time);
+        binary.push_var_i32(0); // education edition offer
+        binary.push_bool(false); // education features
+        binary.push_var_string(""); // education product id
+        binary.push_f32_le(self.rain_level);
+        binary.push_f32_le(self.lightning_level);
+        binary.push_bool(false); // confirmed platform locked content
+        binary.push_bool(true); // multiplayer game
+        binary.push_bool(true); // LAN broadcast
+        binary.push_var_i32(4); // Xbox Live broadcast mode, public
+        binary.push_var_i32(4); // platform broadcast mode, public
+        binary.push_bool(self.commands_enabled);
+        binary.push_bool(self.texture_packs_required);
+        binary.push_var_u32(self.game_rules.len() as u32);
+        for (name, value) in &self.game_rules {
+            binary.push_var_string(name);
+            if protocol >= 554 {
+                binary.push_bool(false); // editable
+            }
+            match value {
+                GameRuleValue::Bool(value) => {
+                    binary.push_var_u32(1);
+                    binary.push_bool(*value);
+                }
+                GameRuleValue::Int(value) => {
+                    binary.push_var_u32(2);
+                    binary.push_var_u32(*value);
+                }
+                GameRuleValue::Float(value) => {
+                    binary.push_var_u32(3);
+                    binary.push_f32_le(*value);
+                }
+            }
+        }
+        if protocol >= 419 {
+            binary.push_u32_le(0); // experiments
+            binary.push_bool(false); // experiments previously toggled
+        }
+        binary.push_bool(false); // bonus chest
+        binary.push_bool(false); // start with map
+        binary.push_var_i32(self.default_permission);
+        binary.push_i32_le(self.chunk_tick_radius);
+        binary.push_bool(false); // locked behaviour pack
+        binary.push_bool(false); // locked resource pack
+        binary.push_bool(false); // from locked world template
+        binary.push_bool(false); // msa gamertags only
+        binary.push_bool(false); // from world template
+        binary.push_bool(false); // world template option locked
+        binary.push_bool(false); // only spawn v1 villagers
+        if protocol >= 554 {
+            binary.push_bool(false); // persona skins disabled
+            binary.push_bool(false); // custom skins disabled
+        }
+        binary.push_var_string("*"); // base game version
+        binary.push_i32_le(0); // limited world width
+        binary.push_i32_le(0); // limited world depth
+        binary.push_bool(true); // new nether
+        if protocol >= 554 {
+            binary.push_var_string(""); // education shared resource button
+            binary.push_var_string(""); // education shared resource link
+        }
+        binary.push_bool(false); // experimental gameplay override
+        if protocol >= 554 {
+            binary.push(0); // chat restriction level
+            binary.push_bool(false); // disable player interactions
+        }
+
+        binary.push_var_string(&self.level_id);
+        binary.push_var_string(&self.world_name);
+        binary.push_var_string(""); // premium world template id
+        binary.push_bool(false); // trial
+        binary.push_var_i32(0); // client authoritative movement
+        if protocol >= 554 {
+            binary.push_var_i32(0); // rewind history size
+            binary.push_bool(false); // server authoritative block breaking
+        }
+        binary.push_i64_le(self.current_tick);
+        binary.push_var_i32(self.enchantment_seed);
+        if protocol >= 554 {
+            binary.push_var_u32(self.custom_blocks.len() as u32);
+            for (name, properties) in &self.custom_blocks {
+                binary.push_var_string(name);
+                binary
+                    .push_nbt("", &Tag::Compound(properties.clone()), Encoding::Network)
+                    .expect("Failed to push custom block properties");
+            }
+        } else {
+            let block_palette = self.block_palette.iter().cloned().map(Tag::Compound).collect();
+            binary
+                .push_nbt("", &Tag::List(block_palette), Encoding::Network)
+                .expect("Failed to push block palette");
+        }
+        binary.push_var_u32(self.items.len() as u32);
+        for item in &self.items {
+            binary.push_var_string(&item.name);
+            binary.push_i16_le(item.runtime_id);
+            if protocol >= 554 {
+                binary.push_bool(item.component_based);
+            }
+        }
+        binary.push_var_string(&self.multiplayer_correlation_id);
+        binary.push_bool(self.server_authoritative_inventory);
+        if protocol >= 554 {
+            binary.push_var_string(&self.engine);
+            binary
+                .push_nbt("", &Tag::Compound(Compound::new()), Encoding::Network)
+                .expect("Failed to push player properties");
+            binary.push_u64_le(0); // block state checksum, not checked
+            binary.push_slice(&[0; 16]); // world template id
+            binary.push_bool(false); // client side generation
+        }
+
+        binary
+    }
+}
+
+impl LevelChunk {
+    pub fn create(x: i32, z: i32, sub_chunk_count: u32, payload: Vec<u8>) -> LevelChunk {
+        LevelChunk {
+            x,
+            z,
+            sub_chunk_count,
+            payload,
+        }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_var_i32(self.x);
+        binary.push_var_i32(self.z);
+        binary.push_var_u32(self.sub_chunk_count);
+        binary.push_bool(false); // blob cache
+        binary.push_var_bytes(&self.payload);
+
+        binary
+    }
+}
+
+impl RequestChunkRadius {
+    pub fn create(radius: i32) -> RequestChunkRadius {
+        RequestChunkRadius { radius }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<RequestChunkRadius, Error> {
+        let (radius, _) = binary.read_var_i32(0)?;
+        Ok(RequestChunkRadius { radius })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_var_i32(self.radius);
+
+        binary
+    }
+
+    pub fn radius(&self) -> i32 {
+        self.radius
+    }
+}
+
+impl ChunkRadiusUpdated {
+    pub fn create(radius: i32) -> ChunkRadiusUpdated {
+        ChunkRadiusUpdated { radius }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_var_i32(self.radius);
+
+        binary
+    }
+}
+
+impl SetLocalPlayerAsInitialized {
+    pub fn create(entity_runtime_id: u64) -> SetLocalPlayerAsInitialized {
+        SetLocalPlayerAsInitialized { entity_runtime_id }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<SetLocalPlayerAsInitialized, Error> {
+        let (entity_runtime_id, _) = binary.read_var_u64(0)?;
+        Ok(SetLocalPlayerAsInitialized { entity_runtime_id })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_var_u64(self.entity_runtime_id);
+
+        binary
+    }
+
+    pub fn entity_runtime_id(&self) -> u64 {
+        self.entity_runtime_id
+    }
+}
+
+impl NetworkChunkPublisherUpdate {
+    pub fn create(position: (i32, i32, i32), radius: u32) -> NetworkChunkPublisherUpdate {
+        NetworkChunkPublisherUpdate { position, radius }
+    }
+
+    /// Encodes the layout of 1.16.20
+    pub fn encode(&self, binary: Vec<u8>) -> Vec<u8> {
+        self.encode_for(binary, 408)
+    }
+
+    pub fn encode_for(&self, mut binary: Vec<u8>, protocol: u32) -> Vec<u8> {
+        binary.push_var_i32(self.position.0);
+        binary.push_var_u32(self.position.1 as u32);
+        binary.push_var_i32(self.position.2);
+        binary.push_var_u32(self.radius);
+        if protocol >= 554 {
+            binary.push_u32_le(0); // chunks the client has saved
+        }
+
+        binary
+    }
+}
+
+impl NetworkSettings {
+    /// Batches smaller than `compression_threshold` are not compressed
+    pub fn create(compression_threshold: u16, compression_algorithm: CompressionAlgorithm) -> NetworkSettings {
+        NetworkSettings {
+            compression_threshold,
+            compression_algorithm,
+            client_throttle: false,
+            client_throttle_threshold: 0,
+            client_throttle_scalar: 0.0,
+        }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<NetworkSettings, Error> {
+        if binary.len() < 10 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "network settings are too short"));
+        }
+        let algorithm = binary.read_u16_le(2);
+        let compression_algorithm = match algorithm {
+            0xffff => CompressionAlgorithm::None,
+            _ => CompressionAlgorithm::from_u8(algorithm as u8)
+                .filter(|_| algorithm <= 0xff)
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("unknown compression algorithm {}", algorithm)))?,
+        };
+        Ok(NetworkSettings {
+            compression_threshold: binary.read_u16_le(0),
+            compression_algorithm,
+            client_throttle: binary.read_bool(4),
+            client_throttle_threshold: binary[5],
+            client_throttle_scalar: binary.read_f32_le(6),
+        })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_u16_le(self.compression_threshold);
+        binary.push_u16_le(match self.compression_algorithm {
+            CompressionAlgorithm::None => 0xffff,
+            algorithm => algorithm as u16,
+        });
+        binary.push_bool(self.client_throttle);
+        binary.push(self.client_throttle_threshold);
+        binary.push_f32_le(self.client_throttle_scalar);
+
+        binary
+    }
+
+    pub fn compression_threshold(&self) -> u16 {
+        self.compression_threshold
+    }
+
+    pub fn compression_algorithm(&self) -> CompressionAlgorithm {
+        self.compression_algorithm
+    }
+}
+
+impl RequestNetworkSettings {
+    pub fn create(protocol: i32) -> RequestNetworkSettings {
+        RequestNetworkSettings { protocol }
+    }
+
+    pub fn decode(binary: &[u8]) -> Result<RequestNetworkSettings, Error> {
+        if binary.len() < 4 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "network settings request is too short"));
+        }
+        Ok(RequestNetworkSettings {
+            protocol: binary.read_i32(0),
+        })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push_i32(self.protocol);
+
+        binary
+    }
+
+    pub fn protocol(&self) -> i32 {
+        self.protocol
+    }
+}
+
+/// Fails if `binary` has less than `len` bytes left after `offset`
+fn ensure_len(binary: &[u8], offset: usize, len: usize) -> Result<(), Error> {
+    if binary.len() < offset + len {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "game packet is truncated"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start_game_golden(protocol: u32) -> Vec<u8> {
+        let mut expected = vec![0x02, 0x01, 0x00]; // entity ids, gamemode
+        expected.extend([0; 20]); // position, pitch, yaw
+        if protocol >= 554 {
+            expected.extend([0; 8]); // seed
+        } else {
+            expected.push(0x00);
+        }
+        expected.extend([0x00, 0x00, 0x00, 0x00]); // biome type and name, dimension
+        expected.extend([0x02, 0x00, 0x04, 0x00, 0x00, 0x00]); // generator, gamemode, difficulty, spawn
+        expected.push(0x01); // achievements disabled
+        if protocol >= 554 {
+            expected.push(0x00); // editor world
+        }
+        expected.extend([0x00, 0x00, 0x00, 0x00]); // time, education offer, features, product id
+        expected.extend([0; 8]); // rain and lightning
+        expected.extend([0x00, 0x01, 0x01, 0x08, 0x08, 0x01, 0x00]); // locked content to texture packs
+        expected.push(0x00); // game rules
+        if protocol >= 419 {
+            expected.extend([0x00, 0x00, 0x00, 0x00, 0x00]); // experiments, previously toggled
+        }
+        expected.extend([0x00, 0x00, 0x02, 0x04, 0x00, 0x00, 0x00]); // bonus chest to tick radius
+        expected.extend([0; 7]); // locked packs to v1 villagers
+        if protocol >= 554 {
+            expected.extend([0x00, 0x00]); // persona and custom skins disabled
+        }
+        expected.extend([0x01, b'*']); // base game version
+        expected.extend([0; 8]); // limited world
+        expected.push(0x01); // new nether
+        if protocol >= 554 {
+            expected.extend([0x00, 0x00]); // education shared resource
+        }
+        expected.push(0x00); // experimental gameplay override
+        if protocol >= 554 {
+            expected.extend([0x00, 0x00]); // chat restriction, player interactions
+        }
+        expected.extend([0x00, 0x00, 0x00, 0x00, 0x00]); // level id to client authoritative movement
+        if protocol >= 554 {
+            expected.extend([0x00, 0x00]); // rewind history, block breaking
+        }
+        expected.extend([0; 8]); // current tick
+        expected.push(0x00); // enchantment seed
+        if protocol >= 554 {
+            expected.push(0x00); // custom blocks
+        } else {
+            expected.extend([0x09, 0x00, 0x00, 0x00]); // empty block palette
+        }
+        expected.extend([0x00, 0x00, 0x00]); // items, correlation id, authoritative inventory
+        if protocol >= 554 {
+            expected.push(0x08);
+            expected.extend(b"Limonite");
+            expected.extend([0x0a, 0x00, 0x00]); // player properties
+            expected.extend([0; 8]); // block state checksum
+            expected.extend([0; 16]); // world template id
+            expected.push(0x00); // client side generation
+        }
+        expected
+    }
+
+    #[test]
+    fn start_game_layouts() {
+        let start_game = StartGame::create(1, (0.0, 0.0, 0.0));
+        for protocol in [408, 554] {
+            assert_eq!(start_game.encode_for(Vec::new(), protocol), start_game_golden(protocol), "{}", protocol);
+        }
+    }
+
+    #[test]
+    fn resource_pack_stack_layouts() {
+        let entry = PackStackEntry {
+            uuid: "a".to_string(),
+            version: "1.0.0".to_string(),
+            sub_pack_name: String::new(),
+        };
+        let mut stack = ResourcePackStack::create(true, vec![entry.clone()], Vec::new());
+        let packs = [0x01, 0x01, 0x01, b'a', 0x05, b'1', b'.', b'0', b'.', b'0', 0x00, 0x00];
+
+        let mut expected = packs.to_vec();
+        expected.extend([0x00, 0x01, b'*']); // experimental, base game version
+        assert_eq!(stack.encode_for(Vec::new(), 408), expected);
+
+        stack.experiments.push(("data_driven_items".to_string(), true));
+        let mut expected = packs.to_vec();
+        expected.extend([0x01, b'*', 0x01, 0x00, 0x00, 0x00, 0x11]);
+        expected.extend(b"data_driven_items");
+        expected.extend([0x01, 0x00]);
+        let binary = stack.encode_for(Vec::new(), 554);
+        assert_eq!(binary, expected);
+
+        let decoded = ResourcePackStack::decode_for(&binary, 554).unwrap();
+        assert_eq!(decoded.behaviour_packs, vec![entry]);
+        assert_eq!(decoded.experiments, stack.experiments);
+        let decoded = ResourcePackStack::decode_for(&stack.encode_for(Vec::new(), 408), 408).unwrap();
+        assert_eq!(decoded.base_game_version, "*");
+        assert_eq!(decoded.experiments.len(), 1);
+    }
+}
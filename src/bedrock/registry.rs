@@ -0,0 +1,209 @@
+use crate::bedrock::batch::GamePacket;
+use crate::bedrock::encryption::CipherMode;
+use crate::bedrock::login::Login;
+use crate::bedrock::packets::{
+    ClientToServerHandshake, Disconnect, GamePacketId, NetworkSettings, Packet, PlayStatus, RequestNetworkSettings,
+    ResourcePackChunkData, ResourcePackChunkRequest, ResourcePackClientResponse, ResourcePackDataInfo,
+    RequestChunkRadius, ResourcePackStack, ResourcePacksInfo, ServerToClientHandshake, SetLocalPlayerAsInitialized,
+};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::sync::OnceLock;
+
+type Decoder = fn(&[u8]) -> Result<Packet, Error>;
+type Encoder = fn(&Packet) -> Vec<u8>;
+
+/// Codecs of the game packets a protocol version is able to understand
+pub struct PacketRegistry {
+    protocol: u32,
+    version: &'static str,
+    cipher_mode: CipherMode,
+    /// whether compressed batches start with the algorithm byte, which clients expect since 1.20.60
+    algorithm_header: bool,
+    decoders: HashMap<u16, Decoder>,
+    /// encoders of packets which are only sent by the server, or whose layout differs from the
+    /// one of `Packet::encode` in this version
+    encoders: HashMap<u16, Encoder>,
+}
+
+impl PacketRegistry {
+    pub fn new(protocol: u32, version: &'static str) -> PacketRegistry {
+        PacketRegistry {
+            protocol,
+            version,
+            cipher_mode: CipherMode::Cfb8,
+            algorithm_header: false,
+            decoders: HashMap::default(),
+            encoders: HashMap::default(),
+        }
+    }
+
+    /// Copies the codecs of `registry` for a newer protocol version, so only the packets which
+    /// changed have to be registered again
+    pub fn extend(registry: PacketRegistry, protocol: u32, version: &'static str) -> PacketRegistry {
+        PacketRegistry {
+            protocol,
+            version,
+            ..registry
+        }
+    }
+
+    /// Returns the registry of a supported protocol version
+    pub fn for_protocol(protocol: u32) -> Option<&'static PacketRegistry> {
+        registries().iter().find(|registry| registry.protocol == protocol)
+    }
+
+    /// Protocol versions with a registry, oldest first
+    pub fn protocols() -> Vec<u32> {
+        registries().iter().map(|registry| registry.protocol).collect()
+    }
+
+    pub fn register(&mut self, id: GamePacketId, decoder: Decoder) {
+        self.decoders.insert(id as u16, decoder);
+    }
+
+    /// Sets how packets with `id` are encoded in this version
+    pub fn register_encoder(&mut self, id: GamePacketId, encoder: Encoder) {
+        self.encoders.insert(id as u16, encoder);
+    }
+
+    pub fn protocol(&self) -> u32 {
+        self.protocol
+    }
+
+    /// Sets how game packets are encrypted in this version
+    pub fn set_cipher_mode(&mut self, cipher_mode: CipherMode) {
+        self.cipher_mode = cipher_mode;
+    }
+
+    pub fn cipher_mode(&self) -> CipherMode {
+        self.cipher_mode
+    }
+
+    pub fn set_algorithm_header(&mut self, algorithm_header: bool) {
+        self.algorithm_header = algorithm_header;
+    }
+
+    pub fn algorithm_header(&self) -> bool {
+        self.algorithm_header
+    }
+
+    /// Name of the game version, e.g. "1.16.20"
+    pub fn version(&self) -> &'static str {
+        self.version
+    }
+
+    /// Decodes `packet` into its typed packet, packets without a decoder are returned as `Packet::Raw`
+    pub fn decode(&self, packet: &GamePacket) -> Result<Packet, Error> {
+        match self.decoders.get(&packet.id) {
+            Some(decoder) => decoder(&packet.payload),
+            None => Ok(Packet::Raw(packet.clone())),
+        }
+    }
+
+    /// Encodes `packet` the way clients of this version expect it, fails for packets this version
+    /// does not know
+    pub fn encode(&self, packet: &Packet) -> Result<GamePacket, Error> {
+        let id = packet.id();
+        if let Packet::Raw(packet) = packet {
+            return Ok(packet.clone());
+        }
+        if !self.decoders.contains_key(&id) && !self.encoders.contains_key(&id) {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("game packet 0x{:02x} does not exist in protocol {}", id, self.protocol),
+            ));
+        }
+        match self.encoders.get(&id) {
+            Some(encoder) => Ok(GamePacket::new(id, encoder(packet))),
+            None => Ok(packet.encode()),
+        }
+    }
+}
+
+fn registries() -> &'static [PacketRegistry] {
+    static REGISTRIES: OnceLock<Vec<PacketRegistry>> = OnceLock::new();
+    REGISTRIES.get_or_init(|| vec![v408(), v554()])
+}
+
+fn v408() -> PacketRegistry {
+    let mut registry = PacketRegistry::new(408, "1.16.20");
+    registry.register(GamePacketId::Login, |binary| Ok(Packet::Login(Login::decode(binary)?)));
+    registry.register(GamePacketId::PlayStatus, |binary| Ok(Packet::PlayStatus(PlayStatus::decode(binary)?)));
+    registry.register(GamePacketId::Disconnect, |binary| Ok(Packet::Disconnect(Disconnect::decode(binary)?)));
+    registry.register(GamePacketId::ResourcePacksInfo, |binary| {
+        Ok(Packet::ResourcePacksInfo(ResourcePacksInfo::decode(binary)?))
+    });
+    registry.register(GamePacketId::ResourcePackStack, |binary| {
+        Ok(Packet::ResourcePackStack(ResourcePackStack::decode(binary)?))
+    });
+    registry.register(GamePacketId::ResourcePackClientResponse, |binary| {
+        Ok(Packet::ResourcePackClientResponse(ResourcePackClientResponse::decode(binary)?))
+    });
+    registry.register(GamePacketId::ResourcePackDataInfo, |binary| {
+        Ok(Packet::ResourcePackDataInfo(ResourcePackDataInfo::decode(binary)?))
+    });
+    registry.register(GamePacketId::ResourcePackChunkData, |binary| {
+        Ok(Packet::ResourcePackChunkData(ResourcePackChunkData::decode(binary)?))
+    });
+    registry.register(GamePacketId::ResourcePackChunkRequest, |binary| {
+        Ok(Packet::ResourcePackChunkRequest(ResourcePackChunkRequest::decode(binary)?))
+    });
+    registry.register(GamePacketId::RequestChunkRadius, |binary| {
+        Ok(Packet::RequestChunkRadius(RequestChunkRadius::decode(binary)?))
+    });
+    registry.register(GamePacketId::SetLocalPlayerAsInitialized, |binary| {
+        Ok(Packet::SetLocalPlayerAsInitialized(SetLocalPlayerAsInitialized::decode(binary)?))
+    });
+    registry.register_encoder(GamePacketId::StartGame, default_encoder);
+    registry.register_encoder(GamePacketId::LevelChunk, default_encoder);
+    registry.register_encoder(GamePacketId::ChunkRadiusUpdated, default_encoder);
+    registry.register_encoder(GamePacketId::NetworkChunkPublisherUpdate, default_encoder);
+    registry.register(GamePacketId::ServerToClientHandshake, |binary| {
+        Ok(Packet::ServerToClientHandshake(ServerToClientHandshake::decode(binary)?))
+    });
+    registry.register(GamePacketId::ClientToServerHandshake, |binary| {
+        Ok(Packet::ClientToServerHandshake(ClientToServerHandshake::decode(binary)?))
+    });
+    registry
+}
+
+fn v554() -> PacketRegistry {
+    let mut registry = PacketRegistry::extend(v408(), 554, "1.19.30");
+    // clients switched from CFB8 to CTR in 1.16.220
+    registry.set_cipher_mode(CipherMode::Ctr);
+    registry.register(GamePacketId::NetworkSettings, |binary| {
+        Ok(Packet::NetworkSettings(NetworkSettings::decode(binary)?))
+    });
+    registry.register(GamePacketId::RequestNetworkSettings, |binary| {
+        Ok(Packet::RequestNetworkSettings(RequestNetworkSettings::decode(binary)?))
+    });
+    registry.register(GamePacketId::ResourcePacksInfo, |binary| {
+        Ok(Packet::ResourcePacksInfo(ResourcePacksInfo::decode_for(binary, 554)?))
+    });
+    registry.register_encoder(GamePacketId::ResourcePacksInfo, |packet| match packet {
+        Packet::ResourcePacksInfo(packet) => packet.encode_for(Vec::new(), 554),
+        _ => unreachable!(),
+    });
+    registry.register(GamePacketId::ResourcePackStack, |binary| {
+        Ok(Packet::ResourcePackStack(ResourcePackStack::decode_for(binary, 554)?))
+    });
+    registry.register_encoder(GamePacketId::ResourcePackStack, |packet| match packet {
+        Packet::ResourcePackStack(packet) => packet.encode_for(Vec::new(), 554),
+        _ => unreachable!(),
+    });
+    registry.register_encoder(GamePacketId::StartGame, |packet| match packet {
+        Packet::StartGame(packet) => packet.encode_for(Vec::new(), 554),
+        _ => unreachable!(),
+    });
+    registry.register_encoder(GamePacketId::NetworkChunkPublisherUpdate, |packet| match packet {
+        Packet::NetworkChunkPublisherUpdate(packet) => packet.encode_for(Vec::new(), 554),
+        _ => unreachable!(),
+    });
+    registry
+}
+
+/// Encodes packets which only exist in the layout of `Packet::encode`
+fn default_encoder(packet: &Packet) -> Vec<u8> {
+    packet.encode().payload
+}
@@ -0,0 +1,183 @@
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Cursor, Error, ErrorKind, Read};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Size of the chunks packs are sent in, unless configured otherwise
+pub const DEFAULT_CHUNK_SIZE: u32 = 128 * 1024;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum PackType {
+    Behaviour = 4,
+    Resources = 6,
+}
+
+/// A zipped pack as it is sent to clients
+pub struct ResourcePack {
+    uuid: String,
+    version: String,
+    name: String,
+    pack_type: PackType,
+    has_scripts: bool,
+    sha256: Vec<u8>,
+    data: Vec<u8>,
+}
+
+/// All packs the server sends to clients
+#[derive(Default)]
+pub struct ResourcePacks {
+    packs: Vec<ResourcePack>,
+}
+
+impl ResourcePack {
+    /// Loads a .mcpack or .zip file
+    pub fn load(path: &Path) -> Result<ResourcePack, Error> {
+        ResourcePack::from_bytes(fs::read(path)?)
+    }
+
+    /// Reads the manifest of the zipped pack in `data`
+    pub fn from_bytes(data: Vec<u8>) -> Result<ResourcePack, Error> {
+        let mut archive = ZipArchive::new(Cursor::new(&data)).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        // the manifest is either at the root or in the folder of the pack
+        let manifest_name = archive
+            .file_names()
+            .filter(|name| *name == "manifest.json" || name.ends_with("/manifest.json"))
+            .min_by_key(|name| name.matches('/').count())
+            .map(|name| name.to_string())
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, "pack has no manifest.json"))?;
+        let mut manifest = String::new();
+        archive
+            .by_name(&manifest_name)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+            .read_to_string(&mut manifest)?;
+        let manifest: Value = serde_json::from_str(&manifest).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+
+        let header = &manifest["header"];
+        let uuid = header["uuid"]
+            .as_str()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "manifest has no uuid"))?
+            .to_string();
+        let version = match &header["version"] {
+            Value::Array(parts) => parts.iter().map(|part| part.to_string()).collect::<Vec<String>>().join("."),
+            Value::String(version) => version.clone(),
+            _ => return Err(Error::new(ErrorKind::InvalidData, "manifest has no version")),
+        };
+        let name = header["name"].as_str().unwrap_or_default().to_string();
+        let modules: Vec<&str> = manifest["modules"]
+            .as_array()
+            .map(|modules| modules.iter().filter_map(|module| module["type"].as_str()).collect())
+            .unwrap_or_default();
+        let pack_type = if modules.contains(&"resources") {
+            PackType::Resources
+        } else if modules.contains(&"data") {
+            PackType::Behaviour
+        } else {
+            return Err(Error::new(ErrorKind::InvalidData, "manifest has neither resources nor data modules"));
+        };
+        let has_scripts = modules.iter().any(|module| matches!(*module, "script" | "javascript" | "client_data"));
+        let sha256 = Sha256::digest(&data).to_vec();
+        Ok(ResourcePack {
+            uuid,
+            version,
+            name,
+            pack_type,
+            has_scripts,
+            sha256,
+            data,
+        })
+    }
+
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Id clients request the pack with, e.g. "<uuid>_1.0.0"
+    pub fn id(&self) -> String {
+        format!("{}_{}", self.uuid, self.version)
+    }
+
+    pub fn pack_type(&self) -> PackType {
+        self.pack_type
+    }
+
+    pub fn has_scripts(&self) -> bool {
+        self.has_scripts
+    }
+
+    /// SHA-256 hash of the zipped pack
+    pub fn sha256(&self) -> &[u8] {
+        &self.sha256
+    }
+
+    /// Size of the zipped pack
+    pub fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    pub fn chunk_count(&self, chunk_size: u32) -> u32 {
+        self.data.len().div_ceil(chunk_size as usize) as u32
+    }
+
+    /// Returns the chunk at `index`, or None if the pack has less chunks
+    pub fn chunk(&self, index: u32, chunk_size: u32) -> Option<&[u8]> {
+        self.data.chunks(chunk_size as usize).nth(index as usize)
+    }
+}
+
+impl ResourcePacks {
+    pub fn new() -> ResourcePacks {
+        ResourcePacks::default()
+    }
+
+    /// Loads all .mcpack and .zip files in `directory`, packs which fail to load are skipped
+    pub fn load(directory: &Path) -> Result<ResourcePacks, Error> {
+        let mut paths: Vec<_> = fs::read_dir(directory)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| extension.eq_ignore_ascii_case("mcpack") || extension.eq_ignore_ascii_case("zip"))
+            })
+            .collect();
+        paths.sort();
+        let mut resource_packs = ResourcePacks::new();
+        for path in paths {
+            match ResourcePack::load(&path) {
+                Ok(pack) => {
+                    info!("Loaded pack {} {} ({})", pack.name(), pack.version(), pack.uuid());
+                    resource_packs.packs.push(pack);
+                }
+                Err(e) => warn!("Failed to load pack {}: {}", path.display(), e),
+            }
+        }
+        Ok(resource_packs)
+    }
+
+    pub fn packs(&self) -> &[ResourcePack] {
+        &self.packs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packs.is_empty()
+    }
+
+    /// Finds a pack by its id, the version may be left out
+    pub fn get(&self, id: &str) -> Option<&ResourcePack> {
+        self.packs.iter().find(|pack| pack.id() == id || pack.uuid() == id)
+    }
+
+    pub fn has_scripts(&self) -> bool {
+        self.packs.iter().any(|pack| pack.has_scripts())
+    }
+}
@@ -0,0 +1,81 @@
+use limonite::protocol::ping::{discover, ping, ServerStatus};
+use std::env;
+use std::process;
+use std::time::Duration;
+
+fn main() {
+    let mut json = false;
+    let mut lan = false;
+    let mut timeout = Duration::from_secs(5);
+    let mut address = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--lan" => lan = true,
+            "--timeout" => {
+                let millis = args.next().and_then(|millis| millis.parse().ok()).unwrap_or_else(|| usage());
+                timeout = Duration::from_millis(millis);
+            }
+            _ if address.is_none() && !arg.starts_with('-') => address = Some(arg),
+            _ => usage(),
+        }
+    }
+    if lan {
+        match discover(timeout) {
+            Ok(servers) if json => {
+                let servers: Vec<serde_json::Value> = servers.iter().map(to_json).collect();
+                println!("{}", serde_json::Value::from(servers));
+            }
+            Ok(servers) => {
+                println!("Found {} server(s)", servers.len());
+                for status in &servers {
+                    println!();
+                    print_status(status);
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to discover servers: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    let mut address = address.unwrap_or_else(|| usage());
+    if !address.contains(':') {
+        address.push_str(":19132");
+    }
+
+    match ping(address.as_str(), timeout) {
+        Ok(status) if json => println!("{}", to_json(&status)),
+        Ok(status) => print_status(&status),
+        Err(e) => {
+            eprintln!("Failed to ping {}: {}", address, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: limonite-ping <address[:port]> [--json] [--timeout <milliseconds>]");
+    eprintln!("       limonite-ping --lan [--json] [--timeout <milliseconds>]");
+    process::exit(2);
+}
+
+fn to_json(status: &ServerStatus) -> serde_json::Value {
+    let mut json = serde_json::to_value(&status.advertisement).expect("Failed to serialize status");
+    json["address"] = status.address.to_string().into();
+    json["latency_ms"] = (status.latency.as_millis() as u64).into();
+    json
+}
+
+fn print_status(status: &ServerStatus) {
+    let advertisement = &status.advertisement;
+    println!("{}", advertisement.motd);
+    println!("{}", advertisement.world_name);
+    println!("Address:  {} ({}ms)", status.address, status.latency.as_millis());
+    println!("Edition:  {} {} (protocol {})", advertisement.edition, advertisement.version, advertisement.protocol);
+    println!("Players:  {}/{}", advertisement.online_players, advertisement.max_players);
+    println!("Gamemode: {}", advertisement.gamemode);
+    println!("Ports:    {} (IPv4), {} (IPv6)", advertisement.ipv4_port, advertisement.ipv6_port);
+}
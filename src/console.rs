@@ -0,0 +1,64 @@
+use crate::server::Server;
+use std::io::BufRead;
+use std::net::SocketAddr;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+pub trait CommandHandler {
+    fn handle_command(&mut self, command: &str);
+}
+
+impl CommandHandler for Server {
+    fn handle_command(&mut self, command: &str) {
+        let args: Vec<&str> = command.split_whitespace().collect();
+        match args.as_slice() {
+            [] => {}
+            ["stats"] => {
+                info!("Server: {}", self.statistics());
+                for (addr, client) in &self.clients {
+                    info!("{}: {}", addr, client.statistics());
+                }
+            }
+            ["stats", addr] => match self.clients.get(*addr) {
+                Some(client) => info!("{}: {}", addr, client.statistics()),
+                None => warn!("No client connected from {}", addr),
+            },
+            ["advertisement"] => info!("{}", self.advertisement().encode()),
+            ["motd", ..] => {
+                self.bedrock_settings.set_motd(args[1..].join(" "));
+                info!("Set motd to {}", self.bedrock_settings.get_motd());
+            }
+            ["kick", addr, ..] => match addr.parse::<SocketAddr>() {
+                Ok(addr) if self.clients.contains_key(&addr.to_string()) => {
+                    let reason = args[2..].join(" ");
+                    self.kick(addr, if reason.is_empty() { "Kicked by an operator" } else { &reason });
+                }
+                _ => warn!("No client connected from {}", addr),
+            },
+            ["worldname", ..] => {
+                self.world_settings.set_name(args[1..].join(" "));
+                info!("Set world name to {}", self.world_settings.get_name());
+            }
+            ["seed"] => info!("Seed: {}", self.world_settings.get_seed()),
+            _ => warn!("Unknown command: {}", command),
+        }
+    }
+}
+
+/// Reads commands from stdin and passes them to `sender` line by line
+pub fn spawn_console(sender: Sender<String>) {
+    thread::Builder::new()
+        .name("Console".to_string())
+        .spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        })
+        .expect("Could not start console");
+}
@@ -0,0 +1,8 @@
+#[macro_use] extern crate log;
+
+pub mod bedrock;
+pub mod console;
+pub mod protocol;
+pub mod server;
+pub mod utils;
+pub mod world;
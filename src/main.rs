@@ -1,15 +1,14 @@
 #[macro_use] extern crate log;
 extern crate simplelog;
 
-use crate::protocol::RakNetSettings;
-use crate::server::Server;
+use limonite::console;
+use limonite::protocol::{RakNetSettings, ReconnectPolicy};
+use limonite::server::Server;
 use simplelog::*;
+use std::path::Path;
+use std::sync::mpsc;
 use std::thread;
 
-mod server;
-mod protocol;
-mod utils;
-
 fn main() {
     let mut config = ConfigBuilder::new();
     config.set_time_to_local(true);
@@ -17,12 +16,26 @@ fn main() {
         TermLogger::new(LevelFilter::Trace, config.build(), TerminalMode::Mixed)
     ]).expect("Failed to initialize Logger");
 
-    let rak_settings = RakNetSettings::new(6, "0.0.0.0:19132".to_string());
+    let mut rak_settings = RakNetSettings::new(6, "0.0.0.0:19132".to_string());
+    rak_settings.set_max_connections(24);
+    rak_settings.set_reconnect_policy(ReconnectPolicy::Replace);
+    let (console_sender, console_receiver) = mpsc::channel();
+    console::spawn_console(console_sender);
     let server_thread = thread::Builder::new()
         .name("RakNet Server".to_string())
-        .spawn(|| {
+        .spawn(move || {
             info!("Starting RakNet server on port {} using RakNet version {}", rak_settings.get_port(), rak_settings.get_version()); //TODO: Config
-            Server::new(rak_settings).start();
+            let mut server = Server::new(rak_settings);
+            server.console = Some(console_receiver);
+            server.bedrock_settings.set_resource_pack_directory(Some("resource_packs".to_string()));
+            server.bedrock_settings.set_block_state_directory(Some("blocks".to_string()));
+            if Path::new("blocks").is_dir() {
+                server.world_settings.set_generator("normal".to_string(), String::new());
+            } else {
+                warn!("There are no block states in blocks, players spawn in a world of nothing but air");
+                server.world_settings.set_generator("void".to_string(), "none".to_string());
+            }
+            server.start();
         })
         .expect("Could not start RakNet server");
     server_thread.join().expect("Failed to join RakNet thread");
@@ -0,0 +1,153 @@
+use serde::Serialize;
+use std::io::{Error, ErrorKind};
+use std::str::FromStr;
+
+/// Server information sent to clients in the server list
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ServerAdvertisement {
+    /// "MCPE" for Bedrock or "MCEE" for Education Edition
+    pub edition: String,
+    /// first line of the motd
+    pub motd: String,
+    pub protocol: u32,
+    pub version: String,
+    pub online_players: usize,
+    pub max_players: usize,
+    pub server_id: u64,
+    /// shown as the second line of the motd
+    pub world_name: String,
+    pub gamemode: String,
+    pub gamemode_id: u8,
+    pub ipv4_port: u16,
+    pub ipv6_port: u16,
+}
+
+impl ServerAdvertisement {
+    pub fn new(server_id: u64, ipv4_port: u16) -> ServerAdvertisement {
+        ServerAdvertisement {
+            edition: "MCPE".to_string(),
+            motd: "Limonite".to_string(),
+            protocol: 408,
+            version: "1.16.20".to_string(),
+            online_players: 0,
+            max_players: 20,
+            server_id,
+            world_name: "world".to_string(),
+            gamemode: "Survival".to_string(),
+            gamemode_id: 1,
+            ipv4_port,
+            ipv6_port: 19133,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        format!(
+            "{};{};{};{};{};{};{};{};{};{};{};{};",
+            sanitize(&self.edition),
+            sanitize(&self.motd),
+            self.protocol,
+            sanitize(&self.version),
+            self.online_players,
+            self.max_players,
+            self.server_id,
+            sanitize(&self.world_name),
+            sanitize(&self.gamemode),
+            self.gamemode_id,
+            self.ipv4_port,
+            self.ipv6_port,
+        )
+    }
+}
+
+impl FromStr for ServerAdvertisement {
+    type Err = Error;
+
+    /// Parses the advertisement of an unconnected pong, only the fields up to the player count are required
+    fn from_str(advertisement: &str) -> Result<Self, Self::Err> {
+        let fields = split(advertisement);
+        if fields.len() < 6 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("advertisement has only {} fields", fields.len()),
+            ));
+        }
+        let field = |index: usize| fields.get(index).cloned().unwrap_or_default();
+        Ok(ServerAdvertisement {
+            edition: field(0),
+            motd: field(1),
+            protocol: parse_field(&field(2), "protocol")?,
+            version: field(3),
+            online_players: parse_field(&field(4), "online players")?,
+            max_players: parse_field(&field(5), "max players")?,
+            server_id: parse_optional_field(&field(6), "server id")?,
+            world_name: field(7),
+            gamemode: field(8),
+            gamemode_id: parse_optional_field(&field(9), "gamemode id")?,
+            ipv4_port: parse_optional_field(&field(10), "ipv4 port")?,
+            ipv6_port: parse_optional_field(&field(11), "ipv6 port")?,
+        })
+    }
+}
+
+/// Semicolons separate the fields and clients show escaped ones as they are, so they are left out
+fn sanitize(field: &str) -> String {
+    field.replace(';', "")
+}
+
+/// Splits an advertisement into its fields
+fn split(advertisement: &str) -> Vec<String> {
+    let mut fields: Vec<String> = advertisement.split(';').map(|field| field.to_string()).collect();
+    if fields.last().is_some_and(|field| field.is_empty()) {
+        fields.pop();
+    }
+    fields
+}
+
+fn parse_field<T: FromStr>(field: &str, name: &str) -> Result<T, Error> {
+    field
+        .trim()
+        .parse()
+        .map_err(|_| Error::new(ErrorKind::InvalidData, format!("invalid {} \"{}\"", name, field)))
+}
+
+/// Like `parse_field`, but missing fields default to zero
+fn parse_optional_field<T: FromStr + Default>(field: &str, name: &str) -> Result<T, Error> {
+    if field.trim().is_empty() {
+        return Ok(T::default());
+    }
+    parse_field(field, name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut advertisement = ServerAdvertisement::new(42, 19132);
+        advertisement.online_players = 3;
+        let encoded = advertisement.encode();
+        assert_eq!(encoded, "MCPE;Limonite;408;1.16.20;3;20;42;world;Survival;1;19132;19133;");
+        assert_eq!(encoded.parse::<ServerAdvertisement>().unwrap(), advertisement);
+    }
+
+    #[test]
+    fn semicolons_are_left_out() {
+        let mut advertisement = ServerAdvertisement::new(42, 19132);
+        advertisement.motd = "a;b".to_string();
+        advertisement.world_name = ";".to_string();
+        let parsed: ServerAdvertisement = advertisement.encode().parse().unwrap();
+        assert_eq!(parsed.motd, "ab");
+        assert_eq!(parsed.world_name, "");
+        assert_eq!(parsed.ipv6_port, 19133);
+    }
+
+    #[test]
+    fn short_advertisements() {
+        let parsed: ServerAdvertisement = "MCPE;Motd;554;1.19.30;0;10".parse().unwrap();
+        assert_eq!(parsed.max_players, 10);
+        assert_eq!(parsed.server_id, 0);
+        assert!("MCPE;Motd;554;1.19.30;0".parse::<ServerAdvertisement>().is_err());
+        assert!("MCPE;Motd;x;1.19.30;0;10".parse::<ServerAdvertisement>().is_err());
+    }
+}
@@ -1,8 +1,44 @@
+use crate::bedrock::batch::Batch;
+use crate::bedrock::compression::Compression;
+use crate::bedrock::encryption::{self, Encryption};
+use crate::bedrock::login::Identity;
+use crate::bedrock::packets::Packet;
+use crate::bedrock::registry::PacketRegistry;
+use crate::protocol::frame::{Acknowledgement, FrameReceiver, FrameSender};
+use crate::protocol::statistics::RakNetStatistics;
+use crate::protocol::{PacketId, CLOSE_DELAY, HANDSHAKE_TIMEOUT, SESSION_TIMEOUT};
+use std::io::{Error, ErrorKind};
 use std::net::SocketAddr;
+use std::time::SystemTime;
 
 pub struct Client {
     mtu_size: i16,
     relationship: Option<SocketAddr>,
+    connected: bool,
+    guid: Option<u64>,
+    statistics: RakNetStatistics,
+    /// time the client has started connecting
+    start: SystemTime,
+    compression: Compression,
+    /// cipher state, once encryption has been enabled
+    encryption: Option<Encryption>,
+    /// whether the ServerToClientHandshake was sent and not answered yet
+    encryption_pending: bool,
+    /// who the client is, once it has logged in
+    identity: Option<Identity>,
+    /// whether LoginSuccess has been sent
+    logged_in: bool,
+    /// game protocol version, once the client has sent it
+    protocol: Option<u32>,
+    /// runtime id of the player, once the game has started
+    entity_id: Option<u64>,
+    spawned: bool,
+    /// chunks waiting to be sent once they are generated, nearest first
+    pending_chunks: Vec<(i32, i32)>,
+    frames: FrameSender,
+    received_frames: FrameReceiver,
+    /// time the session was closed at, it is dropped after `CLOSE_DELAY`
+    closed: Option<SystemTime>,
 }
 
 impl Client {
@@ -10,6 +46,22 @@ impl Client {
         Client {
             mtu_size,
             relationship: None,
+            connected: false,
+            guid: None,
+            statistics: RakNetStatistics::default(),
+            start: SystemTime::now(),
+            compression: Compression::legacy(),
+            encryption: None,
+            encryption_pending: false,
+            identity: None,
+            logged_in: false,
+            protocol: None,
+            entity_id: None,
+            spawned: false,
+            pending_chunks: Vec::new(),
+            frames: FrameSender::default(),
+            received_frames: FrameReceiver::default(),
+            closed: None,
         }
     }
 
@@ -17,7 +69,228 @@ impl Client {
         self.mtu_size
     }
 
+    pub fn set_mtu_size(&mut self, mtu_size: i16) {
+        self.mtu_size = mtu_size;
+    }
+
     pub fn set_relationship(&mut self, relationship: SocketAddr) {
         self.relationship = Some(relationship);
     }
+
+    /// Whether the client has completed the offline handshake
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    pub fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+    }
+
+    /// Whether the client has not completed the offline handshake in time
+    pub fn is_handshake_expired(&self) -> bool {
+        !self.connected && self.start.elapsed().unwrap_or_default() > HANDSHAKE_TIMEOUT
+    }
+
+    /// Closes the session, the datagrams sent before are still resent until it is dropped
+    pub fn close(&mut self) {
+        if self.closed.is_none() {
+            self.closed = Some(SystemTime::now());
+        }
+    }
+
+    /// Whether the session is closed and only waits for its last packets to arrive
+    pub fn is_closing(&self) -> bool {
+        self.closed.is_some()
+    }
+
+    /// Whether the session was closed long enough ago to be dropped
+    pub fn is_close_expired(&self) -> bool {
+        self.closed.is_some_and(|closed| closed.elapsed().unwrap_or_default() > CLOSE_DELAY)
+    }
+
+    /// Whether the client has not acknowledged a datagram for `SESSION_TIMEOUT`
+    pub fn is_timed_out(&self) -> bool {
+        self.frames
+            .oldest_unacknowledged()
+            .is_some_and(|sent| sent.elapsed().unwrap_or_default() > SESSION_TIMEOUT)
+    }
+
+    /// Unique id the client sent during the handshake
+    pub fn guid(&self) -> Option<u64> {
+        self.guid
+    }
+
+    pub fn set_guid(&mut self, guid: u64) {
+        self.guid = Some(guid);
+    }
+
+    /// Snapshot of the network statistics of this connection
+    pub fn statistics(&self) -> RakNetStatistics {
+        let mut statistics = self.statistics.clone();
+        statistics.rtt = self.frames.rtt();
+        statistics.bytes_in_flight = self.frames.bytes_in_flight() as u64;
+        statistics.split_reassemblies = self.received_frames.split_reassemblies() as u64;
+        statistics.uptime = self.start.elapsed().unwrap_or_default();
+        statistics
+    }
+
+    pub fn statistics_mut(&mut self) -> &mut RakNetStatistics {
+        &mut self.statistics
+    }
+
+    pub fn compression(&self) -> &Compression {
+        &self.compression
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    pub fn identity(&self) -> Option<&Identity> {
+        self.identity.as_ref()
+    }
+
+    pub fn set_identity(&mut self, identity: Identity) {
+        self.identity = Some(identity);
+    }
+
+    /// Whether the client has logged in and may continue with resource packs and the game
+    pub fn is_logged_in(&self) -> bool {
+        self.logged_in
+    }
+
+    pub fn set_logged_in(&mut self, logged_in: bool) {
+        self.logged_in = logged_in;
+    }
+
+    pub fn protocol(&self) -> Option<u32> {
+        self.protocol
+    }
+
+    pub fn set_protocol(&mut self, protocol: u32) {
+        self.protocol = Some(protocol);
+    }
+
+    pub fn entity_id(&self) -> Option<u64> {
+        self.entity_id
+    }
+
+    pub fn set_entity_id(&mut self, entity_id: u64) {
+        self.entity_id = Some(entity_id);
+    }
+
+    /// Whether the player has spawned in the world
+    pub fn is_spawned(&self) -> bool {
+        self.spawned
+    }
+
+    pub fn set_spawned(&mut self, spawned: bool) {
+        self.spawned = spawned;
+    }
+
+    pub fn pending_chunks(&self) -> &[(i32, i32)] {
+        &self.pending_chunks
+    }
+
+    pub fn set_pending_chunks(&mut self, pending_chunks: Vec<(i32, i32)>) {
+        self.pending_chunks = pending_chunks;
+    }
+
+    /// Codecs of the protocol version of the client
+    pub fn registry(&self) -> Result<&'static PacketRegistry, Error> {
+        let protocol = self
+            .protocol
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "protocol version of the client is unknown"))?;
+        PacketRegistry::for_protocol(protocol)
+            .ok_or_else(|| Error::new(ErrorKind::Unsupported, format!("protocol {} is not supported", protocol)))
+    }
+
+    /// Wraps `packet` in frames and returns the datagrams to send
+    pub fn encapsulate(&mut self, packet: &[u8]) -> Vec<Vec<u8>> {
+        self.frames.encapsulate(packet, self.mtu_size as usize)
+    }
+
+    /// Takes the packets out of a frame set received from the client
+    pub fn receive(&mut self, datagram: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        self.received_frames.receive(datagram)
+    }
+
+    /// Returns the ACK and NAK of the frame sets received since the last call
+    pub fn take_acknowledgements(&mut self) -> Vec<Vec<u8>> {
+        self.received_frames.take_acknowledgements()
+    }
+
+    /// Handles an ACK or NAK of the client, returns the datagrams to resend
+    pub fn acknowledge(&mut self, acknowledgement: &Acknowledgement) -> Vec<Vec<u8>> {
+        if !acknowledgement.nak {
+            self.frames.acknowledge(&acknowledgement.sequence_numbers);
+            return Vec::new();
+        }
+        let resends = self.frames.resend(&acknowledgement.sequence_numbers);
+        self.statistics.frames_resent += resends.len() as u64;
+        resends
+    }
+
+    /// Returns the datagrams the client has not acknowledged in time
+    pub fn resend_expired(&mut self) -> Vec<Vec<u8>> {
+        let resends = self.frames.resend_expired();
+        self.statistics.frames_resent += resends.len() as u64;
+        resends
+    }
+
+    /// Forgets the frames of the previous connection, e.g. after the client moved to another
+    /// address and numbers its frames from 0 again
+    pub fn reset_frames(&mut self) {
+        self.frames = FrameSender::default();
+        self.received_frames = FrameReceiver::default();
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encryption.is_some()
+    }
+
+    /// Starts the key exchange with the public key the client sent in its login and returns the
+    /// ServerToClientHandshake game packet. Everything after it is encrypted.
+    pub fn start_encryption(&mut self, client_public_key: &str) -> Result<Vec<u8>, Error> {
+        let registry = self.registry()?;
+        let (encryption, handshake) = encryption::handshake(client_public_key, registry.cipher_mode())?;
+        let handshake = registry.encode(&Packet::ServerToClientHandshake(handshake))?;
+        let game_packet = self.encode_game_packet(&Batch::new(vec![handshake]))?;
+        self.encryption = Some(encryption);
+        self.encryption_pending = true;
+        Ok(game_packet)
+    }
+
+    /// Completes the encryption handshake, returns false if none was pending
+    pub fn finish_encryption(&mut self) -> bool {
+        std::mem::take(&mut self.encryption_pending)
+    }
+
+    /// Decrypts, decompresses and splits a 0xfe game packet received from the client
+    pub fn decode_game_packet(&mut self, binary: &[u8]) -> Result<Batch, Error> {
+        let payload = match binary.split_first() {
+            Some((id, payload)) if *id == PacketId::GamePacket as u8 => payload,
+            Some((id, _)) => {
+                return Err(Error::new(ErrorKind::InvalidData, format!("0x{:02x} is not a game packet", id)));
+            }
+            None => return Err(Error::new(ErrorKind::UnexpectedEof, "game packet is empty")),
+        };
+        let payload = match &mut self.encryption {
+            Some(encryption) => encryption.decrypt(payload)?,
+            None => payload.to_vec(),
+        };
+        Batch::decode(&self.compression.decompress(&payload)?)
+    }
+
+    /// Builds a 0xfe game packet for the client, compressed and encrypted as negotiated
+    pub fn encode_game_packet(&mut self, batch: &Batch) -> Result<Vec<u8>, Error> {
+        let payload = self.compression.compress(&batch.encode(Vec::new()))?;
+        let payload = match &mut self.encryption {
+            Some(encryption) => encryption.encrypt(&payload),
+            None => payload,
+        };
+        let mut binary = vec![PacketId::GamePacket as u8];
+        binary.extend(payload);
+        Ok(binary)
+    }
 }
@@ -0,0 +1,672 @@
+use crate::protocol::packet::{PacketFlags, PacketType, Reliability};
+use crate::utils::buffer::{PacketBufferRead, PacketBufferWrite};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, SystemTime};
+
+/// Size of the IP and UDP headers, which count towards the MTU
+const UDP_HEADER_SIZE: usize = 28;
+/// Flags and sequence number of a frame set
+const FRAME_SET_HEADER_SIZE: usize = 4;
+/// Largest header of a reliable ordered frame, which is split
+const FRAME_HEADER_SIZE: usize = 20;
+/// Id of a datagram acknowledging frame sets
+const ACK_ID: u8 = 0xc0;
+/// Id of a datagram asking for frame sets to be resent
+const NAK_ID: u8 = 0xa0;
+/// Most sequence numbers a single ACK or NAK record may cover
+const MAX_ACK_RANGE: u32 = 4096;
+/// Most parts a split packet may have
+const MAX_SPLIT_COUNT: u32 = 2048;
+/// Most split packets which may be reassembled at the same time
+const MAX_SPLITS: usize = 4;
+/// How far ahead of the oldest missing frame reliable and ordered frames are accepted
+const RECEIVE_WINDOW: u32 = 4096;
+/// Order channels RakNet supports
+const ORDER_CHANNELS: u8 = 32;
+/// Time after which unacknowledged datagrams are resent, until the round trip time is known
+const MAX_RESEND_TIMEOUT: Duration = Duration::from_secs(2);
+const MIN_RESEND_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Position of a frame in a packet which was too large for a single datagram
+pub struct Split {
+    pub count: u32,
+    pub id: u16,
+    pub index: u32,
+}
+
+/// A packet, or a part of it, encapsulated in a frame set
+pub struct Frame {
+    pub reliability: Reliability,
+    pub reliable_index: u32,
+    pub sequenced_index: u32,
+    pub ordered_index: u32,
+    pub order_channel: u8,
+    pub split: Option<Split>,
+    pub body: Vec<u8>,
+}
+
+/// A connected datagram carrying frames
+pub struct FrameSet {
+    pub sequence_number: u32,
+    pub frames: Vec<Frame>,
+}
+
+/// ACK or NAK of the frame sets with the given sequence numbers
+pub struct Acknowledgement {
+    pub nak: bool,
+    pub sequence_numbers: Vec<u32>,
+}
+
+/// Frames sent to a client. Every datagram carries a single frame and is kept until the client
+/// acknowledges it, it is resent if the client reports it lost or does not acknowledge it in time.
+#[derive(Default)]
+pub struct FrameSender {
+    sequence_number: u32,
+    reliable_index: u32,
+    ordered_index: u32,
+    split_id: u16,
+    unacknowledged: HashMap<u32, SentDatagram>,
+    /// smoothed round trip time, once a datagram was acknowledged
+    rtt: Option<Duration>,
+}
+
+struct SentDatagram {
+    binary: Vec<u8>,
+    sent: SystemTime,
+    /// time it was sent at the first time, before any resends
+    first_sent: SystemTime,
+}
+
+/// Reassembles the packets in the frame sets received from a client, dropping duplicates and
+/// delivering ordered packets in order
+#[derive(Default)]
+pub struct FrameReceiver {
+    /// frame sets received since the last ACK
+    acks: Vec<u32>,
+    /// frame sets which were skipped since the last NAK
+    naks: Vec<u32>,
+    /// sequence number of the next frame set expected
+    next_sequence_number: u32,
+    /// reliable frames below this index have all been received
+    reliable_base: u32,
+    /// reliable frames received above `reliable_base`
+    reliable_received: HashSet<u32>,
+    splits: HashMap<u16, SplitPacket>,
+    ordering: HashMap<u8, OrderingChannel>,
+    /// newest sequenced index per order channel
+    sequenced: HashMap<u8, u32>,
+}
+
+/// Parts of a split packet received so far
+struct SplitPacket {
+    parts: Vec<Option<Vec<u8>>>,
+    received: usize,
+}
+
+/// Next ordered index of a channel and the packets which arrived before it
+#[derive(Default)]
+struct OrderingChannel {
+    next: u32,
+    pending: BTreeMap<u32, Vec<u8>>,
+}
+
+impl Frame {
+    /// Decodes the frame at `offset`, returns it and the offset of the next frame
+    pub fn decode(binary: &[u8], offset: usize) -> Result<(Frame, usize), Error> {
+        let truncated = || Error::new(ErrorKind::UnexpectedEof, "frame is truncated");
+        let mut offset = offset;
+        let flags = PacketFlags::from_u8(*binary.get(offset).ok_or_else(truncated)?)?;
+        if binary.len() < offset + 3 {
+            return Err(truncated());
+        }
+        let len = (binary.read_u16(offset + 1) as usize).div_ceil(8);
+        offset += 3;
+        let mut header_len = 0;
+        if is_reliable(flags.reliability) {
+            header_len += 3;
+        }
+        if is_sequenced(flags.reliability) {
+            header_len += 3;
+        }
+        if is_sequenced(flags.reliability) || is_ordered(flags.reliability) {
+            header_len += 4;
+        }
+        if flags.has_split_packet {
+            header_len += 10;
+        }
+        if binary.len() < offset + header_len + len {
+            return Err(truncated());
+        }
+        let mut frame = Frame {
+            reliability: flags.reliability,
+            reliable_index: 0,
+            sequenced_index: 0,
+            ordered_index: 0,
+            order_channel: 0,
+            split: None,
+            body: Vec::new(),
+        };
+        if is_reliable(flags.reliability) {
+            frame.reliable_index = binary.read_u24_le(offset);
+            offset += 3;
+        }
+        if is_sequenced(flags.reliability) {
+            frame.sequenced_index = binary.read_u24_le(offset);
+            offset += 3;
+        }
+        if is_sequenced(flags.reliability) || is_ordered(flags.reliability) {
+            frame.ordered_index = binary.read_u24_le(offset);
+            frame.order_channel = binary[offset + 3];
+            offset += 4;
+        }
+        if flags.has_split_packet {
+            frame.split = Some(Split {
+                count: binary.read_u32(offset),
+                id: binary.read_u16(offset + 4),
+                index: binary.read_u32(offset + 6),
+            });
+            offset += 10;
+        }
+        frame.body = binary[offset..offset + len].to_vec();
+
+        Ok((frame, offset + len))
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        let reliability = self.reliability as u8;
+        binary.push((reliability << 5) | if self.split.is_some() { 0x10 } else { 0 });
+        binary.push_u16((self.body.len() * 8) as u16);
+        if is_reliable(self.reliability) {
+            binary.push_u24_le(self.reliable_index);
+        }
+        if is_sequenced(self.reliability) {
+            binary.push_u24_le(self.sequenced_index);
+        }
+        if is_sequenced(self.reliability) || is_ordered(self.reliability) {
+            binary.push_u24_le(self.ordered_index);
+            binary.push(self.order_channel);
+        }
+        if let Some(split) = &self.split {
+            binary.push_u32(split.count);
+            binary.push_u16(split.id);
+            binary.push_u32(split.index);
+        }
+        binary.push_slice(&self.body);
+
+        binary
+    }
+}
+
+impl FrameSet {
+    pub fn decode(binary: &[u8]) -> Result<FrameSet, Error> {
+        let id = *binary.first().ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "frame set is empty"))?;
+        let packet_type = PacketType::from_u8(id);
+        if !packet_type.is_connected_to_peer || packet_type.is_ack || packet_type.is_nak {
+            return Err(Error::new(ErrorKind::InvalidData, format!("0x{:02x} is not a frame set", id)));
+        }
+        if binary.len() < FRAME_SET_HEADER_SIZE {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "frame set is truncated"));
+        }
+        let mut frames = Vec::new();
+        let mut offset = FRAME_SET_HEADER_SIZE;
+        while offset < binary.len() {
+            let (frame, next) = Frame::decode(binary, offset)?;
+            frames.push(frame);
+            offset = next;
+        }
+        Ok(FrameSet {
+            sequence_number: binary.read_u24_le(1),
+            frames,
+        })
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        // valid datagram, needs B and AS
+        binary.push(0x84);
+        binary.push_u24_le(self.sequence_number);
+        for frame in &self.frames {
+            binary = frame.encode(binary);
+        }
+
+        binary
+    }
+}
+
+impl Acknowledgement {
+    pub fn decode(binary: &[u8]) -> Result<Acknowledgement, Error> {
+        let truncated = || Error::new(ErrorKind::UnexpectedEof, "acknowledgement is truncated");
+        let id = *binary.first().ok_or_else(truncated)?;
+        let packet_type = PacketType::from_u8(id);
+        if !packet_type.is_connected_to_peer || packet_type.is_ack == packet_type.is_nak {
+            return Err(Error::new(ErrorKind::InvalidData, format!("0x{:02x} is not an ACK or NAK", id)));
+        }
+        if binary.len() < 3 {
+            return Err(truncated());
+        }
+        let mut sequence_numbers = Vec::new();
+        let mut offset = 3;
+        for _ in 0..binary.read_u16(1) {
+            let single = *binary.get(offset).ok_or_else(truncated)? != 0;
+            let len = if single { 4 } else { 7 };
+            if binary.len() < offset + len {
+                return Err(truncated());
+            }
+            let min = binary.read_u24_le(offset + 1);
+            let max = if single { min } else { binary.read_u24_le(offset + 4) };
+            if max < min || max - min >= MAX_ACK_RANGE {
+                return Err(Error::new(ErrorKind::InvalidData, format!("invalid acknowledgement range {}..={}", min, max)));
+            }
+            sequence_numbers.extend(min..=max);
+            offset += len;
+        }
+        Ok(Acknowledgement {
+            nak: packet_type.is_nak,
+            sequence_numbers,
+        })
+    }
+
+    /// Encodes the sequence numbers as ranges of consecutive numbers
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        let mut sequence_numbers = self.sequence_numbers.clone();
+        sequence_numbers.sort_unstable();
+        sequence_numbers.dedup();
+        let mut ranges: Vec<(u32, u32)> = Vec::new();
+        for sequence_number in sequence_numbers {
+            match ranges.last_mut() {
+                Some((_, max)) if *max + 1 == sequence_number => *max = sequence_number,
+                _ => ranges.push((sequence_number, sequence_number)),
+            }
+        }
+        binary.push(if self.nak { NAK_ID } else { ACK_ID });
+        binary.push_u16(ranges.len() as u16);
+        for (min, max) in ranges {
+            binary.push_bool(min == max);
+            binary.push_u24_le(min);
+            if min != max {
+                binary.push_u24_le(max);
+            }
+        }
+
+        binary
+    }
+}
+
+impl FrameReceiver {
+    /// Takes the frames out of a frame set and returns the packets which are complete and due,
+    /// in the order they are to be handled
+    pub fn receive(&mut self, binary: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        let frame_set = FrameSet::decode(binary)?;
+        let skipped = distance(self.next_sequence_number, frame_set.sequence_number);
+        if skipped < RECEIVE_WINDOW {
+            for missing in 0..skipped {
+                self.naks.push((self.next_sequence_number + missing) & 0xffffff);
+            }
+            self.next_sequence_number = (frame_set.sequence_number + 1) & 0xffffff;
+        }
+        self.acks.push(frame_set.sequence_number);
+        let mut packets = Vec::new();
+        for frame in frame_set.frames {
+            let reliable = is_reliable(frame.reliability);
+            let reliable_index = frame.reliable_index;
+            if reliable {
+                let distance = distance(self.reliable_base, reliable_index);
+                // behind the window it was received already, ahead of it the client resends it later
+                if distance >= RECEIVE_WINDOW || self.reliable_received.contains(&reliable_index) {
+                    continue;
+                }
+            }
+            let frame = match frame.split {
+                Some(_) => match self.reassemble(frame) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        debug!("Dropping frame: {}", e);
+                        continue;
+                    }
+                },
+                None => Some(frame),
+            };
+            if reliable {
+                self.mark_reliable(reliable_index);
+            }
+            if let Some(frame) = frame {
+                self.deliver(frame, &mut packets);
+            }
+        }
+        Ok(packets)
+    }
+
+    /// Returns the ACK of the frame sets received and the NAK of the ones skipped since the last
+    /// call, if there are any
+    pub fn take_acknowledgements(&mut self) -> Vec<Vec<u8>> {
+        let mut acknowledgements = Vec::new();
+        for (nak, sequence_numbers) in [(false, &mut self.acks), (true, &mut self.naks)] {
+            if !sequence_numbers.is_empty() {
+                let acknowledgement = Acknowledgement {
+                    nak,
+                    sequence_numbers: std::mem::take(sequence_numbers),
+                };
+                acknowledgements.push(acknowledgement.encode(Vec::new()));
+            }
+        }
+        acknowledgements
+    }
+
+    /// Split packets which are still missing parts
+    pub fn split_reassemblies(&self) -> usize {
+        self.splits.len()
+    }
+
+    fn mark_reliable(&mut self, reliable_index: u32) {
+        self.reliable_received.insert(reliable_index);
+        while self.reliable_received.remove(&self.reliable_base) {
+            self.reliable_base = (self.reliable_base + 1) & 0xffffff;
+        }
+    }
+
+    /// Stores a part of a split packet, returns the whole packet once all parts arrived
+    fn reassemble(&mut self, mut frame: Frame) -> Result<Option<Frame>, Error> {
+        let split = frame.split.take().unwrap();
+        if split.count == 0 || split.count > MAX_SPLIT_COUNT || split.index >= split.count {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid split part {} of {}", split.index, split.count),
+            ));
+        }
+        if !self.splits.contains_key(&split.id) && self.splits.len() >= MAX_SPLITS {
+            return Err(Error::new(ErrorKind::InvalidData, "too many split packets at the same time"));
+        }
+        let packet = self.splits.entry(split.id).or_insert_with(|| SplitPacket {
+            parts: vec![None; split.count as usize],
+            received: 0,
+        });
+        if packet.parts.len() != split.count as usize {
+            return Err(Error::new(ErrorKind::InvalidData, format!("split packet {} changed its size", split.id)));
+        }
+        let part = &mut packet.parts[split.index as usize];
+        if part.is_none() {
+            *part = Some(std::mem::take(&mut frame.body));
+            packet.received += 1;
+        }
+        if packet.received < packet.parts.len() {
+            return Ok(None);
+        }
+        let packet = self.splits.remove(&split.id).unwrap();
+        frame.body = packet.parts.into_iter().flatten().flatten().collect();
+        Ok(Some(frame))
+    }
+
+    /// Adds the packet of `frame` to `packets` if it is due, ordered packets which arrive early
+    /// are kept until the ones before them arrived
+    fn deliver(&mut self, frame: Frame, packets: &mut Vec<Vec<u8>>) {
+        if (is_sequenced(frame.reliability) || is_ordered(frame.reliability)) && frame.order_channel >= ORDER_CHANNELS {
+            debug!("Dropping frame on invalid order channel {}", frame.order_channel);
+            return;
+        }
+        if is_sequenced(frame.reliability) {
+            // older packets are dropped, they were superseded by the newest one
+            let newer = self.sequenced.get(&frame.order_channel).is_none_or(|newest| {
+                let distance = distance(*newest, frame.sequenced_index);
+                distance != 0 && distance < 0x800000
+            });
+            if newer {
+                self.sequenced.insert(frame.order_channel, frame.sequenced_index);
+                packets.push(frame.body);
+            }
+        } else if is_ordered(frame.reliability) {
+            let channel = self.ordering.entry(frame.order_channel).or_default();
+            let distance = distance(channel.next, frame.ordered_index);
+            if distance == 0 {
+                packets.push(frame.body);
+                channel.next = (channel.next + 1) & 0xffffff;
+                while let Some(body) = channel.pending.remove(&channel.next) {
+                    packets.push(body);
+                    channel.next = (channel.next + 1) & 0xffffff;
+                }
+            } else if distance < RECEIVE_WINDOW {
+                channel.pending.insert(frame.ordered_index, frame.body);
+            }
+        } else {
+            packets.push(frame.body);
+        }
+    }
+}
+
+impl FrameSender {
+    /// Encapsulates `body` in reliable ordered frames, split up to fit in `mtu_size`, and returns
+    /// the encoded frame sets
+    pub fn encapsulate(&mut self, body: &[u8], mtu_size: usize) -> Vec<Vec<u8>> {
+        let max_body_size = mtu_size.saturating_sub(UDP_HEADER_SIZE + FRAME_SET_HEADER_SIZE + FRAME_HEADER_SIZE).max(1);
+        let ordered_index = self.ordered_index;
+        self.ordered_index = (self.ordered_index + 1) & 0xffffff;
+        let chunks: Vec<&[u8]> = if body.is_empty() { vec![body] } else { body.chunks(max_body_size).collect() };
+        let split_id = self.split_id;
+        if chunks.len() > 1 {
+            self.split_id = self.split_id.wrapping_add(1);
+        }
+        let count = chunks.len() as u32;
+        let mut frame_sets = Vec::new();
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let frame = Frame {
+                reliability: Reliability::ReliableOrdered,
+                reliable_index: self.reliable_index,
+                sequenced_index: 0,
+                ordered_index,
+                order_channel: 0,
+                split: if count > 1 { Some(Split { count, id: split_id, index: index as u32 }) } else { None },
+                body: chunk.to_vec(),
+            };
+            self.reliable_index = (self.reliable_index + 1) & 0xffffff;
+            let frame_set = FrameSet {
+                sequence_number: self.sequence_number,
+                frames: vec![frame],
+            };
+            let binary = frame_set.encode(Vec::new());
+            let now = SystemTime::now();
+            self.unacknowledged.insert(self.sequence_number, SentDatagram {
+                binary: binary.clone(),
+                sent: now,
+                first_sent: now,
+            });
+            self.sequence_number = (self.sequence_number + 1) & 0xffffff;
+            frame_sets.push(binary);
+        }
+        frame_sets
+    }
+
+    /// Forgets the datagrams the client has received and updates the round trip time
+    pub fn acknowledge(&mut self, sequence_numbers: &[u32]) {
+        for sequence_number in sequence_numbers {
+            if let Some(datagram) = self.unacknowledged.remove(sequence_number) {
+                let sample = datagram.sent.elapsed().unwrap_or_default();
+                self.rtt = Some(match self.rtt {
+                    Some(rtt) => (rtt * 7 + sample) / 8,
+                    None => sample,
+                });
+            }
+        }
+    }
+
+    /// Returns the datagrams the client reported lost, with new sequence numbers
+    pub fn resend(&mut self, sequence_numbers: &[u32]) -> Vec<Vec<u8>> {
+        let lost: Vec<SentDatagram> = sequence_numbers
+            .iter()
+            .filter_map(|sequence_number| self.unacknowledged.remove(sequence_number))
+            .collect();
+        lost.into_iter().map(|datagram| self.resequence(datagram)).collect()
+    }
+
+    /// Returns the datagrams which were not acknowledged in time, with new sequence numbers
+    pub fn resend_expired(&mut self) -> Vec<Vec<u8>> {
+        let timeout = self.resend_timeout();
+        let mut expired: Vec<u32> = self
+            .unacknowledged
+            .iter()
+            .filter(|(_, datagram)| datagram.sent.elapsed().unwrap_or_default() > timeout)
+            .map(|(sequence_number, _)| *sequence_number)
+            .collect();
+        expired.sort_unstable_by_key(|sequence_number| self.unacknowledged[sequence_number].sent);
+        self.resend(&expired)
+    }
+
+    /// Smoothed round trip time, unknown until the first datagram was acknowledged
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Bytes sent which have not been acknowledged yet
+    pub fn bytes_in_flight(&self) -> usize {
+        self.unacknowledged.values().map(|datagram| datagram.binary.len()).sum()
+    }
+
+    /// Time the oldest datagram which has not been acknowledged yet was first sent at
+    pub fn oldest_unacknowledged(&self) -> Option<SystemTime> {
+        self.unacknowledged.values().map(|datagram| datagram.first_sent).min()
+    }
+
+    fn resend_timeout(&self) -> Duration {
+        self.rtt
+            .map_or(MAX_RESEND_TIMEOUT, |rtt| (rtt * 2).clamp(MIN_RESEND_TIMEOUT, MAX_RESEND_TIMEOUT))
+    }
+
+    /// Gives a datagram which is resent the next sequence number
+    fn resequence(&mut self, mut datagram: SentDatagram) -> Vec<u8> {
+        let sequence_number = self.sequence_number;
+        self.sequence_number = (self.sequence_number + 1) & 0xffffff;
+        datagram.binary[1..4].copy_from_slice(&sequence_number.to_le_bytes()[0..3]);
+        datagram.sent = SystemTime::now();
+        let binary = datagram.binary.clone();
+        self.unacknowledged.insert(sequence_number, datagram);
+        binary
+    }
+}
+
+/// How far `to` is ahead of `from`, with both being u24 indices which wrap around
+fn distance(from: u32, to: u32) -> u32 {
+    to.wrapping_sub(from) & 0xffffff
+}
+
+fn is_reliable(reliability: Reliability) -> bool {
+    matches!(
+        reliability,
+        Reliability::Reliable
+            | Reliability::ReliableOrdered
+            | Reliability::ReliableSequenced
+            | Reliability::ReliableAck
+            | Reliability::ReliableOrderedAck
+    )
+}
+
+fn is_sequenced(reliability: Reliability) -> bool {
+    matches!(reliability, Reliability::UnreliableSequenced | Reliability::ReliableSequenced)
+}
+
+fn is_ordered(reliability: Reliability) -> bool {
+    matches!(reliability, Reliability::ReliableOrdered | Reliability::ReliableOrderedAck)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_packets_are_reassembled() {
+        let body: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        let datagrams = FrameSender::default().encapsulate(&body, 576);
+        assert!(datagrams.len() > 1);
+        let mut receiver = FrameReceiver::default();
+        for datagram in datagrams.iter().skip(1).rev() {
+            assert!(receiver.receive(datagram).unwrap().is_empty());
+        }
+        assert_eq!(receiver.receive(&datagrams[0]).unwrap(), vec![body]);
+        assert!(receiver.splits.is_empty());
+    }
+
+    #[test]
+    fn ordered_packets_wait_for_earlier_ones() {
+        let mut sender = FrameSender::default();
+        let datagrams: Vec<Vec<u8>> = (1..=3).flat_map(|i| sender.encapsulate(&[i], 1400)).collect();
+        let mut receiver = FrameReceiver::default();
+        assert!(receiver.receive(&datagrams[2]).unwrap().is_empty());
+        assert_eq!(receiver.receive(&datagrams[0]).unwrap(), vec![vec![1]]);
+        assert_eq!(receiver.receive(&datagrams[1]).unwrap(), vec![vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn duplicates_are_dropped_but_acknowledged() {
+        let datagram = FrameSender::default().encapsulate(&[1, 2, 3], 1400).remove(0);
+        let mut receiver = FrameReceiver::default();
+        assert_eq!(receiver.receive(&datagram).unwrap(), vec![vec![1, 2, 3]]);
+        assert!(receiver.receive(&datagram).unwrap().is_empty());
+        let acknowledgements = receiver.take_acknowledgements();
+        assert_eq!(acknowledgements.len(), 1);
+        assert_eq!(Acknowledgement::decode(&acknowledgements[0]).unwrap().sequence_numbers, vec![0]);
+        assert!(receiver.take_acknowledgements().is_empty());
+    }
+
+    #[test]
+    fn skipped_frame_sets_are_reported() {
+        let mut sender = FrameSender::default();
+        let datagrams: Vec<Vec<u8>> = (1..=4).flat_map(|i| sender.encapsulate(&[i], 1400)).collect();
+        let mut receiver = FrameReceiver::default();
+        receiver.receive(&datagrams[0]).unwrap();
+        receiver.receive(&datagrams[3]).unwrap();
+        let acknowledgements: Vec<Acknowledgement> = receiver
+            .take_acknowledgements()
+            .iter()
+            .map(|binary| Acknowledgement::decode(binary).unwrap())
+            .collect();
+        assert_eq!(acknowledgements[0].sequence_numbers, vec![0, 3]);
+        assert!(acknowledgements[1].nak);
+        assert_eq!(acknowledgements[1].sequence_numbers, vec![1, 2]);
+        // late frame sets are still taken
+        assert_eq!(receiver.receive(&datagrams[1]).unwrap(), vec![vec![2]]);
+        assert_eq!(receiver.receive(&datagrams[2]).unwrap(), vec![vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn lost_datagrams_are_resent_until_acknowledged() {
+        let mut sender = FrameSender::default();
+        let sent: Vec<Vec<u8>> = (1..=3).flat_map(|i| sender.encapsulate(&[i], 1400)).collect();
+        assert_eq!(sender.bytes_in_flight(), sent.iter().map(|datagram| datagram.len()).sum::<usize>());
+        sender.acknowledge(&[0, 2]);
+        assert!(sender.rtt().is_some());
+        assert_eq!(sender.bytes_in_flight(), sent[1].len());
+        let resent = sender.resend(&[1]);
+        assert_eq!(resent.len(), 1);
+        // same frame, next sequence number
+        assert_eq!(resent[0][1..4], [3, 0, 0]);
+        assert_eq!(resent[0][4..], sent[1][4..]);
+        assert!(sender.resend(&[1]).is_empty());
+        sender.acknowledge(&[3]);
+        assert_eq!(sender.bytes_in_flight(), 0);
+        assert!(sender.oldest_unacknowledged().is_none());
+    }
+
+    #[test]
+    fn acknowledgement_ranges() {
+        let ack = Acknowledgement {
+            nak: false,
+            sequence_numbers: vec![5, 1, 2, 3, 7],
+        };
+        let binary = ack.encode(Vec::new());
+        assert_eq!(
+            binary,
+            vec![0xc0, 0x00, 0x03, 0x00, 0x01, 0x00, 0x00, 0x03, 0x00, 0x00, 0x01, 0x05, 0x00, 0x00, 0x01, 0x07, 0x00, 0x00]
+        );
+        let decoded = Acknowledgement::decode(&binary).unwrap();
+        assert!(!decoded.nak);
+        assert_eq!(decoded.sequence_numbers, vec![1, 2, 3, 5, 7]);
+        // a single record may not make the server walk millions of sequence numbers
+        let huge = vec![0xa0, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff];
+        assert!(Acknowledgement::decode(&huge).is_err());
+    }
+
+    #[test]
+    fn truncated_frame_sets() {
+        let datagram = FrameSender::default().encapsulate(&[1, 2, 3], 1400).remove(0);
+        assert!(FrameSet::decode(&datagram[..datagram.len() - 1]).is_err());
+        assert!(FrameSet::decode(&datagram[..2]).is_err());
+        assert_eq!(FrameSet::decode(&datagram).unwrap().frames[0].body, vec![1, 2, 3]);
+    }
+}
@@ -1,20 +1,36 @@
 use crate::utils::buffer::PacketBufferRead;
+use crate::bedrock::handler::GameHandler;
 use crate::protocol::client::Client;
-use crate::protocol::packet::PacketInfo;
-use crate::protocol::PacketId;
+use crate::protocol::frame::Acknowledgement;
+use crate::protocol::inbound::{ConnectedPing, ConnectionRequest, UnconnectedPing};
+use crate::protocol::packet::{PacketInfo, PacketType};
+use crate::protocol::{PacketId, ReconnectPolicy};
 use crate::server::Server;
 use std::net::{SocketAddr};
 use crate::protocol::outbound::{
-    UnconnectedPong, IncompatibleProtocolVersion, ConnectionReply1, ConnectionReply2
+    UnconnectedPong, IncompatibleProtocolVersion, ConnectionReply1, ConnectionReply2, NoFreeIncomingConnections,
+    AlreadyConnected, ConnectedPong, ConnectionRequestAccepted,
 };
 
+/// Id, magic and raknet version, followed by the padding which makes up the mtu size
+const CONNECTION_REQUEST_1_MIN_LEN: usize = 19;
+/// Id, magic, an IPv4 address, the mtu size and the guid
+const CONNECTION_REQUEST_2_MIN_LEN: usize = 34;
+
 pub trait Handler {
     fn handle_packet(&mut self, packet: &[u8], src: SocketAddr);
+    /// Handles packets received on the LAN discovery socket, which only answers pings
+    fn handle_discovery_packet(&mut self, packet: &[u8], src: SocketAddr);
 }
 
 //TODO: Split up handler.
 impl Handler for Server {
+    #[allow(clippy::needless_borrow, clippy::len_zero, clippy::to_string_in_format_args)]
     fn handle_packet(&mut self, packet_bytes: &[u8], src: SocketAddr) {
+        if packet_bytes.first().is_some_and(|id| PacketType::from_u8(*id).is_connected_to_peer) {
+            self.handle_datagram(packet_bytes, src);
+            return;
+        }
         let packet_info = PacketInfo::from_bytes(&packet_bytes);
         let mut resp: Vec<u8> = Vec::new();
         if packet_info.packet_id().is_none() {
@@ -30,13 +46,30 @@ impl Handler for Server {
         );
 
         match packet_info.packet_id().unwrap() {
-            PacketId::UnconnectedPing => {
-                resp = UnconnectedPong::create(self.start.elapsed().unwrap().as_millis(), self.server_id).encode(resp.clone());
+            PacketId::UnconnectedPing | PacketId::UnconnectedPingOpenConnections => {
+                resp = match self.answer_ping(packet_bytes, src) {
+                    Some(pong) => pong,
+                    None => return,
+                };
             }
             PacketId::ConnectionRequest1 => {
+                if packet_bytes.len() < CONNECTION_REQUEST_1_MIN_LEN {
+                    debug!("{} sent a truncated ConnectionRequest1, ignoring", src);
+                    return;
+                }
                 let raknet_version = packet_bytes[17];
                 let mtu_size = packet_bytes[19..].len() as i16;
-                if raknet_version != self.raknet_settings.get_version() {
+                let already_connected = self.clients.get(&src.to_string()).is_some_and(|client| client.is_connected());
+                if already_connected && self.raknet_settings.get_reconnect_policy() == ReconnectPolicy::Refuse {
+                    debug!("{} is already connected, refusing", src);
+                    resp = AlreadyConnected::create(self.server_id).encode(resp.clone());
+                } else if !self.has_free_connections(&src.to_string()) {
+                    debug!("No free connections, refusing {}", src);
+                    resp = NoFreeIncomingConnections::create(self.server_id).encode(resp.clone());
+                } else if !self.has_free_handshakes(&src.to_string()) {
+                    debug!("Too many clients are in the handshake, refusing {}", src);
+                    resp = NoFreeIncomingConnections::create(self.server_id).encode(resp.clone());
+                } else if raknet_version != self.raknet_settings.get_version() {
                     debug!(
                         "{} has an incompatible raknet version ({})",
                         src.to_string(),
@@ -44,17 +77,46 @@ impl Handler for Server {
                     );
                     resp = IncompatibleProtocolVersion::create(raknet_version, self.server_id).encode(resp.clone());
                 } else {
+                    if already_connected {
+                        debug!("{} reconnected, dropping stale session", src);
+                        self.remove_client(&src.to_string());
+                    }
                     resp = ConnectionReply1::create(self.server_id, 0x00, mtu_size).encode(resp.clone());
+                    self.clients
+                        .insert(src.to_string(), Client::new(mtu_size));
                 }
-                self.clients
-                    .insert(src.to_string(), Client::new(mtu_size));
             }
             PacketId::ConnectionRequest2 => {
-                let client = self.clients.get_mut(&src.to_string()).unwrap();
-                client.set_relationship(packet_bytes.read_address(17));
-                resp = ConnectionReply2::create(self.server_id, client.mtu_size(), 0x00).encode(resp.clone());
-            }
-            PacketId::ConnectionRequest => {
+                if packet_bytes.len() < CONNECTION_REQUEST_2_MIN_LEN {
+                    debug!("{} sent a truncated ConnectionRequest2, ignoring", src);
+                    return;
+                }
+                let client_guid = packet_bytes.read_u64(packet_bytes.len() - 8);
+                let rebinding = self.clients.contains_key(&src.to_string())
+                    && self.guids.get(&client_guid).is_some_and(|old_src| *old_src != src.to_string());
+                if rebinding {
+                    // keep the existing session, but with the mtu size negotiated at the new address
+                    let mtu_size = self.clients[&src.to_string()].mtu_size();
+                    debug!("Client {} moved to {}, migrating session", client_guid, src);
+                    if self.migrate_client(client_guid, &src.to_string()) {
+                        let client = self.clients.get_mut(&src.to_string()).unwrap();
+                        client.set_mtu_size(mtu_size);
+                        client.reset_frames();
+                    }
+                }
+                // another client may have taken the last slot since ConnectionRequest1
+                if !self.has_free_connections(&src.to_string()) {
+                    debug!("No free connections, refusing {}", src);
+                    self.remove_client(&src.to_string());
+                    resp = NoFreeIncomingConnections::create(self.server_id).encode(resp.clone());
+                } else if let Some(client) = self.clients.get_mut(&src.to_string()) {
+                    client.set_relationship(packet_bytes.read_address(17));
+                    client.set_connected(true);
+                    resp = ConnectionReply2::create(self.server_id, client.mtu_size(), 0x00).encode(resp.clone());
+                    self.set_client_guid(&src.to_string(), client_guid);
+                } else {
+                    debug!("{} sent ConnectionRequest2 without ConnectionRequest1, ignoring", src);
+                }
             }
             _ => {
                 warn!(
@@ -66,7 +128,130 @@ impl Handler for Server {
             }
         }
         if resp.len() > 0 {
-            self.sock.as_ref().unwrap().send_to(&resp, src).expect("Failed to unwrap");
+            self.send_to(&resp, src);
+        }
+    }
+
+    fn handle_discovery_packet(&mut self, packet_bytes: &[u8], src: SocketAddr) {
+        let first = match packet_bytes.first() {
+            Some(first) => *first,
+            None => return,
+        };
+        let packet_id = PacketId::from(first);
+        if packet_id != PacketId::UnconnectedPing && packet_id != PacketId::UnconnectedPingOpenConnections {
+            trace!("Ignoring 0x{:02x} sent to the discovery socket by {}", first, src);
+            return;
+        }
+        if let Some(pong) = self.answer_ping(packet_bytes, src) {
+            self.send_discovery_to(&pong, src);
+        }
+    }
+}
+
+impl Server {
+    /// Handles a datagram of a connected client: acknowledges its frame sets and handles the
+    /// packets in them
+    fn handle_datagram(&mut self, packet_bytes: &[u8], src: SocketAddr) {
+        let client = match self.clients.get_mut(&src.to_string()) {
+            Some(client) if client.is_connected() => client,
+            _ => {
+                debug!("Received datagram from unconnected {}, ignoring", src);
+                return;
+            }
+        };
+        let packet_type = PacketType::from_u8(packet_bytes[0]);
+        if packet_type.is_ack || packet_type.is_nak {
+            let resends = match Acknowledgement::decode(packet_bytes) {
+                Ok(acknowledgement) => client.acknowledge(&acknowledgement),
+                Err(e) => {
+                    debug!("Dropping acknowledgement from {}: {}", src, e);
+                    return;
+                }
+            };
+            self.statistics.frames_resent += resends.len() as u64;
+            for datagram in resends {
+                self.send_to(&datagram, src);
+            }
+            return;
+        }
+        let packets = match client.receive(packet_bytes) {
+            Ok(packets) => packets,
+            Err(e) => {
+                debug!("Dropping datagram from {}: {}", src, e);
+                return;
+            }
+        };
+        for acknowledgement in client.take_acknowledgements() {
+            self.send_to(&acknowledgement, src);
+        }
+        for packet in packets {
+            self.handle_connected_packet(&packet, src);
+            if !self.clients.contains_key(&src.to_string()) {
+                return;
+            }
+        }
+    }
+
+    /// Handles a packet taken out of the frames of a connected client
+    fn handle_connected_packet(&mut self, packet_bytes: &[u8], src: SocketAddr) {
+        let id = match packet_bytes.first() {
+            Some(id) => *id,
+            None => return,
+        };
+        trace!("Received connected 0x{:02x} ({:?}) from {}", id, PacketId::from(id), src);
+        match PacketId::from(id) {
+            PacketId::ConnectedPing => {
+                let ping = match ConnectedPing::decode(packet_bytes) {
+                    Ok(ping) => ping,
+                    Err(e) => {
+                        debug!("Dropping connected ping from {}: {}", src, e);
+                        return;
+                    }
+                };
+                let pong = ConnectedPong::create(ping.time(), self.time()).encode(Vec::new());
+                self.send_encapsulated(&pong, src);
+            }
+            PacketId::ConnectionRequest => {
+                let request = match ConnectionRequest::decode(packet_bytes) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        debug!("Dropping connection request from {}: {}", src, e);
+                        return;
+                    }
+                };
+                debug!("{} requested a connection (guid: {})", src, request.client_guid());
+                let accepted = ConnectionRequestAccepted::create(src, request.time(), self.time()).encode(Vec::new());
+                self.send_encapsulated(&accepted, src);
+            }
+            PacketId::NewIncomingConnection => debug!("{} is connected", src),
+            PacketId::DisconnectionNotification => {
+                debug!("{} disconnected", src);
+                self.remove_client(&src.to_string());
+            }
+            PacketId::GamePacket => self.handle_game_packet(packet_bytes, src),
+            _ => debug!("Could not handle connected packet 0x{:02x} from {}", id, src),
+        }
+    }
+
+    /// Milliseconds since the server has started, the time in pings and pongs
+    fn time(&self) -> u64 {
+        self.start.elapsed().unwrap_or_default().as_millis() as u64
+    }
+
+    /// Creates the pong for an unconnected ping, if it should be answered
+    fn answer_ping(&self, packet_bytes: &[u8], src: SocketAddr) -> Option<Vec<u8>> {
+        let ping = match UnconnectedPing::decode(packet_bytes) {
+            Ok(ping) => ping,
+            Err(e) => {
+                debug!("Dropping ping from {}: {}", src, e);
+                return None;
+            }
+        };
+        if ping.open_connections_only() && !self.has_free_connections(&src.to_string()) {
+            debug!("No free connections, ignoring ping from {}", src);
+            return None;
         }
+        trace!("Ping from {} (client guid: {})", src, ping.client_guid());
+        Some(UnconnectedPong::create(ping.time(), self.server_id, self.advertisement().encode()).encode(Vec::new()))
     }
 }
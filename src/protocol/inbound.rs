@@ -0,0 +1,148 @@
+use crate::protocol::PacketId;
+use crate::utils::buffer::PacketBufferRead;
+use std::io::{Error, ErrorKind};
+
+pub struct UnconnectedPing {
+    packet_id: u8,
+    time: u64,
+    client_guid: u64,
+}
+
+impl UnconnectedPing {
+    pub fn decode(binary: &[u8]) -> Result<UnconnectedPing, Error> {
+        if binary.len() < 33 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("unconnected ping is too short ({} bytes)", binary.len()),
+            ));
+        }
+        if !binary.read_magic(9) {
+            return Err(Error::new(ErrorKind::InvalidData, "unconnected ping has invalid magic"));
+        }
+        Ok(UnconnectedPing {
+            packet_id: binary[0],
+            time: binary.read_u64(1),
+            client_guid: binary.read_u64(25),
+        })
+    }
+
+    /// Whether the client only wants an answer if there are free connections
+    pub fn open_connections_only(&self) -> bool {
+        self.packet_id == PacketId::UnconnectedPingOpenConnections as u8
+    }
+
+    /// Time the client has sent the ping at, echoed back in the pong
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn client_guid(&self) -> u64 {
+        self.client_guid
+    }
+}
+
+/// Ping sent by a connected client to measure the round trip time
+pub struct ConnectedPing {
+    time: u64,
+}
+
+/// First packet a client sends once it has completed the offline handshake
+pub struct ConnectionRequest {
+    client_guid: u64,
+    time: u64,
+    security: bool,
+}
+
+pub struct UnconnectedPong {
+    time: u64,
+    server_id: u64,
+    advertisement: String,
+}
+
+impl ConnectedPing {
+    pub fn decode(binary: &[u8]) -> Result<ConnectedPing, Error> {
+        if binary.len() < 9 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("connected ping is too short ({} bytes)", binary.len()),
+            ));
+        }
+        Ok(ConnectedPing {
+            time: binary.read_u64(1),
+        })
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+}
+
+impl ConnectionRequest {
+    pub fn decode(binary: &[u8]) -> Result<ConnectionRequest, Error> {
+        if binary.len() < 18 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("connection request is too short ({} bytes)", binary.len()),
+            ));
+        }
+        Ok(ConnectionRequest {
+            client_guid: binary.read_u64(1),
+            time: binary.read_u64(9),
+            security: binary.get(17).is_some_and(|security| *security != 0),
+        })
+    }
+
+    pub fn client_guid(&self) -> u64 {
+        self.client_guid
+    }
+
+    /// Time the client has sent the request at, echoed back in the reply
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    /// Whether the client wants a secured connection, which Minecraft never does
+    pub fn security(&self) -> bool {
+        self.security
+    }
+}
+
+impl UnconnectedPong {
+    pub fn decode(binary: &[u8]) -> Result<UnconnectedPong, Error> {
+        if binary.len() < 35 {
+            return Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                format!("unconnected pong is too short ({} bytes)", binary.len()),
+            ));
+        }
+        if binary[0] != PacketId::UnconnectedPong as u8 {
+            return Err(Error::new(ErrorKind::InvalidData, format!("0x{:02x} is not an unconnected pong", binary[0])));
+        }
+        if !binary.read_magic(17) {
+            return Err(Error::new(ErrorKind::InvalidData, "unconnected pong has invalid magic"));
+        }
+        let advertisement_len = binary.read_u16(33) as usize;
+        if binary.len() < 35 + advertisement_len {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "unconnected pong advertisement is truncated"));
+        }
+        Ok(UnconnectedPong {
+            time: binary.read_u64(1),
+            server_id: binary.read_u64(9),
+            advertisement: String::from_utf8(binary[35..35 + advertisement_len].to_vec())
+                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?,
+        })
+    }
+
+    /// Time of the ping this pong answers
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn server_id(&self) -> u64 {
+        self.server_id
+    }
+
+    pub fn advertisement(&self) -> &str {
+        &self.advertisement
+    }
+}
@@ -1,27 +1,100 @@
+pub mod advertisement;
 pub mod client;
+pub mod frame;
 pub mod handler;
+pub mod inbound;
 pub mod packet;
+pub mod ping;
 pub mod outbound;
+pub mod statistics;
+
+use std::time::Duration;
 
 pub const MAGIC: [u8; 16] = [
     0x00, 0xff, 0xff, 0x0, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
 ];
 
-//pub const ADDRESS_COUNT: u8 = 10; //Minecraft uses 20
+/// Port clients broadcast their pings to when looking for LAN games
+pub const LAN_DISCOVERY_PORT: u16 = 19132;
+
+/// Sessions which have not completed the offline handshake by then are dropped
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time a closed session is kept so the last packets sent to it can arrive
+pub const CLOSE_DELAY: Duration = Duration::from_secs(2);
+/// Sessions which have not acknowledged a datagram for this long are dropped
+pub const SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Internal addresses sent in ConnectionRequestAccepted, RakNet uses 10 but Minecraft 20
+pub const ADDRESS_COUNT: usize = 20;
+
+/// What to do when a client starts a new handshake while it still has a connected session
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum ReconnectPolicy {
+    /// Reply with AlreadyConnected and keep the existing session
+    Refuse,
+    /// Drop the existing session and let the client connect again
+    Replace,
+}
 
 pub struct RakNetSettings {
     version: u8,
-    address: String
+    address: String,
+    max_connections: usize,
+    max_handshakes: usize,
+    reconnect_policy: ReconnectPolicy,
+    lan_discovery: bool,
 }
 
 impl RakNetSettings {
     pub fn new(version: u8, address: String) -> RakNetSettings {
         RakNetSettings {
             version,
-            address
+            address,
+            max_connections: 20,
+            max_handshakes: 20,
+            reconnect_policy: ReconnectPolicy::Replace,
+            lan_discovery: true,
         }
     }
 
+    /// Sets the amount of clients allowed to be connected at the same time
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = max_connections;
+    }
+
+    pub fn get_max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// Sets the amount of clients allowed to be in the offline handshake at the same time, which
+    /// don't count toward the connection limit until they complete it
+    pub fn set_max_handshakes(&mut self, max_handshakes: usize) {
+        self.max_handshakes = max_handshakes;
+    }
+
+    pub fn get_max_handshakes(&self) -> usize {
+        self.max_handshakes
+    }
+
+    /// Sets how reconnects from an already connected address are handled
+    pub fn set_reconnect_policy(&mut self, reconnect_policy: ReconnectPolicy) {
+        self.reconnect_policy = reconnect_policy;
+    }
+
+    pub fn get_reconnect_policy(&self) -> ReconnectPolicy {
+        self.reconnect_policy
+    }
+
+    /// Sets whether pings broadcast to the LAN discovery port are answered on all interfaces,
+    /// even if the server is bound to a specific address or port
+    pub fn set_lan_discovery(&mut self, lan_discovery: bool) {
+        self.lan_discovery = lan_discovery;
+    }
+
+    pub fn get_lan_discovery(&self) -> bool {
+        self.lan_discovery
+    }
+
     pub fn get_version(&self) -> u8 {
         self.version
     }
@@ -50,6 +123,7 @@ pub enum PacketId {
     ConnectedPong = 0x03,
 
     UnconnectedPing = 0x01,
+    UnconnectedPingOpenConnections = 0x02,
     UnconnectedPong = 0x1c,
 
     ConnectionRequest1 = 0x05,
@@ -58,8 +132,14 @@ pub enum PacketId {
     ConnectionReply2 = 0x08,
     ConnectionRequest = 0x09,
     ConnectionRequestAccepted = 0x10,
+    NewIncomingConnection = 0x13,
 
+    AlreadyConnected = 0x12,
+    NoFreeIncomingConnections = 0x14,
+    DisconnectionNotification = 0x15,
     IncompatibleProtocolVersion = 0x19,
+
+    GamePacket = 0xfe,
 }
 
 impl From<u8> for PacketId {
@@ -69,6 +149,7 @@ impl From<u8> for PacketId {
             0x03 => PacketId::ConnectedPong,
 
             0x01 => PacketId::UnconnectedPing,
+            0x02 => PacketId::UnconnectedPingOpenConnections,
             0x1c => PacketId::UnconnectedPong,
 
             0x05 => PacketId::ConnectionRequest1,
@@ -77,8 +158,14 @@ impl From<u8> for PacketId {
             0x08 => PacketId::ConnectionReply2,
             0x09 => PacketId::ConnectionRequest,
             0x10 => PacketId::ConnectionRequestAccepted,
+            0x13 => PacketId::NewIncomingConnection,
 
+            0x12 => PacketId::AlreadyConnected,
+            0x14 => PacketId::NoFreeIncomingConnections,
+            0x15 => PacketId::DisconnectionNotification,
             0x19 => PacketId::IncompatibleProtocolVersion,
+
+            0xfe => PacketId::GamePacket,
             _ => PacketId::Unknown,
         }
     }
@@ -1,10 +1,18 @@
-use crate::protocol::PacketId;
+use crate::protocol::{PacketId, ADDRESS_COUNT};
 use crate::utils::buffer::PacketBufferWrite;
+use std::net::{Ipv4Addr, SocketAddr};
+
+pub struct UnconnectedPing {
+    packet_id: u8,
+    time: u64,
+    client_guid: u64,
+}
 
 pub struct UnconnectedPong {
     packet_id: u8,
-    timestamp: u128,
+    timestamp: u64,
     server_id: u64,
+    advertisement: String,
 }
 
 pub struct ConnectionReply1 {
@@ -21,40 +29,77 @@ pub struct ConnectionReply2 {
     server_security: u8,
 }
 
+pub struct ConnectedPong {
+    packet_id: u8,
+    ping_time: u64,
+    pong_time: u64,
+}
+
+/// Accepts the ConnectionRequest of a client, after which it may send game packets
+pub struct ConnectionRequestAccepted {
+    packet_id: u8,
+    client_address: SocketAddr,
+    request_time: u64,
+    time: u64,
+}
+
 pub struct IncompatibleProtocolVersion {
     packet_id: u8,
     raknet_version: u8,
     server_id: u64,
 }
 
+pub struct AlreadyConnected {
+    packet_id: u8,
+    server_id: u64,
+}
+
+/// Tells a connected client its session has been closed
+pub struct DisconnectionNotification {
+    packet_id: u8,
+}
+
+pub struct NoFreeIncomingConnections {
+    packet_id: u8,
+    server_id: u64,
+}
+
+impl UnconnectedPing {
+    pub fn create(time: u64, client_guid: u64) -> UnconnectedPing {
+        UnconnectedPing {
+            packet_id: PacketId::UnconnectedPing as u8,
+            time,
+            client_guid,
+        }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push(self.packet_id);
+        binary.push_u64(self.time);
+        binary.push_magic();
+        binary.push_u64(self.client_guid);
+
+        binary
+    }
+}
+
 impl UnconnectedPong {
-    pub fn create(timestamp: u128, server_id: u64) -> UnconnectedPong {
+    pub fn create(timestamp: u64, server_id: u64, advertisement: String) -> UnconnectedPong {
         UnconnectedPong {
             packet_id: PacketId::UnconnectedPong as u8,
             timestamp,
             server_id,
+            advertisement,
         }
     }
 
+    #[allow(clippy::needless_return)]
     pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
         binary.push(self.packet_id);
-        binary.push_u64(self.timestamp as u64);
+        binary.push_u64(self.timestamp);
         binary.push_u64(self.server_id);
         binary.push_magic();
-        binary.push_string(format!(
-            "MCPE;{};{};{};{};{};{};{};{};{};{};{};",
-            "Limonite",     // motd
-            408,            // protocol
-            "1.16.20",      // version
-            0,              // online players
-            20,             // max players
-            self.server_id, // server_id
-            "world",        // world name
-            "Survival",     // gamemode
-            1,              // is limited to switch
-            19132,          // ipv4 port
-            19133,          // ipv6 port
-        )).expect("Failed to push string");
+        binary.push_string(self.advertisement.clone()).expect("Failed to push string");
 
         return binary;
     }
@@ -70,6 +115,7 @@ impl ConnectionReply1 {
         }
     }
 
+    #[allow(clippy::needless_return)]
     pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
         binary.push(self.packet_id);
         binary.push_magic();
@@ -91,6 +137,7 @@ impl ConnectionReply2 {
         }
     }
 
+    #[allow(clippy::needless_return)]
     pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
         binary.push(self.packet_id);
         binary.push_magic();
@@ -102,6 +149,48 @@ impl ConnectionReply2 {
     }
 }
 
+impl ConnectedPong {
+    pub fn create(ping_time: u64, pong_time: u64) -> ConnectedPong {
+        ConnectedPong {
+            packet_id: PacketId::ConnectedPong as u8,
+            ping_time,
+            pong_time,
+        }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push(self.packet_id);
+        binary.push_u64(self.ping_time);
+        binary.push_u64(self.pong_time);
+
+        binary
+    }
+}
+
+impl ConnectionRequestAccepted {
+    pub fn create(client_address: SocketAddr, request_time: u64, time: u64) -> ConnectionRequestAccepted {
+        ConnectionRequestAccepted {
+            packet_id: PacketId::ConnectionRequestAccepted as u8,
+            client_address,
+            request_time,
+            time,
+        }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push(self.packet_id);
+        binary.push_address(self.client_address);
+        binary.push_u16(0); // system index
+        for _ in 0..ADDRESS_COUNT {
+            binary.push_address(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)));
+        }
+        binary.push_u64(self.request_time);
+        binary.push_u64(self.time);
+
+        binary
+    }
+}
+
 impl IncompatibleProtocolVersion {
     pub fn create(raknet_version: u8, server_id: u64) -> IncompatibleProtocolVersion {
         IncompatibleProtocolVersion {
@@ -111,6 +200,7 @@ impl IncompatibleProtocolVersion {
         }
     }
 
+    #[allow(clippy::needless_return)]
     pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
         binary.push(self.packet_id);
         binary.push(self.raknet_version);
@@ -120,3 +210,51 @@ impl IncompatibleProtocolVersion {
         return binary;
     }
 }
+
+impl NoFreeIncomingConnections {
+    pub fn create(server_id: u64) -> NoFreeIncomingConnections {
+        NoFreeIncomingConnections {
+            packet_id: PacketId::NoFreeIncomingConnections as u8,
+            server_id,
+        }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push(self.packet_id);
+        binary.push_magic();
+        binary.push_u64(self.server_id);
+
+        binary
+    }
+}
+
+impl AlreadyConnected {
+    pub fn create(server_id: u64) -> AlreadyConnected {
+        AlreadyConnected {
+            packet_id: PacketId::AlreadyConnected as u8,
+            server_id,
+        }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push(self.packet_id);
+        binary.push_magic();
+        binary.push_u64(self.server_id);
+
+        binary
+    }
+}
+
+impl DisconnectionNotification {
+    pub fn create() -> DisconnectionNotification {
+        DisconnectionNotification {
+            packet_id: PacketId::DisconnectionNotification as u8,
+        }
+    }
+
+    pub fn encode(&self, mut binary: Vec<u8>) -> Vec<u8> {
+        binary.push(self.packet_id);
+
+        binary
+    }
+}
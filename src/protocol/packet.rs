@@ -97,23 +97,32 @@ impl PacketType {
 }
 
 impl PacketInfo {
+    /// Reads the id of a datagram, which is None if the datagram is too short to have one
     pub fn from_bytes(bytes: &[u8]) -> Self {
-        let packet_header = PacketType::from_u8(bytes[0]);
+        let first = match bytes.first() {
+            Some(first) => *first,
+            None => {
+                return PacketInfo {
+                    packet_id: None,
+                    encapsulated: false,
+                }
+            }
+        };
+        let packet_header = PacketType::from_u8(first);
         let mut encapsulated = false;
         let mut packet_id = Some(PacketId::Unknown);
         // good enough i guess
-        if PacketId::from(bytes[0]) == PacketId::Unknown {
+        if PacketId::from(first) == PacketId::Unknown {
             encapsulated = true;
-            if !packet_header.is_ack || !packet_header.is_nak {
-                let packet_flags = PacketFlags::from_u8(bytes[4]).unwrap();
-                packet_id = Some(if packet_flags.reliability != Reliability::Unreliable {
-                    PacketId::from(bytes[10])
-                } else {
-                    PacketId::from(bytes[7])
+            if !packet_header.is_ack && !packet_header.is_nak {
+                let packet_flags = bytes.get(4).and_then(|flags| PacketFlags::from_u8(*flags).ok());
+                packet_id = packet_flags.and_then(|packet_flags| {
+                    let id_offset = if packet_flags.reliability != Reliability::Unreliable { 10 } else { 7 };
+                    bytes.get(id_offset).map(|id| PacketId::from(*id))
                 });
             }
         } else {
-            packet_id = Some(PacketId::from(bytes[0]));
+            packet_id = Some(PacketId::from(first));
         }
         PacketInfo {
             packet_id,
@@ -0,0 +1,94 @@
+use crate::protocol::advertisement::ServerAdvertisement;
+use crate::protocol::inbound::UnconnectedPong;
+use crate::protocol::outbound::UnconnectedPing;
+use rand::random;
+use std::io::{Error, ErrorKind};
+use crate::protocol::LAN_DISCOVERY_PORT;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Status of a server as reported by its unconnected pong
+#[derive(Clone, Debug)]
+pub struct ServerStatus {
+    pub address: SocketAddr,
+    pub latency: Duration,
+    pub advertisement: ServerAdvertisement,
+}
+
+/// Sends an unconnected ping to `address` and waits up to `timeout` for the pong
+pub fn ping<A: ToSocketAddrs>(address: A, timeout: Duration) -> Result<ServerStatus, Error> {
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "address did not resolve"))?;
+    let bind_address = if address.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" };
+    let sock = UdpSocket::bind(bind_address)?;
+    sock.connect(address)?;
+
+    let start = Instant::now();
+    sock.send(&UnconnectedPing::create(ping_time(), random::<u64>()).encode(Vec::new()))?;
+    match recv_pong(&sock, start, timeout)? {
+        Some(status) => Ok(status),
+        None => Err(Error::new(ErrorKind::TimedOut, format!("{} did not answer", address))),
+    }
+}
+
+/// Broadcasts an unconnected ping on the local network and collects all pongs received within `timeout`
+pub fn discover(timeout: Duration) -> Result<Vec<ServerStatus>, Error> {
+    let sock = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    sock.set_broadcast(true)?;
+
+    let start = Instant::now();
+    let ping = UnconnectedPing::create(ping_time(), random::<u64>()).encode(Vec::new());
+    sock.send_to(&ping, (Ipv4Addr::BROADCAST, LAN_DISCOVERY_PORT))?;
+    let mut servers: Vec<ServerStatus> = Vec::new();
+    while let Some(status) = recv_pong(&sock, start, timeout)? {
+        // servers bound to all interfaces may answer more than once
+        if !servers.iter().any(|server| server.advertisement.server_id == status.advertisement.server_id) {
+            servers.push(status);
+        }
+    }
+    Ok(servers)
+}
+
+/// Waits for the next valid pong until `timeout` has passed since `start`
+fn recv_pong(sock: &UdpSocket, start: Instant, timeout: Duration) -> Result<Option<ServerStatus>, Error> {
+    let mut buff: [u8; 2048] = [0; 2048];
+    loop {
+        let remaining = match timeout.checked_sub(start.elapsed()) {
+            Some(remaining) if !remaining.is_zero() => remaining,
+            _ => return Ok(None),
+        };
+        sock.set_read_timeout(Some(remaining))?;
+        let (len, src) = match sock.recv_from(&mut buff) {
+            Ok(received) => received,
+            Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        match parse_pong(&buff[0..len], src, start.elapsed()) {
+            Ok(status) => return Ok(Some(status)),
+            Err(e) => debug!("Ignoring packet from {}: {}", src, e),
+        }
+    }
+}
+
+fn parse_pong(packet: &[u8], address: SocketAddr, latency: Duration) -> Result<ServerStatus, Error> {
+    let pong = UnconnectedPong::decode(packet)?;
+    let mut advertisement: ServerAdvertisement = pong.advertisement().parse()?;
+    if advertisement.server_id == 0 {
+        advertisement.server_id = pong.server_id();
+    }
+    Ok(ServerStatus {
+        address,
+        latency,
+        advertisement,
+    })
+}
+
+/// Milliseconds since the unix epoch, sent as the ping time
+fn ping_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
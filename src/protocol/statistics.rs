@@ -0,0 +1,67 @@
+use std::fmt;
+use std::time::Duration;
+
+/// Network statistics of a single connection or of the whole server
+#[derive(Clone, Debug, Default)]
+pub struct RakNetStatistics {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub datagrams_sent: u64,
+    pub datagrams_received: u64,
+    pub frames_resent: u64,
+    pub naks_received: u64,
+    /// round trip time, unknown until the first datagram was acknowledged
+    pub rtt: Option<Duration>,
+    pub bytes_in_flight: u64,
+    /// split packets which are still missing parts
+    pub split_reassemblies: u64,
+    pub uptime: Duration,
+}
+
+impl RakNetStatistics {
+    pub fn record_sent(&mut self, len: usize) {
+        self.bytes_sent += len as u64;
+        self.datagrams_sent += 1;
+    }
+
+    pub fn record_received(&mut self, len: usize, is_nak: bool) {
+        self.bytes_received += len as u64;
+        self.datagrams_received += 1;
+        if is_nak {
+            self.naks_received += 1;
+        }
+    }
+
+    /// Share of sent frames which were lost and had to be resent, between 0 and 1
+    pub fn estimated_loss(&self) -> f64 {
+        if self.datagrams_sent == 0 {
+            return 0.0;
+        }
+        (self.frames_resent as f64 / self.datagrams_sent as f64).min(1.0)
+    }
+}
+
+impl fmt::Display for RakNetStatistics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rtt = match self.rtt {
+            Some(rtt) => format!("{}ms", rtt.as_millis()),
+            None => "n/a".to_string(),
+        };
+        write!(
+            f,
+            "sent: {} bytes / {} datagrams, received: {} bytes / {} datagrams, resent: {} frames, \
+            naks: {}, loss: {:.2}%, rtt: {}, in flight: {} bytes, split reassemblies: {}, uptime: {}s",
+            self.bytes_sent,
+            self.datagrams_sent,
+            self.bytes_received,
+            self.datagrams_received,
+            self.frames_resent,
+            self.naks_received,
+            self.estimated_loss() * 100.0,
+            rtt,
+            self.bytes_in_flight,
+            self.split_reassemblies,
+            self.uptime.as_secs(),
+        )
+    }
+}
@@ -1,45 +1,552 @@
+use crate::bedrock::batch::Batch;
+use crate::bedrock::packets::{Disconnect, Packet};
+use crate::bedrock::registry::PacketRegistry;
+use crate::bedrock::resource_packs::ResourcePacks;
+use crate::bedrock::BedrockSettings;
 use crate::protocol::client::Client;
-use std::net::{UdpSocket};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
 
+use crate::console::CommandHandler;
 use crate::protocol::handler::Handler;
+use crate::protocol::packet::PacketType;
+use crate::protocol::advertisement::ServerAdvertisement;
+use crate::protocol::outbound::DisconnectionNotification;
+use crate::protocol::statistics::RakNetStatistics;
 use rand::random;
 use std::collections::HashMap;
-use std::time::SystemTime;
-use crate::protocol::RakNetSettings;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+use std::time::{Duration, SystemTime};
+use crate::protocol::{RakNetSettings, LAN_DISCOVERY_PORT};
+use crate::world::block::BlockRegistry;
+use crate::world::generator::{self, Generator};
+use crate::world::loader::ChunkLoader;
+use crate::world::WorldSettings;
 
 pub struct Server {
     /// unique server id
     pub server_id: u64,
     /// clients connected to the server <ip:port, Client>
     pub clients: HashMap<String, Client>,
+    /// addresses of clients by the guid they sent <guid, ip:port>
+    pub guids: HashMap<u64, String>,
     /// raknet settings
     pub raknet_settings: RakNetSettings,
+    /// settings of the game protocol
+    pub bedrock_settings: BedrockSettings,
     /// time server has started
     pub start: SystemTime,
     /// Server socket
     pub sock: Option<UdpSocket>,
+    /// Socket answering broadcast pings if the server socket does not receive them
+    pub discovery_sock: Option<UdpSocket>,
+    /// statistics of all traffic the server has sent and received
+    pub statistics: RakNetStatistics,
+    /// commands entered in the console
+    pub console: Option<Receiver<String>>,
+    /// packs sent to clients after login
+    pub resource_packs: ResourcePacks,
+    /// block states of the supported versions
+    pub blocks: BlockRegistry,
+    /// settings of the world players spawn in
+    pub world_settings: WorldSettings,
+    /// generator of the chunks sent to players, created from the world settings when the server
+    /// starts unless one is set
+    pub generator: Option<Arc<dyn Generator>>,
+    /// generates the chunks on worker threads, started with the generator
+    pub chunk_loader: Option<ChunkLoader>,
+    /// runtime id the next entity gets
+    pub next_entity_id: u64,
 }
 
 impl Server {
     pub fn new(raknet_settings: RakNetSettings) -> Server {
+        let server_id = random::<u64>();
+        let bedrock_settings = BedrockSettings::new();
+        let blocks = BlockRegistry::new(bedrock_settings.get_newest_protocol().unwrap_or_default());
         Server {
-            server_id: random::<u64>(),
+            server_id,
             clients: HashMap::default(),
+            guids: HashMap::default(),
             raknet_settings,
+            bedrock_settings,
             start: SystemTime::now(),
             sock: None,
+            discovery_sock: None,
+            statistics: RakNetStatistics::default(),
+            console: None,
+            resource_packs: ResourcePacks::new(),
+            blocks,
+            world_settings: WorldSettings::default(),
+            generator: None,
+            chunk_loader: None,
+            next_entity_id: 1,
         }
     }
 
+    #[allow(clippy::expect_fun_call)]
     pub fn start(&mut self) {
         let mut buff: [u8; 2048] = [0; 2048];
         self.sock = Some(
             UdpSocket::bind(self.raknet_settings.get_address())
                 .expect(format!("Failed to bind to port {}", self.raknet_settings.get_port()).as_str()),
         );
+        // wake up regularly to process console commands
+        self.sock.as_ref().unwrap().set_read_timeout(Some(Duration::from_millis(50))).expect("Failed to set socket timeout");
+        if self.raknet_settings.get_lan_discovery() {
+            self.bind_discovery_sock();
+        }
+        self.load_resource_packs();
+        self.load_blocks();
+        self.chunks();
         loop {
-            let (len, src) = self.sock.as_ref().unwrap().recv_from(&mut buff).unwrap();
-            self.handle_packet(&buff[0..len], src);
+            match self.sock.as_ref().unwrap().recv_from(&mut buff) {
+                Ok((len, src)) => {
+                    self.record_received(&buff[0..len], src);
+                    self.handle_packet(&buff[0..len], src);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(e) => error!("Failed to receive packet: {}", e),
+            }
+            while let Some(Ok((len, src))) = self.discovery_sock.as_ref().map(|sock| sock.recv_from(&mut buff)) {
+                self.statistics.record_received(len, false);
+                self.handle_discovery_packet(&buff[0..len], src);
+            }
+            let commands: Vec<String> = match &self.console {
+                Some(console) => console.try_iter().collect(),
+                None => Vec::new(),
+            };
+            for command in commands {
+                self.handle_command(&command);
+            }
+            self.drop_expired_handshakes();
+            self.resend_unacknowledged();
+            self.drop_closed_sessions();
+            self.send_generated_chunks();
+        }
+    }
+
+    /// Sends `packet` to `dst` and counts it in the statistics
+    pub fn send_to(&mut self, packet: &[u8], dst: SocketAddr) {
+        if let Err(e) = self.sock.as_ref().unwrap().send_to(packet, dst) {
+            error!("Failed to send packet to {}: {}", dst, e);
+            return;
+        }
+        self.statistics.record_sent(packet.len());
+        if let Some(client) = self.clients.get_mut(&dst.to_string()) {
+            client.statistics_mut().record_sent(packet.len());
+        }
+    }
+
+    /// Sends `packet` to the client at `dst` in reliable ordered frames
+    pub fn send_encapsulated(&mut self, packet: &[u8], dst: SocketAddr) {
+        let datagrams = match self.clients.get_mut(&dst.to_string()) {
+            Some(client) => client.encapsulate(packet),
+            None => return,
+        };
+        for datagram in datagrams {
+            self.send_to(&datagram, dst);
+        }
+    }
+
+    /// Encodes `packets` with the codecs of the protocol version of the client at `dst` and sends
+    /// them in one game packet
+    pub fn send_game_packets(&mut self, packets: Vec<Packet>, dst: SocketAddr) {
+        if let Some(game_packet) = self.encode_game_packets(packets, dst) {
+            self.send_encapsulated(&game_packet, dst);
+        }
+    }
+
+    /// Encodes `packets` into one game packet for the client at `dst`
+    fn encode_game_packets(&mut self, packets: Vec<Packet>, dst: SocketAddr) -> Option<Vec<u8>> {
+        let client = self.clients.get_mut(&dst.to_string())?;
+        let game_packet = client.registry().and_then(|registry| {
+            let packets = packets.iter().map(|packet| registry.encode(packet)).collect::<Result<Vec<_>, _>>()?;
+            client.encode_game_packet(&Batch::new(packets))
+        });
+        match game_packet {
+            Ok(game_packet) => Some(game_packet),
+            Err(e) => {
+                error!("Failed to encode game packet for {}: {}", dst, e);
+                None
+            }
+        }
+    }
+
+    /// Shows `reason` to the client at `dst` and closes its session
+    pub fn kick(&mut self, dst: SocketAddr, reason: &str) {
+        let has_protocol = match self.clients.get(&dst.to_string()) {
+            Some(client) => client.protocol().is_some(),
+            None => return,
+        };
+        info!("Kicking {}: {}", dst, reason);
+        if has_protocol {
+            let disconnect = Packet::Disconnect(Disconnect::create(reason.to_string()));
+            self.send_game_packets(vec![disconnect], dst);
+        }
+        self.disconnect(dst);
+    }
+
+    /// Closes the session of the client at `dst`. It is dropped after `CLOSE_DELAY`, so the
+    /// packets sent before have time to arrive.
+    pub fn disconnect(&mut self, dst: SocketAddr) {
+        if let Some(client) = self.clients.get_mut(&dst.to_string()) {
+            client.close();
+        }
+    }
+
+    /// Resends the datagrams clients have not acknowledged in time and drops the sessions which
+    /// stopped acknowledging them
+    fn resend_unacknowledged(&mut self) {
+        let mut resends = Vec::new();
+        let mut timed_out = Vec::new();
+        for (addr, client) in self.clients.iter_mut() {
+            if client.is_timed_out() {
+                timed_out.push(addr.clone());
+            } else {
+                resends.push((addr.clone(), client.resend_expired()));
+            }
+        }
+        for (addr, datagrams) in resends {
+            self.statistics.frames_resent += datagrams.len() as u64;
+            if let Ok(dst) = addr.parse() {
+                for datagram in &datagrams {
+                    self.send_to(datagram, dst);
+                }
+            }
+        }
+        for addr in timed_out {
+            info!("{} timed out", addr);
+            self.remove_client(&addr);
+        }
+    }
+
+    /// Drops the sessions which were closed long enough ago, telling them their session is closed
+    fn drop_closed_sessions(&mut self) {
+        let expired: Vec<String> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.is_close_expired())
+            .map(|(addr, _)| addr.clone())
+            .collect();
+        for addr in expired {
+            if let Ok(dst) = addr.parse() {
+                let notification = DisconnectionNotification::create().encode(Vec::new());
+                self.send_encapsulated(&notification, dst);
+            }
+            self.remove_client(&addr);
+        }
+    }
+
+    /// Sends `packet` to `dst` from the LAN discovery socket
+    pub fn send_discovery_to(&mut self, packet: &[u8], dst: SocketAddr) {
+        if let Some(sock) = &self.discovery_sock {
+            match sock.send_to(packet, dst) {
+                Ok(_) => self.statistics.record_sent(packet.len()),
+                Err(e) => error!("Failed to send packet to {} from the discovery socket: {}", dst, e),
+            }
+        }
+    }
+
+    /// Listens for broadcast pings on all interfaces, unless the server socket already does
+    fn bind_discovery_sock(&mut self) {
+        let local_addr = self.sock.as_ref().unwrap().local_addr().expect("Failed to get local address");
+        if local_addr.ip().is_unspecified() && local_addr.port() == LAN_DISCOVERY_PORT {
+            return;
+        }
+        match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, LAN_DISCOVERY_PORT)) {
+            Ok(sock) => {
+                sock.set_nonblocking(true).expect("Failed to set discovery socket non-blocking");
+                info!("Answering LAN discovery pings on port {}", LAN_DISCOVERY_PORT);
+                self.discovery_sock = Some(sock);
+            }
+            Err(e) => warn!("Failed to bind LAN discovery socket to port {}: {}", LAN_DISCOVERY_PORT, e),
+        }
+    }
+
+    /// Loads the packs from the configured directory, if there is one
+    fn load_resource_packs(&mut self) {
+        let directory = match self.bedrock_settings.get_resource_pack_directory() {
+            Some(directory) => Path::new(directory),
+            None => return,
+        };
+        if !directory.is_dir() {
+            debug!("Resource pack directory {} does not exist", directory.display());
+            return;
+        }
+        match ResourcePacks::load(directory) {
+            Ok(resource_packs) => self.resource_packs = resource_packs,
+            Err(e) => error!("Failed to load resource packs from {}: {}", directory.display(), e),
+        }
+    }
+
+    /// Loads the block states from the configured directory, without one chunks only consist of air
+    fn load_blocks(&mut self) {
+        let protocols: Vec<u32> = self
+            .bedrock_settings
+            .get_protocols()
+            .iter()
+            .copied()
+            .filter(|protocol| self.bedrock_settings.is_supported(*protocol))
+            .collect();
+        self.blocks = match self.bedrock_settings.get_block_state_directory() {
+            Some(directory) => BlockRegistry::load(Path::new(directory), &protocols),
+            None => BlockRegistry::new(self.bedrock_settings.get_newest_protocol().unwrap_or_default()),
+        };
+    }
+
+    /// Creates the configured generator, or the fallback one if that fails
+    fn create_generator(&self) -> Arc<dyn Generator> {
+        let name = self.world_settings.get_generator();
+        let options = self.world_settings.get_generator_options();
+        match generator::create(name, options, self.world_settings.get_seed(), &self.blocks) {
+            Ok(generator) => {
+                info!("Generating chunks with the {} generator", name);
+                Arc::from(generator)
+            }
+            Err(e) => {
+                error!("Failed to create the {} generator: {}", name, e);
+                Arc::from(generator::fallback(&self.blocks))
+            }
+        }
+    }
+
+    /// Chunks of the world, the generator is started on first use
+    pub fn chunks(&mut self) -> &mut ChunkLoader {
+        if self.chunk_loader.is_none() {
+            if self.generator.is_none() {
+                self.generator = Some(self.create_generator());
+            }
+            let generator = self.generator.clone().unwrap();
+            self.chunk_loader = Some(ChunkLoader::new(generator, self.blocks.air(), self.world_settings.get_generator_workers()));
+        }
+        self.chunk_loader.as_mut().unwrap()
+    }
+
+    /// Block players spawn in, as set in the world settings or chosen by the generator
+    pub fn spawn(&self) -> (i32, i32, i32) {
+        self.world_settings
+            .get_spawn()
+            .or_else(|| self.generator.as_ref().map(|generator| generator.spawn()))
+            .unwrap_or((0, 64, 0))
+    }
+
+    fn record_received(&mut self, packet: &[u8], src: SocketAddr) {
+        let is_nak = packet.first().is_some_and(|id| {
+            let packet_type = PacketType::from_u8(*id);
+            packet_type.is_connected_to_peer && packet_type.is_nak
+        });
+        self.statistics.record_received(packet.len(), is_nak);
+        if let Some(client) = self.clients.get_mut(&src.to_string()) {
+            client.statistics_mut().record_received(packet.len(), is_nak);
+        }
+    }
+
+    /// Information shown in the server list, built from the settings and the current player count
+    pub fn advertisement(&self) -> ServerAdvertisement {
+        let port = self.raknet_settings.get_port().parse().unwrap_or(19132);
+        let mut advertisement = ServerAdvertisement::new(self.server_id, port);
+        advertisement.motd = self.bedrock_settings.get_motd().to_string();
+        advertisement.online_players = self
+            .clients
+            .values()
+            .filter(|client| client.is_logged_in() && !client.is_closing())
+            .count();
+        advertisement.max_players = self.bedrock_settings.get_max_players();
+        advertisement.world_name = self.world_settings.get_name().to_string();
+        advertisement.gamemode = self.world_settings.get_gamemode().name().to_string();
+        if let Some(registry) = self.bedrock_settings.get_newest_protocol().and_then(PacketRegistry::for_protocol) {
+            advertisement.protocol = registry.protocol();
+            advertisement.version = registry.version().to_string();
+        }
+        advertisement
+    }
+
+    /// Snapshot of the statistics of the whole server, including the state of all connections
+    pub fn statistics(&self) -> RakNetStatistics {
+        let mut statistics = self.statistics.clone();
+        statistics.uptime = self.start.elapsed().unwrap_or_default();
+        let mut rtts = Vec::new();
+        for client in self.clients.values() {
+            let client_statistics = client.statistics();
+            statistics.bytes_in_flight += client_statistics.bytes_in_flight;
+            statistics.split_reassemblies += client_statistics.split_reassemblies;
+            rtts.extend(client_statistics.rtt);
+        }
+        if !rtts.is_empty() {
+            statistics.rtt = Some(rtts.iter().sum::<Duration>() / rtts.len() as u32);
+        }
+        statistics
+    }
+
+    /// Drops the session of the client at `src`
+    pub fn remove_client(&mut self, src: &str) -> Option<Client> {
+        let client = self.clients.remove(src)?;
+        if let Some(guid) = client.guid() {
+            if self.guids.get(&guid).is_some_and(|addr| addr == src) {
+                self.guids.remove(&guid);
+            }
+        }
+        Some(client)
+    }
+
+    /// Assigns `guid` to the client at `src` so it can be found again if its address changes
+    pub fn set_client_guid(&mut self, src: &str, guid: u64) {
+        if let Some(client) = self.clients.get_mut(src) {
+            client.set_guid(guid);
+            self.guids.insert(guid, src.to_string());
+        }
+    }
+
+    /// Address of the client which sent `guid` during its handshake
+    pub fn client_by_guid(&self, guid: u64) -> Option<&str> {
+        self.guids.get(&guid).map(|addr| addr.as_str())
+    }
+
+    /// Moves the session of the client with `guid` to `new_src`, e.g. after its NAT mapping changed.
+    /// Any other session at `new_src` is dropped. Returns false if no client uses `guid`.
+    pub fn migrate_client(&mut self, guid: u64, new_src: &str) -> bool {
+        let old_src = match self.guids.get(&guid) {
+            Some(old_src) => old_src.clone(),
+            None => return false,
+        };
+        if old_src == new_src {
+            return true;
+        }
+        let client = match self.clients.remove(&old_src) {
+            Some(client) => client,
+            None => return false,
+        };
+        self.remove_client(new_src);
+        self.clients.insert(new_src.to_string(), client);
+        self.guids.insert(guid, new_src.to_string());
+        true
+    }
+
+    /// Checks if another client can connect, not counting the client at `src`. Sessions still
+    /// in the offline handshake don't take up a connection.
+    pub fn has_free_connections(&self, src: &str) -> bool {
+        let connections = self
+            .clients
+            .iter()
+            .filter(|(addr, client)| *addr != src && client.is_connected())
+            .count();
+        connections < self.raknet_settings.get_max_connections()
+    }
+
+    /// Checks if another client can start the offline handshake, not counting the client at `src`
+    pub fn has_free_handshakes(&self, src: &str) -> bool {
+        let handshakes = self
+            .clients
+            .iter()
+            .filter(|(addr, client)| *addr != src && !client.is_connected())
+            .count();
+        handshakes < self.raknet_settings.get_max_handshakes()
+    }
+
+    /// Drops sessions which started the offline handshake but never completed it
+    fn drop_expired_handshakes(&mut self) {
+        let expired: Vec<String> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| client.is_handshake_expired())
+            .map(|(addr, _)| addr.clone())
+            .collect();
+        for addr in expired {
+            debug!("{} did not complete the handshake in time, dropping it", addr);
+            self.remove_client(&addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{PacketId, MAGIC};
+    use crate::utils::buffer::PacketBufferWrite;
+
+    fn server(raknet_settings: RakNetSettings) -> Server {
+        let mut server = Server::new(raknet_settings);
+        server.sock = Some(UdpSocket::bind("127.0.0.1:0").unwrap());
+        server
+    }
+
+    fn settings() -> RakNetSettings {
+        RakNetSettings::new(10, "127.0.0.1:19132".to_string())
+    }
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    fn connection_request_1() -> Vec<u8> {
+        let mut packet = vec![PacketId::ConnectionRequest1 as u8];
+        packet.extend_from_slice(&MAGIC);
+        packet.push(10);
+        packet.resize(1400, 0);
+        packet
+    }
+
+    fn connection_request_2(guid: u64) -> Vec<u8> {
+        let mut packet = vec![PacketId::ConnectionRequest2 as u8];
+        packet.extend_from_slice(&MAGIC);
+        packet.push_address(address(19132));
+        packet.push_i16(1400);
+        packet.push_u64(guid);
+        packet
+    }
+
+    fn handshake(server: &mut Server, src: SocketAddr, guid: u64) {
+        server.handle_packet(&connection_request_1(), src);
+        server.handle_packet(&connection_request_2(guid), src);
+    }
+
+    #[test]
+    fn guid_index() {
+        let mut server = server(settings());
+        handshake(&mut server, address(1), 42);
+        handshake(&mut server, address(2), 43);
+        assert_eq!(server.client_by_guid(42), Some(address(1).to_string().as_str()));
+        assert_eq!(server.clients[&address(1).to_string()].guid(), Some(42));
+        server.remove_client(&address(2).to_string());
+        assert_eq!(server.client_by_guid(43), None);
+        assert_eq!(server.client_by_guid(42), Some(address(1).to_string().as_str()));
+    }
+
+    #[test]
+    fn sessions_move_with_their_guid() {
+        let mut server = server(settings());
+        handshake(&mut server, address(1), 42);
+        server.clients.get_mut(&address(1).to_string()).unwrap().set_protocol(554);
+        handshake(&mut server, address(2), 42);
+        assert!(!server.clients.contains_key(&address(1).to_string()));
+        let client = &server.clients[&address(2).to_string()];
+        assert_eq!(client.protocol(), Some(554));
+        assert!(client.is_connected());
+        assert_eq!(server.client_by_guid(42), Some(address(2).to_string().as_str()));
+        // other guids get a session of their own
+        handshake(&mut server, address(3), 7);
+        assert_eq!(server.clients.len(), 2);
+        assert_eq!(server.clients[&address(3).to_string()].protocol(), None);
+    }
+
+    #[test]
+    fn half_open_sessions_are_limited() {
+        let mut settings = settings();
+        settings.set_max_handshakes(2);
+        let mut server = server(settings);
+        for port in 1..=3 {
+            server.handle_packet(&connection_request_1(), address(port));
         }
+        assert_eq!(server.clients.len(), 2);
+        assert!(!server.clients.contains_key(&address(3).to_string()));
+        // restarting the handshake doesn't take up another slot
+        server.handle_packet(&connection_request_1(), address(1));
+        assert_eq!(server.clients.len(), 2);
+        server.clients.get_mut(&address(1).to_string()).unwrap().set_connected(true);
+        server.handle_packet(&connection_request_1(), address(3));
+        assert!(server.clients.contains_key(&address(3).to_string()));
     }
 }
@@ -1,5 +1,7 @@
 use crate::protocol::MAGIC;
-use std::convert::TryInto;
+use crate::utils::nbt::{self, Encoding, Limits, Tag};
+use std::convert::{TryFrom, TryInto};
+use std::io::{Error, ErrorKind};
 use std::net::{IpAddr, SocketAddr};
 use std::num::TryFromIntError;
 use std::ops::Deref;
@@ -18,6 +20,32 @@ pub trait PacketBufferRead {
     fn read_i32(&self, start: usize) -> i32;
     fn read_i64(&self, start: usize) -> i64;
     fn read_i128(&self, start: usize) -> i128;
+    fn read_u16_le(&self, start: usize) -> u16;
+    fn read_u24_le(&self, start: usize) -> u32;
+    fn read_u32_le(&self, start: usize) -> u32;
+    fn read_u64_le(&self, start: usize) -> u64;
+    fn read_i16_le(&self, start: usize) -> i16;
+    fn read_i32_le(&self, start: usize) -> i32;
+    fn read_i64_le(&self, start: usize) -> i64;
+    fn read_f32(&self, start: usize) -> f32;
+    fn read_f64(&self, start: usize) -> f64;
+    fn read_f32_le(&self, start: usize) -> f32;
+    fn read_f64_le(&self, start: usize) -> f64;
+    fn read_bool(&self, start: usize) -> bool;
+    /// Returns the value and the amount of bytes it took up
+    fn read_var_u32(&self, start: usize) -> Result<(u32, usize), Error>;
+    /// Reads a zigzag encoded varint
+    fn read_var_i32(&self, start: usize) -> Result<(i32, usize), Error>;
+    fn read_var_u64(&self, start: usize) -> Result<(u64, usize), Error>;
+    /// Reads a zigzag encoded varlong
+    fn read_var_i64(&self, start: usize) -> Result<(i64, usize), Error>;
+    /// Reads bytes prefixed with their length as varint
+    fn read_var_bytes(&self, start: usize) -> Result<(&[u8], usize), Error>;
+    /// Reads a string prefixed with its length as varint
+    fn read_var_string(&self, start: usize) -> Result<(String, usize), Error>;
+    /// Reads a named NBT tag with the default limits, returns its name, the tag and the amount
+    /// of bytes it took up
+    fn read_nbt(&self, start: usize, encoding: Encoding) -> Result<(String, Tag, usize), Error>;
 }
 
 pub trait PacketBufferWrite {
@@ -35,12 +63,36 @@ pub trait PacketBufferWrite {
     fn push_i32(&mut self, num: i32);
     fn push_i64(&mut self, num: i64);
     fn push_i128(&mut self, num: i128);
+    fn push_u16_le(&mut self, num: u16);
+    fn push_u24_le(&mut self, num: u32);
+    fn push_u32_le(&mut self, num: u32);
+    fn push_u64_le(&mut self, num: u64);
+    fn push_i16_le(&mut self, num: i16);
+    fn push_i32_le(&mut self, num: i32);
+    fn push_i64_le(&mut self, num: i64);
+    fn push_f32(&mut self, num: f32);
+    fn push_f64(&mut self, num: f64);
+    fn push_f32_le(&mut self, num: f32);
+    fn push_f64_le(&mut self, num: f64);
+    fn push_bool(&mut self, value: bool);
+    fn push_var_u32(&mut self, num: u32);
+    fn push_var_i32(&mut self, num: i32);
+    fn push_var_u64(&mut self, num: u64);
+    fn push_var_i64(&mut self, num: i64);
+    /// Pushes bytes prefixed with their length as varint
+    ///
+    /// Panics if there are more than u32::MAX bytes
+    fn push_var_bytes(&mut self, bytes: &[u8]);
+    fn push_var_string(&mut self, string: &str);
+    /// Pushes a named NBT tag, fails for tags which can't be represented
+    fn push_nbt(&mut self, name: &str, tag: &Tag, encoding: Encoding) -> Result<(), Error>;
 }
 
 impl<T> PacketBufferRead for T
 where
     T: Deref<Target = [u8]>,
 {
+    #[allow(clippy::unnecessary_cast)]
     fn read_magic(&self, start: usize) -> bool {
         self[start as usize..(start + 16) as usize].to_vec() == MAGIC.to_vec()
     }
@@ -51,6 +103,7 @@ where
         String::from_utf8(self[start..(start + str_buff_len as usize)].to_vec())
     }
 
+    #[allow(clippy::identity_op)]
     fn read_address(&self, start: usize) -> SocketAddr {
         //let ipv: u16 = self.read_u16(start);
         let mut parts: Vec<u8> = Vec::new();
@@ -66,6 +119,7 @@ where
         u16::from_be_bytes(self[start..start + 2].try_into().unwrap())
     }
 
+    #[allow(clippy::useless_conversion, clippy::let_and_return)]
     fn read_u24(&self, start: usize) -> u32 {
         let mut container = [0u8; 4];
         container[1..4].clone_from_slice(&self[start..start + 3]);
@@ -100,6 +154,118 @@ where
     fn read_i128(&self, start: usize) -> i128 {
         i128::from_be_bytes(self[start..start + 16].try_into().unwrap())
     }
+
+    fn read_u16_le(&self, start: usize) -> u16 {
+        u16::from_le_bytes(self[start..start + 2].try_into().unwrap())
+    }
+
+    fn read_u24_le(&self, start: usize) -> u32 {
+        let mut container = [0u8; 4];
+        container[0..3].clone_from_slice(&self[start..start + 3]);
+        u32::from_le_bytes(container)
+    }
+
+    fn read_u32_le(&self, start: usize) -> u32 {
+        u32::from_le_bytes(self[start..start + 4].try_into().unwrap())
+    }
+
+    fn read_u64_le(&self, start: usize) -> u64 {
+        u64::from_le_bytes(self[start..start + 8].try_into().unwrap())
+    }
+
+    fn read_i16_le(&self, start: usize) -> i16 {
+        i16::from_le_bytes(self[start..start + 2].try_into().unwrap())
+    }
+
+    fn read_i32_le(&self, start: usize) -> i32 {
+        i32::from_le_bytes(self[start..start + 4].try_into().unwrap())
+    }
+
+    fn read_i64_le(&self, start: usize) -> i64 {
+        i64::from_le_bytes(self[start..start + 8].try_into().unwrap())
+    }
+
+    fn read_f32(&self, start: usize) -> f32 {
+        f32::from_be_bytes(self[start..start + 4].try_into().unwrap())
+    }
+
+    fn read_f64(&self, start: usize) -> f64 {
+        f64::from_be_bytes(self[start..start + 8].try_into().unwrap())
+    }
+
+    fn read_f32_le(&self, start: usize) -> f32 {
+        f32::from_le_bytes(self[start..start + 4].try_into().unwrap())
+    }
+
+    fn read_f64_le(&self, start: usize) -> f64 {
+        f64::from_le_bytes(self[start..start + 8].try_into().unwrap())
+    }
+
+    fn read_bool(&self, start: usize) -> bool {
+        self[start] != 0
+    }
+
+    fn read_var_u32(&self, start: usize) -> Result<(u32, usize), Error> {
+        let (num, len) = read_var(self, start, 32)?;
+        Ok((num as u32, len))
+    }
+
+    fn read_var_i32(&self, start: usize) -> Result<(i32, usize), Error> {
+        let (num, len) = self.read_var_u32(start)?;
+        Ok(((num >> 1) as i32 ^ -((num & 1) as i32), len))
+    }
+
+    fn read_var_u64(&self, start: usize) -> Result<(u64, usize), Error> {
+        read_var(self, start, 64)
+    }
+
+    fn read_var_i64(&self, start: usize) -> Result<(i64, usize), Error> {
+        let (num, len) = self.read_var_u64(start)?;
+        Ok(((num >> 1) as i64 ^ -((num & 1) as i64), len))
+    }
+
+    fn read_var_bytes(&self, start: usize) -> Result<(&[u8], usize), Error> {
+        let (bytes_len, len) = self.read_var_u32(start)?;
+        let bytes = self
+            .get(start + len..start + len + bytes_len as usize)
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, format!("{} bytes are truncated", bytes_len)))?;
+        Ok((bytes, len + bytes_len as usize))
+    }
+
+    fn read_var_string(&self, start: usize) -> Result<(String, usize), Error> {
+        let (bytes, len) = self.read_var_bytes(start)?;
+        let string = String::from_utf8(bytes.to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        Ok((string, len))
+    }
+
+    fn read_nbt(&self, start: usize, encoding: Encoding) -> Result<(String, Tag, usize), Error> {
+        nbt::read(self, start, encoding, Limits::default())
+    }
+}
+
+/// Reads a varint of at most `bits` bits
+fn read_var(binary: &[u8], start: usize, bits: usize) -> Result<(u64, usize), Error> {
+    let max_len = bits.div_ceil(7);
+    let mut num: u64 = 0;
+    for i in 0..max_len {
+        let byte = *binary
+            .get(start + i)
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "varint is truncated"))?;
+        if i == max_len - 1 && (byte & 0x7f) >> (bits - 7 * i) != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("varint does not fit into {} bits", bits),
+            ));
+        }
+        num |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok((num, i + 1));
+        }
+    }
+    Err(Error::new(
+        ErrorKind::InvalidData,
+        format!("varint is longer than {} bytes", max_len),
+    ))
 }
 
 impl PacketBufferWrite for Vec<u8> {
@@ -117,6 +283,7 @@ impl PacketBufferWrite for Vec<u8> {
         Ok(())
     }
 
+    #[allow(clippy::from_str_radix_10, clippy::identity_op)]
     fn push_address(&mut self, addr: SocketAddr) {
         if addr.is_ipv4() {
             self.push(0x04);
@@ -172,4 +339,200 @@ impl PacketBufferWrite for Vec<u8> {
     fn push_i128(&mut self, num: i128) {
         self.push_slice(&num.to_be_bytes());
     }
+
+    fn push_u16_le(&mut self, num: u16) {
+        self.push_slice(&num.to_le_bytes());
+    }
+
+    fn push_u24_le(&mut self, num: u32) {
+        let bytes = &num.to_le_bytes()[0..3];
+        self.push_slice(bytes);
+    }
+
+    fn push_u32_le(&mut self, num: u32) {
+        self.push_slice(&num.to_le_bytes());
+    }
+
+    fn push_u64_le(&mut self, num: u64) {
+        self.push_slice(&num.to_le_bytes());
+    }
+
+    fn push_i16_le(&mut self, num: i16) {
+        self.push_slice(&num.to_le_bytes());
+    }
+
+    fn push_i32_le(&mut self, num: i32) {
+        self.push_slice(&num.to_le_bytes());
+    }
+
+    fn push_i64_le(&mut self, num: i64) {
+        self.push_slice(&num.to_le_bytes());
+    }
+
+    fn push_f32(&mut self, num: f32) {
+        self.push_slice(&num.to_be_bytes());
+    }
+
+    fn push_f64(&mut self, num: f64) {
+        self.push_slice(&num.to_be_bytes());
+    }
+
+    fn push_f32_le(&mut self, num: f32) {
+        self.push_slice(&num.to_le_bytes());
+    }
+
+    fn push_f64_le(&mut self, num: f64) {
+        self.push_slice(&num.to_le_bytes());
+    }
+
+    fn push_bool(&mut self, value: bool) {
+        self.push(value as u8);
+    }
+
+    fn push_var_u32(&mut self, num: u32) {
+        self.push_var_u64(num as u64);
+    }
+
+    fn push_var_i32(&mut self, num: i32) {
+        self.push_var_u32(((num << 1) ^ (num >> 31)) as u32);
+    }
+
+    fn push_var_u64(&mut self, mut num: u64) {
+        while num >= 0x80 {
+            self.push((num as u8 & 0x7f) | 0x80);
+            num >>= 7;
+        }
+        self.push(num as u8);
+    }
+
+    fn push_var_i64(&mut self, num: i64) {
+        self.push_var_u64(((num << 1) ^ (num >> 63)) as u64);
+    }
+
+    fn push_var_bytes(&mut self, bytes: &[u8]) {
+        self.push_var_u32(u32::try_from(bytes.len()).expect("bytes are too long for a varint length"));
+        self.push_slice(bytes);
+    }
+
+    fn push_var_string(&mut self, string: &str) {
+        self.push_var_bytes(string.as_bytes());
+    }
+
+    fn push_nbt(&mut self, name: &str, tag: &Tag, encoding: Encoding) -> Result<(), Error> {
+        let binary = nbt::write(Vec::new(), name, tag, encoding)?;
+        self.push_slice(&binary);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_round_trip() {
+        for num in [0, 1, 127, 128, 300, u32::MAX] {
+            let mut binary = Vec::new();
+            binary.push_var_u32(num);
+            assert_eq!(binary.read_var_u32(0).unwrap(), (num, binary.len()));
+        }
+        for num in [0, -1, 1, i32::MIN, i32::MAX] {
+            let mut binary = Vec::new();
+            binary.push_var_i32(num);
+            assert_eq!(binary.read_var_i32(0).unwrap().0, num);
+        }
+        for num in [0, u64::MAX, u32::MAX as u64 + 1] {
+            let mut binary = Vec::new();
+            binary.push_var_u64(num);
+            assert_eq!(binary.read_var_u64(0).unwrap(), (num, binary.len()));
+        }
+        for num in [i64::MIN, i64::MAX, -2] {
+            let mut binary = Vec::new();
+            binary.push_var_i64(num);
+            assert_eq!(binary.read_var_i64(0).unwrap().0, num);
+        }
+    }
+
+    #[test]
+    fn varint_overflow() {
+        assert_eq!(vec![0xff, 0xff, 0xff, 0xff, 0x0f].read_var_u32(0).unwrap().0, u32::MAX);
+        assert!(vec![0xff, 0xff, 0xff, 0xff, 0x1f].read_var_u32(0).is_err());
+        assert!(vec![0xff, 0xff, 0xff, 0xff, 0xff, 0x01].read_var_u32(0).is_err());
+        let mut binary = vec![0xff; 9];
+        binary.push(0x01);
+        assert_eq!(binary.read_var_u64(0).unwrap().0, u64::MAX);
+        binary[9] = 0x02;
+        assert!(binary.read_var_u64(0).is_err());
+    }
+
+    #[test]
+    fn varint_truncated() {
+        assert!(vec![0x80, 0x80].read_var_u32(0).is_err());
+        assert!(Vec::new().read_var_u64(0).is_err());
+        assert!(vec![0x05, b'a'].read_var_bytes(0).is_err());
+    }
+
+    #[test]
+    fn little_endian_integers() {
+        let mut binary = Vec::new();
+        binary.push_u16_le(0x0102);
+        binary.push_u24_le(0x030405);
+        binary.push_u32_le(0x06070809);
+        binary.push_u64_le(0x0a0b0c0d0e0f1011);
+        assert_eq!(
+            binary,
+            vec![
+                0x02, 0x01, 0x05, 0x04, 0x03, 0x09, 0x08, 0x07, 0x06, 0x11, 0x10, 0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a
+            ]
+        );
+        assert_eq!(binary.read_u16_le(0), 0x0102);
+        assert_eq!(binary.read_u24_le(2), 0x030405);
+        assert_eq!(binary.read_u32_le(5), 0x06070809);
+        assert_eq!(binary.read_u64_le(9), 0x0a0b0c0d0e0f1011);
+
+        let mut binary = Vec::new();
+        binary.push_i16_le(-2);
+        binary.push_i32_le(i32::MIN);
+        binary.push_i64_le(-0x0102030405060708);
+        assert_eq!(&binary[0..6], &[0xfe, 0xff, 0x00, 0x00, 0x00, 0x80]);
+        assert_eq!(&binary[6..14], &[0xf8, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe]);
+        assert_eq!(binary.read_i16_le(0), -2);
+        assert_eq!(binary.read_i32_le(2), i32::MIN);
+        assert_eq!(binary.read_i64_le(6), -0x0102030405060708);
+    }
+
+    #[test]
+    fn floats() {
+        let mut binary = Vec::new();
+        binary.push_f32_le(1.0);
+        binary.push_f64_le(-2.5);
+        binary.push_f32(1.0);
+        binary.push_f64(-2.5);
+        assert_eq!(&binary[0..4], &[0x00, 0x00, 0x80, 0x3f]);
+        assert_eq!(&binary[4..12], &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0xc0]);
+        assert_eq!(&binary[12..16], &[0x3f, 0x80, 0x00, 0x00]);
+        assert_eq!(&binary[16..24], &[0xc0, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(binary.read_f32_le(0), 1.0);
+        assert_eq!(binary.read_f64_le(4), -2.5);
+        assert_eq!(binary.read_f32(12), 1.0);
+        assert_eq!(binary.read_f64(16), -2.5);
+    }
+
+    #[test]
+    fn bools() {
+        let mut binary = Vec::new();
+        binary.push_bool(true);
+        binary.push_bool(false);
+        assert_eq!(binary, vec![1, 0]);
+        assert!(binary.read_bool(0));
+        assert!(!binary.read_bool(1));
+        assert!(vec![2].read_bool(0));
+    }
+
+    #[test]
+    fn var_string_round_trip() {
+        let mut binary = Vec::new();
+        binary.push_var_string("limonite");
+        assert_eq!(binary.read_var_string(0).unwrap(), ("limonite".to_string(), 9));
+    }
 }
@@ -0,0 +1,610 @@
+use crate::utils::buffer::{PacketBufferRead, PacketBufferWrite};
+use std::convert::{TryFrom, TryInto};
+use std::io::{Error, ErrorKind};
+use std::iter::FromIterator;
+
+/// Compounds nested deeper than this are rejected, like the game does
+pub const MAX_DEPTH: usize = 512;
+/// Default amount of bytes a single tag may take up
+pub const MAX_SIZE: usize = 2 * 1024 * 1024;
+/// Elements reserved up front for lists and arrays, longer ones grow while they are read
+const MAX_PREALLOCATED: usize = 1024;
+
+const END: u8 = 0;
+const BYTE: u8 = 1;
+const SHORT: u8 = 2;
+const INT: u8 = 3;
+const LONG: u8 = 4;
+const FLOAT: u8 = 5;
+const DOUBLE: u8 = 6;
+const BYTE_ARRAY: u8 = 7;
+const STRING: u8 = 8;
+const LIST: u8 = 9;
+const COMPOUND: u8 = 10;
+const INT_ARRAY: u8 = 11;
+const LONG_ARRAY: u8 = 12;
+
+/// Byte order and length encoding of a tag
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Encoding {
+    /// as used by Java Edition
+    BigEndian,
+    /// as used in Bedrock world files
+    LittleEndian,
+    /// as sent to Bedrock clients, ints, longs and lengths are varints
+    Network,
+}
+
+/// Limits against malicious input
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Limits {
+    pub max_depth: usize,
+    pub max_size: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    /// tags of the same type
+    List(Vec<Tag>),
+    Compound(Compound),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+/// Named tags, in the order they were added
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Compound {
+    entries: Vec<(String, Tag)>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: MAX_DEPTH,
+            max_size: MAX_SIZE,
+        }
+    }
+}
+
+impl Tag {
+    pub fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => BYTE,
+            Tag::Short(_) => SHORT,
+            Tag::Int(_) => INT,
+            Tag::Long(_) => LONG,
+            Tag::Float(_) => FLOAT,
+            Tag::Double(_) => DOUBLE,
+            Tag::ByteArray(_) => BYTE_ARRAY,
+            Tag::String(_) => STRING,
+            Tag::List(_) => LIST,
+            Tag::Compound(_) => COMPOUND,
+            Tag::IntArray(_) => INT_ARRAY,
+            Tag::LongArray(_) => LONG_ARRAY,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&Compound> {
+        match self {
+            Tag::Compound(compound) => Some(compound),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Value of any integer tag
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Tag::Byte(value) => Some(*value as i64),
+            Tag::Short(value) => Some(*value as i64),
+            Tag::Int(value) => Some(*value as i64),
+            Tag::Long(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl Compound {
+    pub fn new() -> Compound {
+        Compound::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Tag> {
+        self.entries.iter().find(|(key, _)| key == name).map(|(_, tag)| tag)
+    }
+
+    /// Adds `tag`, replacing the tag with the same name
+    pub fn insert(&mut self, name: String, tag: Tag) {
+        match self.entries.iter_mut().find(|(key, _)| *key == name) {
+            Some(entry) => entry.1 = tag,
+            None => self.entries.push((name, tag)),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Tag> {
+        let index = self.entries.iter().position(|(key, _)| key == name)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Tag)> {
+        self.entries.iter().map(|(key, tag)| (key, tag))
+    }
+}
+
+impl FromIterator<(String, Tag)> for Compound {
+    fn from_iter<I: IntoIterator<Item = (String, Tag)>>(iter: I) -> Self {
+        let mut compound = Compound::new();
+        for (name, tag) in iter {
+            compound.insert(name, tag);
+        }
+        compound
+    }
+}
+
+/// Reads the named tag at `offset`, returns its name, the tag and the amount of bytes read
+pub fn read(binary: &[u8], offset: usize, encoding: Encoding, limits: Limits) -> Result<(String, Tag, usize), Error> {
+    let end = binary.len().min(offset.saturating_add(limits.max_size));
+    let mut reader = Reader {
+        binary: binary.get(..end).unwrap_or_default(),
+        position: offset,
+        encoding,
+        max_depth: limits.max_depth,
+    };
+    let id = reader.u8()?;
+    if id == END {
+        return Err(Error::new(ErrorKind::InvalidData, "root tag is empty"));
+    }
+    let name = reader.string()?;
+    let tag = reader.tag(id, 0)?;
+    Ok((name, tag, reader.position - offset))
+}
+
+/// Appends `tag` with `name`. Fails for tags which can't be represented, e.g. lists with
+/// elements of different types or strings which are too long.
+pub fn write(mut binary: Vec<u8>, name: &str, tag: &Tag, encoding: Encoding) -> Result<Vec<u8>, Error> {
+    binary.push(tag.id());
+    let mut writer = Writer { binary, encoding };
+    writer.string(name)?;
+    writer.tag(tag)?;
+    Ok(writer.binary)
+}
+
+struct Reader<'a> {
+    binary: &'a [u8],
+    position: usize,
+    encoding: Encoding,
+    max_depth: usize,
+}
+
+struct Writer {
+    binary: Vec<u8>,
+    encoding: Encoding,
+}
+
+impl Reader<'_> {
+    fn tag(&mut self, id: u8, depth: usize) -> Result<Tag, Error> {
+        if depth > self.max_depth {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("tags are nested deeper than {}", self.max_depth),
+            ));
+        }
+        Ok(match id {
+            BYTE => Tag::Byte(self.u8()? as i8),
+            SHORT => Tag::Short(i16::from_bits(self.fixed::<2>()?, self.encoding)),
+            INT => Tag::Int(self.int()?),
+            LONG => Tag::Long(self.long()?),
+            FLOAT => Tag::Float(f32::from_bits(u32::from_bits(self.fixed::<4>()?, self.encoding))),
+            DOUBLE => Tag::Double(f64::from_bits(u64::from_bits(self.fixed::<8>()?, self.encoding))),
+            BYTE_ARRAY => {
+                let len = self.length(1)?;
+                Tag::ByteArray(self.bytes(len)?.to_vec())
+            }
+            STRING => Tag::String(self.string()?),
+            LIST => {
+                let element_id = self.u8()?;
+                // every element takes up at least one byte
+                let len = self.length(1)?;
+                if element_id == END && len > 0 {
+                    return Err(Error::new(ErrorKind::InvalidData, "list of end tags is not empty"));
+                }
+                let mut list = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+                for _ in 0..len {
+                    list.push(self.tag(element_id, depth + 1)?);
+                }
+                Tag::List(list)
+            }
+            COMPOUND => {
+                let mut compound = Compound::new();
+                loop {
+                    let id = self.u8()?;
+                    if id == END {
+                        break;
+                    }
+                    let name = self.string()?;
+                    let tag = self.tag(id, depth + 1)?;
+                    compound.insert(name, tag);
+                }
+                Tag::Compound(compound)
+            }
+            INT_ARRAY => {
+                let len = self.length(if self.encoding == Encoding::Network { 1 } else { 4 })?;
+                let mut array = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+                for _ in 0..len {
+                    array.push(self.int()?);
+                }
+                Tag::IntArray(array)
+            }
+            LONG_ARRAY => {
+                let len = self.length(if self.encoding == Encoding::Network { 1 } else { 8 })?;
+                let mut array = Vec::with_capacity(len.min(MAX_PREALLOCATED));
+                for _ in 0..len {
+                    array.push(self.long()?);
+                }
+                Tag::LongArray(array)
+            }
+            _ => return Err(Error::new(ErrorKind::InvalidData, format!("unknown tag type {}", id))),
+        })
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&[u8], Error> {
+        let bytes = self
+            .binary
+            .get(self.position..self.position.saturating_add(len))
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "tag is truncated or too large"))?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn int(&mut self) -> Result<i32, Error> {
+        match self.encoding {
+            Encoding::Network => {
+                let (value, len) = self.binary.read_var_i32(self.position)?;
+                self.position += len;
+                Ok(value)
+            }
+            encoding => Ok(i32::from_bits(self.fixed::<4>()?, encoding)),
+        }
+    }
+
+    fn long(&mut self) -> Result<i64, Error> {
+        match self.encoding {
+            Encoding::Network => {
+                let (value, len) = self.binary.read_var_i64(self.position)?;
+                self.position += len;
+                Ok(value)
+            }
+            encoding => Ok(i64::from_bits(self.fixed::<8>()?, encoding)),
+        }
+    }
+
+    /// Reads the length of a list or array, which has to fit in the remaining bytes with
+    /// `element_size` bytes per element
+    fn length(&mut self, element_size: usize) -> Result<usize, Error> {
+        let len = self.int()?;
+        let remaining = self.binary.len() - self.position;
+        if len < 0 || (len as usize).saturating_mul(element_size) > remaining {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid length {}", len)));
+        }
+        Ok(len as usize)
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        let len = match self.encoding {
+            Encoding::Network => {
+                let (len, read) = self.binary.read_var_u32(self.position)?;
+                self.position += read;
+                len as usize
+            }
+            encoding => u16::from_bits(self.fixed::<2>()?, encoding) as usize,
+        };
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+    }
+}
+
+impl Writer {
+    fn tag(&mut self, tag: &Tag) -> Result<(), Error> {
+        match tag {
+            Tag::Byte(value) => self.binary.push(*value as u8),
+            Tag::Short(value) => self.fixed(&value.to_bits(self.encoding)),
+            Tag::Int(value) => self.int(*value),
+            Tag::Long(value) => self.long(*value),
+            Tag::Float(value) => self.fixed(&value.to_bits().to_bits(self.encoding)),
+            Tag::Double(value) => self.fixed(&value.to_bits().to_bits(self.encoding)),
+            Tag::ByteArray(array) => {
+                self.length(array.len())?;
+                self.binary.extend_from_slice(array);
+            }
+            Tag::String(string) => self.string(string)?,
+            Tag::List(list) => {
+                let element_id = list.first().map_or(END, |tag| tag.id());
+                if list.iter().any(|tag| tag.id() != element_id) {
+                    return Err(Error::new(ErrorKind::InvalidInput, "list contains tags of different types"));
+                }
+                self.binary.push(element_id);
+                self.length(list.len())?;
+                for tag in list {
+                    self.tag(tag)?;
+                }
+            }
+            Tag::Compound(compound) => {
+                for (name, tag) in compound.iter() {
+                    self.binary.push(tag.id());
+                    self.string(name)?;
+                    self.tag(tag)?;
+                }
+                self.binary.push(END);
+            }
+            Tag::IntArray(array) => {
+                self.length(array.len())?;
+                for value in array {
+                    self.int(*value);
+                }
+            }
+            Tag::LongArray(array) => {
+                self.length(array.len())?;
+                for value in array {
+                    self.long(*value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the length of a list or array, which has to fit in an int
+    fn length(&mut self, len: usize) -> Result<(), Error> {
+        let len = i32::try_from(len)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("{} elements are too many for a tag", len)))?;
+        self.int(len);
+        Ok(())
+    }
+
+    fn fixed(&mut self, bytes: &[u8]) {
+        self.binary.extend_from_slice(bytes);
+    }
+
+    fn int(&mut self, value: i32) {
+        match self.encoding {
+            Encoding::Network => self.binary.push_var_i32(value),
+            encoding => self.fixed(&value.to_bits(encoding)),
+        }
+    }
+
+    fn long(&mut self, value: i64) {
+        match self.encoding {
+            Encoding::Network => self.binary.push_var_i64(value),
+            encoding => self.fixed(&value.to_bits(encoding)),
+        }
+    }
+
+    fn string(&mut self, string: &str) -> Result<(), Error> {
+        let max_len = match self.encoding {
+            Encoding::Network => u32::MAX as usize,
+            _ => u16::MAX as usize,
+        };
+        if string.len() > max_len {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("string of {} bytes is longer than {}", string.len(), max_len),
+            ));
+        }
+        match self.encoding {
+            Encoding::Network => self.binary.push_var_string(string),
+            encoding => {
+                self.fixed(&(string.len() as u16).to_bits(encoding));
+                self.binary.extend_from_slice(string.as_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Conversion of fixed size numbers in the byte order of an encoding
+trait Bits<const N: usize>: Sized {
+    fn from_bits(bytes: [u8; N], encoding: Encoding) -> Self;
+    fn to_bits(&self, encoding: Encoding) -> [u8; N];
+}
+
+macro_rules! impl_bits {
+    ($($num:ty => $size:expr),*) => {
+        $(impl Bits<$size> for $num {
+            fn from_bits(bytes: [u8; $size], encoding: Encoding) -> Self {
+                match encoding {
+                    Encoding::BigEndian => <$num>::from_be_bytes(bytes),
+                    _ => <$num>::from_le_bytes(bytes),
+                }
+            }
+
+            fn to_bits(&self, encoding: Encoding) -> [u8; $size] {
+                match encoding {
+                    Encoding::BigEndian => self.to_be_bytes(),
+                    _ => self.to_le_bytes(),
+                }
+            }
+        })*
+    };
+}
+
+impl_bits!(u16 => 2, i16 => 2, u32 => 4, i32 => 4, u64 => 8, i64 => 8);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODINGS: [Encoding; 3] = [Encoding::BigEndian, Encoding::LittleEndian, Encoding::Network];
+
+    fn sample() -> Tag {
+        let mut nested = Compound::new();
+        nested.insert("name".to_string(), Tag::String("minecraft:stone".to_string()));
+        nested.insert("unicode".to_string(), Tag::String("ünïcödé ✓".to_string()));
+        Tag::Compound(Compound::from_iter(vec![
+            ("byte".to_string(), Tag::Byte(-1)),
+            ("short".to_string(), Tag::Short(-300)),
+            ("int".to_string(), Tag::Int(i32::MIN)),
+            ("long".to_string(), Tag::Long(i64::MAX)),
+            ("float".to_string(), Tag::Float(1.5)),
+            ("double".to_string(), Tag::Double(-0.25)),
+            ("bytes".to_string(), Tag::ByteArray(vec![1, 2, 3])),
+            ("list".to_string(), Tag::List(vec![Tag::Int(1), Tag::Int(2)])),
+            ("empty".to_string(), Tag::List(Vec::new())),
+            ("nested".to_string(), Tag::Compound(nested)),
+            ("ints".to_string(), Tag::IntArray(vec![-1, 0, 1])),
+            ("longs".to_string(), Tag::LongArray(vec![i64::MIN])),
+        ]))
+    }
+
+    fn nested(depth: usize) -> Tag {
+        (0..depth).fold(Tag::Compound(Compound::new()), |tag, _| {
+            Tag::Compound(Compound::from_iter(vec![("a".to_string(), tag)]))
+        })
+    }
+
+    #[test]
+    fn round_trip() {
+        for encoding in ENCODINGS {
+            let binary = write(vec![0xaa], "root", &sample(), encoding).unwrap();
+            let (name, tag, len) = read(&binary, 1, encoding, Limits::default()).unwrap();
+            assert_eq!(name, "root");
+            assert_eq!(tag, sample());
+            assert_eq!(len, binary.len() - 1);
+        }
+    }
+
+    #[test]
+    fn compound_keeps_order() {
+        let mut compound = Compound::new();
+        compound.insert("b".to_string(), Tag::Byte(1));
+        compound.insert("a".to_string(), Tag::Byte(2));
+        compound.insert("b".to_string(), Tag::Byte(3));
+        let names: Vec<&String> = compound.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["b", "a"]);
+        assert_eq!(compound.get("b"), Some(&Tag::Byte(3)));
+        assert_eq!(compound.remove("b"), Some(Tag::Byte(3)));
+        assert_eq!(compound.len(), 1);
+    }
+
+    #[test]
+    fn truncated_tags() {
+        for encoding in ENCODINGS {
+            let binary = write(Vec::new(), "", &sample(), encoding).unwrap();
+            for len in 0..binary.len() {
+                assert!(read(&binary[..len], 0, encoding, Limits::default()).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn depth_limit() {
+        let limits = Limits {
+            max_depth: 16,
+            ..Limits::default()
+        };
+        for encoding in ENCODINGS {
+            let binary = write(Vec::new(), "", &nested(16), encoding).unwrap();
+            assert!(read(&binary, 0, encoding, limits).is_ok());
+            let binary = write(Vec::new(), "", &nested(17), encoding).unwrap();
+            assert!(read(&binary, 0, encoding, limits).is_err());
+        }
+    }
+
+    #[test]
+    fn size_limit() {
+        let tag = Tag::ByteArray(vec![0; 1000]);
+        let limits = Limits {
+            max_size: 500,
+            ..Limits::default()
+        };
+        for encoding in ENCODINGS {
+            let binary = write(Vec::new(), "", &tag, encoding).unwrap();
+            assert!(read(&binary, 0, encoding, Limits::default()).is_ok());
+            assert!(read(&binary, 0, encoding, limits).is_err());
+        }
+    }
+
+    #[test]
+    fn lengths_beyond_the_remaining_bytes() {
+        // a list claiming i32::MAX bytes with nothing following it
+        let mut binary = vec![LIST, 0, 0, BYTE];
+        binary.extend_from_slice(&i32::MAX.to_be_bytes());
+        assert!(read(&binary, 0, Encoding::BigEndian, Limits::default()).is_err());
+        let mut binary = vec![LIST, 0, 0, BYTE];
+        binary.extend_from_slice(&(-1i32).to_be_bytes());
+        assert!(read(&binary, 0, Encoding::BigEndian, Limits::default()).is_err());
+        // a list of end tags has to be empty
+        let binary = vec![LIST, 0, 0, END, 0, 0, 0, 1, 0];
+        assert!(read(&binary, 0, Encoding::BigEndian, Limits::default()).is_err());
+    }
+
+    #[test]
+    fn unknown_and_empty_root_tags() {
+        assert!(read(&[13, 0, 0], 0, Encoding::BigEndian, Limits::default()).is_err());
+        assert!(read(&[END], 0, Encoding::BigEndian, Limits::default()).is_err());
+        assert!(read(&[], 0, Encoding::BigEndian, Limits::default()).is_err());
+    }
+
+    #[test]
+    fn mixed_lists_are_rejected() {
+        let tag = Tag::List(vec![Tag::Int(1), Tag::Byte(2)]);
+        for encoding in ENCODINGS {
+            assert_eq!(write(Vec::new(), "", &tag, encoding).unwrap_err().kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn long_strings_are_rejected() {
+        let string = "é".repeat(u16::MAX as usize / 2 + 1);
+        let tag = Tag::String(string.clone());
+        assert!(write(Vec::new(), "", &tag, Encoding::BigEndian).is_err());
+        assert!(write(Vec::new(), &string, &Tag::Byte(0), Encoding::LittleEndian).is_err());
+        // network strings are prefixed with a varint instead
+        let binary = write(Vec::new(), "", &tag, Encoding::Network).unwrap();
+        assert_eq!(read(&binary, 0, Encoding::Network, Limits::default()).unwrap().1, tag);
+
+        let string = "a".repeat(u16::MAX as usize);
+        let binary = write(Vec::new(), "", &Tag::String(string.clone()), Encoding::BigEndian).unwrap();
+        assert_eq!(read(&binary, 0, Encoding::BigEndian, Limits::default()).unwrap().1, Tag::String(string));
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let binary = vec![STRING, 0, 0, 0, 2, 0xc3, 0x28];
+        assert!(read(&binary, 0, Encoding::BigEndian, Limits::default()).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+pub const OCEAN: u32 = 0;
+pub const PLAINS: u32 = 1;
+pub const DESERT: u32 = 2;
+pub const EXTREME_HILLS: u32 = 3;
+pub const FOREST: u32 = 4;
+pub const TAIGA: u32 = 5;
+pub const SWAMPLAND: u32 = 6;
+pub const RIVER: u32 = 7;
+pub const ICE_PLAINS: u32 = 12;
+pub const BEACH: u32 = 16;
+
+const NAMES: [(&str, u32); 10] = [
+    ("ocean", OCEAN),
+    ("plains", PLAINS),
+    ("desert", DESERT),
+    ("extreme_hills", EXTREME_HILLS),
+    ("forest", FOREST),
+    ("taiga", TAIGA),
+    ("swampland", SWAMPLAND),
+    ("river", RIVER),
+    ("ice_plains", ICE_PLAINS),
+    ("beach", BEACH),
+];
+
+/// Finds a biome by its name, with or without "minecraft:", or by its id
+pub fn from_name(name: &str) -> Option<u32> {
+    let name = name.trim();
+    if let Ok(id) = name.parse() {
+        return Some(id);
+    }
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    NAMES.iter().find(|(other, _)| *other == name).map(|(_, id)| *id)
+}
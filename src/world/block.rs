@@ -0,0 +1,375 @@
+use crate::utils::buffer::PacketBufferRead;
+use crate::utils::nbt::{self, Compound, Encoding, Tag};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+/// Block states of a version in runtime id order, as network NBT compounds one after another
+pub const BLOCK_STATES_FILE: &str = "canonical_block_states.nbt";
+/// Legacy numeric ids of the block names as JSON object, needed by clients before 1.16.100
+pub const LEGACY_IDS_FILE: &str = "block_id_map.json";
+pub const AIR: &str = "minecraft:air";
+/// Shown by clients for blocks they don't know
+pub const UNKNOWN: &str = "minecraft:info_update";
+
+/// A block with the values of its properties, e.g. "minecraft:wool" with "color" "red"
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockState {
+    name: String,
+    states: Compound,
+    version: i32,
+}
+
+/// The block states of one game version, indexed by runtime id
+pub struct BlockPalette {
+    states: Vec<BlockState>,
+    /// runtime ids by the encoded name and sorted states
+    runtime_ids: HashMap<Vec<u8>, u32>,
+    legacy_ids: HashMap<String, i16>,
+}
+
+/// Block palettes of all supported protocol versions. Chunks store the runtime ids of the newest
+/// version, which are translated for clients of older versions.
+pub struct BlockRegistry {
+    newest: u32,
+    palettes: HashMap<u32, BlockPalette>,
+    /// runtime ids of older versions, indexed by the runtime ids of the newest one
+    translations: HashMap<u32, Vec<u32>>,
+}
+
+impl BlockState {
+    pub fn new(name: String, states: Compound, version: i32) -> BlockState {
+        BlockState { name, states, version }
+    }
+
+    /// Reads a compound with "name", "states" and "version"
+    pub fn from_compound(compound: &Compound) -> Result<BlockState, Error> {
+        let name = compound
+            .get("name")
+            .and_then(|name| name.as_str())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "block state has no name"))?;
+        let states = match compound.get("states") {
+            Some(Tag::Compound(states)) => states.clone(),
+            Some(_) => return Err(Error::new(ErrorKind::InvalidData, format!("states of {} are no compound", name))),
+            None => Compound::new(),
+        };
+        let version = compound.get("version").and_then(|version| version.as_i64()).unwrap_or_default();
+        Ok(BlockState::new(name.to_string(), states, version as i32))
+    }
+
+    pub fn to_compound(&self) -> Compound {
+        let mut compound = Compound::new();
+        compound.insert("name".to_string(), Tag::String(self.name.clone()));
+        compound.insert("states".to_string(), Tag::Compound(self.states.clone()));
+        compound.insert("version".to_string(), Tag::Int(self.version));
+        compound
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn states(&self) -> &Compound {
+        &self.states
+    }
+
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Little endian NBT of the name and the states sorted by name, which identifies a state
+    /// regardless of the order of its properties
+    fn key(name: &str, states: &Compound) -> Result<Vec<u8>, Error> {
+        let mut sorted: Vec<(&String, &Tag)> = states.iter().collect();
+        sorted.sort_by(|a, b| a.0.cmp(b.0));
+        let mut compound = Compound::new();
+        compound.insert("name".to_string(), Tag::String(name.to_string()));
+        compound.insert(
+            "states".to_string(),
+            Tag::Compound(sorted.into_iter().map(|(name, tag)| (name.clone(), tag.clone())).collect()),
+        );
+        nbt::write(Vec::new(), "", &Tag::Compound(compound), Encoding::LittleEndian)
+    }
+}
+
+impl BlockPalette {
+    pub fn new(states: Vec<BlockState>) -> BlockPalette {
+        let runtime_ids = states
+            .iter()
+            .enumerate()
+            // states which can't be encoded can't be looked up either
+            .filter_map(|(runtime_id, state)| Some((BlockState::key(&state.name, &state.states).ok()?, runtime_id as u32)))
+            .collect();
+        BlockPalette {
+            states,
+            runtime_ids,
+            legacy_ids: HashMap::new(),
+        }
+    }
+
+    /// Palette with nothing but air, used if no block states were loaded
+    pub fn fallback() -> BlockPalette {
+        BlockPalette::new(vec![BlockState::new(AIR.to_string(), Compound::new(), 0)])
+    }
+
+    /// Loads the block states and, if present, the legacy ids from `directory`
+    pub fn load(directory: &Path) -> Result<BlockPalette, Error> {
+        let mut palette = BlockPalette::from_bytes(&fs::read(directory.join(BLOCK_STATES_FILE))?)?;
+        let legacy_ids = directory.join(LEGACY_IDS_FILE);
+        if legacy_ids.is_file() {
+            palette.load_legacy_ids(&fs::read_to_string(legacy_ids)?)?;
+        }
+        Ok(palette)
+    }
+
+    /// Reads network NBT compounds of block states until the end of `binary`
+    pub fn from_bytes(binary: &[u8]) -> Result<BlockPalette, Error> {
+        let mut states = Vec::new();
+        let mut offset = 0;
+        while offset < binary.len() {
+            let (_, tag, len) = binary.read_nbt(offset, Encoding::Network)?;
+            offset += len;
+            let compound = tag
+                .as_compound()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, "block state is no compound"))?;
+            states.push(BlockState::from_compound(compound)?);
+        }
+        if states.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "there are no block states"));
+        }
+        Ok(BlockPalette::new(states))
+    }
+
+    /// Reads a JSON object of block names and their legacy ids
+    pub fn load_legacy_ids(&mut self, json: &str) -> Result<(), Error> {
+        let ids: HashMap<String, Value> = serde_json::from_str(json).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        for (name, id) in ids {
+            let id = id
+                .as_i64()
+                .ok_or_else(|| Error::new(ErrorKind::InvalidData, format!("legacy id of {} is no number", name)))?;
+            let id = i16::try_from(id)
+                .map_err(|_| Error::new(ErrorKind::InvalidData, format!("legacy id {} of {} is out of range", id, name)))?;
+            self.legacy_ids.insert(name, id);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn states(&self) -> &[BlockState] {
+        &self.states
+    }
+
+    pub fn get(&self, runtime_id: u32) -> Option<&BlockState> {
+        self.states.get(runtime_id as usize)
+    }
+
+    /// Finds the runtime id of a block state, the order of the states doesn't matter
+    pub fn runtime_id(&self, name: &str, states: &Compound) -> Option<u32> {
+        self.runtime_ids.get(&BlockState::key(name, states).ok()?).copied()
+    }
+
+    /// Runtime id of the first state of a block, which usually is its default
+    pub fn default_runtime_id(&self, name: &str) -> Option<u32> {
+        self.states.iter().position(|state| state.name == name).map(|runtime_id| runtime_id as u32)
+    }
+
+    /// Returns the legacy id and meta value of a block state, the meta value being the index of
+    /// the state among those of the same block
+    pub fn legacy_id(&self, runtime_id: u32) -> Option<(i16, i16)> {
+        let state = self.get(runtime_id)?;
+        let id = *self.legacy_ids.get(&state.name)?;
+        let meta = self.states[..runtime_id as usize]
+            .iter()
+            .filter(|other| other.name == state.name)
+            .count();
+        Some((id, i16::try_from(meta).ok()?))
+    }
+
+    /// Block palette as sent in the StartGame packet to clients before 1.16.100
+    pub fn legacy_entries(&self) -> Vec<Compound> {
+        self.states
+            .iter()
+            .map(|state| {
+                let mut entry = Compound::new();
+                entry.insert("block".to_string(), Tag::Compound(state.to_compound()));
+                entry.insert("id".to_string(), Tag::Short(self.legacy_ids.get(&state.name).copied().unwrap_or_default()));
+                entry
+            })
+            .collect()
+    }
+}
+
+impl BlockRegistry {
+    /// Registry with only air, for clients of `newest` and older
+    pub fn new(newest: u32) -> BlockRegistry {
+        let mut palettes = HashMap::new();
+        palettes.insert(newest, BlockPalette::fallback());
+        BlockRegistry {
+            newest,
+            palettes,
+            translations: HashMap::new(),
+        }
+    }
+
+    /// Loads the palettes of `protocols` from the subdirectories named after them, e.g.
+    /// "blocks/554/canonical_block_states.nbt". Versions without a palette only get air.
+    pub fn load(directory: &Path, protocols: &[u32]) -> BlockRegistry {
+        let newest = protocols.iter().copied().max().unwrap_or_default();
+        let mut registry = BlockRegistry::new(newest);
+        for protocol in protocols {
+            let path = directory.join(protocol.to_string());
+            match BlockPalette::load(&path) {
+                Ok(palette) => {
+                    info!("Loaded {} block states of protocol {}", palette.len(), protocol);
+                    registry.add_palette(*protocol, palette);
+                }
+                Err(e) => warn!("Failed to load block states from {}: {}", path.display(), e),
+            }
+        }
+        registry
+    }
+
+    /// Adds the palette of a version, the newest one replaces the states chunks are stored with
+    pub fn add_palette(&mut self, protocol: u32, palette: BlockPalette) {
+        self.palettes.insert(protocol, palette);
+        if protocol == self.newest {
+            let others: Vec<u32> = self.translations.keys().copied().collect();
+            self.translations.clear();
+            for other in others {
+                self.translate(other);
+            }
+        } else {
+            self.translate(protocol);
+        }
+    }
+
+    /// Palette chunks are stored with
+    pub fn palette(&self) -> &BlockPalette {
+        &self.palettes[&self.newest]
+    }
+
+    /// Palette of a version, or the newest one if there is none for `protocol`
+    pub fn palette_for(&self, protocol: u32) -> &BlockPalette {
+        self.palettes.get(&protocol).unwrap_or_else(|| self.palette())
+    }
+
+    /// Runtime id of a block state
+    pub fn get(&self, name: &str, states: &Compound) -> Option<u32> {
+        self.palette().runtime_id(name, states)
+    }
+
+    /// Runtime id of the default state of a block
+    pub fn get_default(&self, name: &str) -> Option<u32> {
+        self.palette().default_runtime_id(name)
+    }
+
+    pub fn air(&self) -> u32 {
+        self.get_default(AIR).unwrap_or_default()
+    }
+
+    /// Translates a runtime id of the stored states to the one of clients of `protocol`, blocks
+    /// they don't know become "info_update"
+    pub fn runtime_id(&self, runtime_id: u32, protocol: u32) -> u32 {
+        match self.translations.get(&protocol) {
+            Some(translation) => translation.get(runtime_id as usize).copied().unwrap_or_default(),
+            None => runtime_id,
+        }
+    }
+
+    fn translate(&mut self, protocol: u32) {
+        let palette = &self.palettes[&protocol];
+        let unknown = palette
+            .default_runtime_id(UNKNOWN)
+            .or_else(|| palette.default_runtime_id(AIR))
+            .unwrap_or_default();
+        let translation = self
+            .palette()
+            .states()
+            .iter()
+            .map(|state| palette.runtime_id(&state.name, &state.states).unwrap_or(unknown))
+            .collect();
+        self.translations.insert(protocol, translation);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::buffer::PacketBufferWrite;
+
+    fn wool(color: &str, hidden: bool) -> BlockState {
+        let mut states = Compound::new();
+        states.insert("color".to_string(), Tag::String(color.to_string()));
+        states.insert("hidden".to_string(), Tag::Byte(hidden as i8));
+        BlockState::new("minecraft:wool".to_string(), states, 1)
+    }
+
+    fn palette() -> BlockPalette {
+        BlockPalette::new(vec![
+            BlockState::new(AIR.to_string(), Compound::new(), 1),
+            wool("white", false),
+            wool("red", false),
+            wool("red", true),
+        ])
+    }
+
+    #[test]
+    fn runtime_ids_ignore_the_order_of_states() {
+        let palette = palette();
+        let mut states = Compound::new();
+        states.insert("hidden".to_string(), Tag::Byte(1));
+        states.insert("color".to_string(), Tag::String("red".to_string()));
+        assert_eq!(palette.runtime_id("minecraft:wool", &states), Some(3));
+        assert_eq!(palette.default_runtime_id("minecraft:wool"), Some(1));
+        assert_eq!(palette.runtime_id("minecraft:stone", &Compound::new()), None);
+    }
+
+    #[test]
+    fn from_bytes() {
+        let mut binary = Vec::new();
+        for state in palette().states() {
+            binary.push_nbt("", &Tag::Compound(state.to_compound()), Encoding::Network).unwrap();
+        }
+        let palette = BlockPalette::from_bytes(&binary).unwrap();
+        assert_eq!(palette.states(), self::palette().states());
+        assert!(BlockPalette::from_bytes(&[]).is_err());
+        assert!(BlockPalette::from_bytes(&binary[..binary.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn legacy_ids() {
+        let mut palette = palette();
+        palette.load_legacy_ids(r#"{"minecraft:air": 0, "minecraft:wool": 35}"#).unwrap();
+        assert_eq!(palette.legacy_id(0), Some((0, 0)));
+        assert_eq!(palette.legacy_id(3), Some((35, 2)));
+        assert!(palette.load_legacy_ids(r#"{"minecraft:wool": 32768}"#).is_err());
+        assert!(palette.load_legacy_ids(r#"{"minecraft:wool": "35"}"#).is_err());
+    }
+
+    #[test]
+    fn translation() {
+        let mut registry = BlockRegistry::new(2);
+        registry.add_palette(2, palette());
+        registry.add_palette(
+            1,
+            BlockPalette::new(vec![
+                BlockState::new(AIR.to_string(), Compound::new(), 0),
+                BlockState::new(UNKNOWN.to_string(), Compound::new(), 0),
+                wool("red", false),
+            ]),
+        );
+        assert_eq!(registry.runtime_id(2, 1), 2);
+        assert_eq!(registry.runtime_id(1, 1), 1);
+        assert_eq!(registry.runtime_id(3, 2), 3);
+    }
+}
@@ -0,0 +1,322 @@
+use crate::utils::buffer::PacketBufferWrite;
+use crate::utils::nbt::{Compound, Encoding, Tag};
+use crate::world::biome::PLAINS;
+use crate::world::palette::PalettedStorage;
+use std::io::Error;
+
+/// Lowest block of a chunk
+pub const MIN_Y: i32 = -64;
+/// Block above the highest block of a chunk
+pub const MAX_Y: i32 = 320;
+pub const SUB_CHUNK_COUNT: usize = ((MAX_Y - MIN_Y) / 16) as usize;
+/// First protocol version with chunks from y -64 to 320 and 3D biomes (1.18.0)
+const EXTENDED_HEIGHT_PROTOCOL: u32 = 475;
+/// Sub chunks of older clients only reach from y 0 to 256
+const LEGACY_SUB_CHUNKS: std::ops::Range<usize> = 4..20;
+/// Sub chunk format with block storage layers
+const SUB_CHUNK_VERSION: u8 = 8;
+
+/// 16x16x16 blocks. The first layer holds the blocks, the second one e.g. water in waterlogged
+/// blocks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubChunk {
+    layers: Vec<PalettedStorage>,
+}
+
+/// A 16x16 column of sub chunks with its biomes and block entities
+pub struct Chunk {
+    x: i32,
+    z: i32,
+    /// block state of air, which empty sub chunks consist of
+    air: u32,
+    sub_chunks: Vec<SubChunk>,
+    biomes: Vec<PalettedStorage>,
+    /// y above the highest block which is not air, for each column
+    heightmap: Vec<i32>,
+    block_entities: Vec<Compound>,
+}
+
+impl SubChunk {
+    pub fn new(air: u32) -> SubChunk {
+        SubChunk {
+            layers: vec![PalettedStorage::new(air)],
+        }
+    }
+
+    pub fn layers(&self) -> &[PalettedStorage] {
+        &self.layers
+    }
+
+    /// Whether the sub chunk only consists of air
+    pub fn is_empty(&self, air: u32) -> bool {
+        self.layers.iter().all(|layer| layer.contains_only(air))
+    }
+
+    pub fn get_block(&self, x: usize, y: usize, z: usize, layer: usize) -> Option<u32> {
+        self.layers.get(layer).map(|storage| storage.get(x, y, z))
+    }
+
+    pub fn set_block(&mut self, x: usize, y: usize, z: usize, layer: usize, state: u32, air: u32) {
+        while self.layers.len() <= layer {
+            self.layers.push(PalettedStorage::new(air));
+        }
+        self.layers[layer].set(x, y, z, state);
+    }
+
+    pub fn compact(&mut self) {
+        for layer in &mut self.layers {
+            layer.compact();
+        }
+    }
+
+    /// Encodes the sub chunk as sent to clients, empty sub chunks have no layers at all
+    pub fn encode(&self, mut binary: Vec<u8>, air: u32, runtime_id: &dyn Fn(u32) -> u32, protocol: u32) -> Vec<u8> {
+        binary.push(SUB_CHUNK_VERSION);
+        if self.is_empty(air) {
+            binary.push(0);
+            return binary;
+        }
+        binary.push(self.layers.len() as u8);
+        for layer in &self.layers {
+            binary = layer.encode(binary, runtime_id, protocol >= EXTENDED_HEIGHT_PROTOCOL);
+        }
+
+        binary
+    }
+}
+
+impl Chunk {
+    /// Chunk filled with `air` and plains
+    pub fn new(x: i32, z: i32, air: u32) -> Chunk {
+        Chunk {
+            x,
+            z,
+            air,
+            sub_chunks: vec![SubChunk::new(air); SUB_CHUNK_COUNT],
+            biomes: vec![PalettedStorage::new(PLAINS); SUB_CHUNK_COUNT],
+            heightmap: vec![MIN_Y; 256],
+            block_entities: Vec::new(),
+        }
+    }
+
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+
+    pub fn sub_chunks(&self) -> &[SubChunk] {
+        &self.sub_chunks
+    }
+
+    /// Returns the block state at the chunk relative `x` and `z`, or air outside of the height limits
+    pub fn get_block(&self, x: usize, y: i32, z: usize) -> u32 {
+        self.get_block_layer(x, y, z, 0)
+    }
+
+    pub fn get_block_layer(&self, x: usize, y: i32, z: usize, layer: usize) -> u32 {
+        match sub_chunk_index(y) {
+            Some(index) => self.sub_chunks[index]
+                .get_block(x, (y & 15) as usize, z, layer)
+                .unwrap_or(self.air),
+            None => self.air,
+        }
+    }
+
+    /// Sets the block state at the chunk relative `x` and `z`, blocks outside of the height limits
+    /// are ignored
+    pub fn set_block(&mut self, x: usize, y: i32, z: usize, state: u32) {
+        self.set_block_layer(x, y, z, 0, state);
+    }
+
+    pub fn set_block_layer(&mut self, x: usize, y: i32, z: usize, layer: usize, state: u32) {
+        let index = match sub_chunk_index(y) {
+            Some(index) => index,
+            None => return,
+        };
+        self.sub_chunks[index].set_block(x, (y & 15) as usize, z, layer, state, self.air);
+        if layer != 0 {
+            return;
+        }
+        let height = &mut self.heightmap[(z << 4) | x];
+        if state != self.air && y >= *height {
+            *height = y + 1;
+        } else if state == self.air && y + 1 == *height {
+            self.recalculate_height(x, z);
+        }
+    }
+
+    /// Returns the biome at the chunk relative `x` and `z`, `y` outside of the height limits is
+    /// clamped to them
+    pub fn get_biome(&self, x: usize, y: i32, z: usize) -> u32 {
+        let y = y.clamp(MIN_Y, MAX_Y - 1);
+        let index = sub_chunk_index(y).unwrap();
+        self.biomes[index].get(x, (y & 15) as usize, z)
+    }
+
+    pub fn set_biome(&mut self, x: usize, y: i32, z: usize, biome: u32) {
+        if let Some(index) = sub_chunk_index(y) {
+            self.biomes[index].set(x, (y & 15) as usize, z, biome);
+        }
+    }
+
+    /// Sets the biome of a whole column
+    pub fn set_biome_column(&mut self, x: usize, z: usize, biome: u32) {
+        for y in MIN_Y..MAX_Y {
+            self.set_biome(x, y, z, biome);
+        }
+    }
+
+    /// Sets the biome of the whole chunk
+    pub fn fill_biome(&mut self, biome: u32) {
+        self.biomes = vec![PalettedStorage::new(biome); SUB_CHUNK_COUNT];
+    }
+
+    /// Returns the y above the highest block which is not air, or `MIN_Y` for empty columns
+    pub fn get_height(&self, x: usize, z: usize) -> i32 {
+        self.heightmap[(z << 4) | x]
+    }
+
+    pub fn recalculate_heightmap(&mut self) {
+        for x in 0..16 {
+            for z in 0..16 {
+                self.recalculate_height(x, z);
+            }
+        }
+    }
+
+    pub fn add_block_entity(&mut self, block_entity: Compound) {
+        self.block_entities.push(block_entity);
+    }
+
+    pub fn block_entities(&self) -> &[Compound] {
+        &self.block_entities
+    }
+
+    /// Drops unused palette entries, e.g. after many blocks were replaced
+    pub fn compact(&mut self) {
+        for sub_chunk in &mut self.sub_chunks {
+            sub_chunk.compact();
+        }
+        for biomes in &mut self.biomes {
+            biomes.compact();
+        }
+    }
+
+    /// Returns the sub chunk count and payload of a LevelChunk for clients of `protocol`.
+    /// `runtime_id` maps the stored block states to the runtime ids of that version. Empty sub
+    /// chunks at the top are left out. Fails if a block entity can't be encoded.
+    pub fn network_payload(&self, protocol: u32, runtime_id: &dyn Fn(u32) -> u32) -> Result<(u32, Vec<u8>), Error> {
+        let extended = protocol >= EXTENDED_HEIGHT_PROTOCOL;
+        let sub_chunks = if extended {
+            &self.sub_chunks[..]
+        } else {
+            &self.sub_chunks[LEGACY_SUB_CHUNKS]
+        };
+        let count = sub_chunks
+            .iter()
+            .rposition(|sub_chunk| !sub_chunk.is_empty(self.air))
+            .map_or(0, |index| index + 1);
+        let mut payload = Vec::new();
+        for sub_chunk in &sub_chunks[..count] {
+            payload = sub_chunk.encode(payload, self.air, runtime_id, protocol);
+        }
+        if extended {
+            for biomes in &self.biomes {
+                payload = biomes.encode(payload, &|biome| biome, true);
+            }
+        } else {
+            // one biome per column, taken at the surface
+            for z in 0..16 {
+                for x in 0..16 {
+                    let y = (self.get_height(x, z) - 1).max(0);
+                    payload.push(self.get_biome(x, y, z) as u8);
+                }
+            }
+        }
+        payload.push(0); // border blocks
+        for block_entity in &self.block_entities {
+            payload.push_nbt("", &Tag::Compound(block_entity.clone()), Encoding::Network)?;
+        }
+        Ok((count as u32, payload))
+    }
+
+    fn recalculate_height(&mut self, x: usize, z: usize) {
+        let mut height = MIN_Y;
+        for y in (MIN_Y..MAX_Y).rev() {
+            if self.get_block(x, y, z) != self.air {
+                height = y + 1;
+                break;
+            }
+        }
+        self.heightmap[(z << 4) | x] = height;
+    }
+}
+
+fn sub_chunk_index(y: i32) -> Option<usize> {
+    if !(MIN_Y..MAX_Y).contains(&y) {
+        return None;
+    }
+    Some(((y - MIN_Y) >> 4) as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::biome::{DESERT, OCEAN};
+
+    const AIR: u32 = 0;
+    const STONE: u32 = 1;
+
+    #[test]
+    fn blocks_and_heightmap() {
+        let mut chunk = Chunk::new(0, 0, AIR);
+        chunk.set_block(3, -64, 5, STONE);
+        chunk.set_block(3, 100, 5, STONE);
+        chunk.set_block(3, MAX_Y, 5, STONE);
+        assert_eq!(chunk.get_block(3, -64, 5), STONE);
+        assert_eq!(chunk.get_block(3, 100, 5), STONE);
+        assert_eq!(chunk.get_block(3, MAX_Y, 5), AIR);
+        assert_eq!(chunk.get_height(3, 5), 101);
+        chunk.set_block(3, 100, 5, AIR);
+        assert_eq!(chunk.get_height(3, 5), -63);
+        assert_eq!(chunk.get_height(0, 0), MIN_Y);
+    }
+
+    #[test]
+    fn biomes_outside_of_the_height_limits() {
+        let mut chunk = Chunk::new(0, 0, AIR);
+        chunk.set_biome(0, MIN_Y, 0, OCEAN);
+        chunk.set_biome(0, MAX_Y - 1, 0, DESERT);
+        // -65 & 15 and 320 & 15 would point into the middle of the clamped sub chunks
+        assert_eq!(chunk.get_biome(0, MIN_Y - 1, 0), OCEAN);
+        assert_eq!(chunk.get_biome(0, MAX_Y, 0), DESERT);
+        assert_eq!(chunk.get_biome(0, i32::MIN, 0), OCEAN);
+        assert_eq!(chunk.get_biome(0, 0, 0), PLAINS);
+    }
+
+    #[test]
+    fn network_payload_leaves_out_empty_sub_chunks() {
+        let mut chunk = Chunk::new(0, 0, AIR);
+        assert_eq!(chunk.network_payload(554, &|state| state).unwrap().0, 0);
+        chunk.set_block(0, 0, 0, STONE);
+        // y 0 is the fifth sub chunk since 1.18, but the first one before
+        assert_eq!(chunk.network_payload(554, &|state| state).unwrap().0, 5);
+        assert_eq!(chunk.network_payload(408, &|state| state).unwrap().0, 1);
+    }
+
+    #[test]
+    fn sub_chunk_encoding() {
+        let mut sub_chunk = SubChunk::new(AIR);
+        assert_eq!(sub_chunk.encode(Vec::new(), AIR, &|state| state, 554), vec![SUB_CHUNK_VERSION, 0]);
+        sub_chunk.set_block(1, 2, 3, 0, STONE, AIR);
+        let binary = sub_chunk.encode(Vec::new(), AIR, &|state| state + 10, 554);
+        assert_eq!(&binary[..2], &[SUB_CHUNK_VERSION, 1]);
+        let (storage, len) = PalettedStorage::decode(&binary, 2).unwrap();
+        assert_eq!(len, binary.len() - 2);
+        assert_eq!(storage.get(1, 2, 3), STONE + 10);
+        assert_eq!(storage.get(0, 0, 0), AIR + 10);
+    }
+}
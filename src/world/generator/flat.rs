@@ -0,0 +1,85 @@
+use crate::world::biome;
+use crate::world::block::BlockRegistry;
+use crate::world::chunk::{Chunk, MAX_Y};
+use crate::world::generator::{block, Generator};
+use std::io::{Error, ErrorKind};
+
+/// Layers and biome of a flat world if no options are given
+pub const DEFAULT_PRESET: &str = "minecraft:bedrock,2*minecraft:dirt,minecraft:grass;plains";
+/// y of the lowest layer, clients before 1.18 don't see blocks below 0
+const BOTTOM: i32 = 0;
+/// Layers fitting between the bottom and the top of the world
+const MAX_LAYERS: usize = (MAX_Y - BOTTOM) as usize;
+
+/// Generates chunks consisting of the same layers of blocks everywhere
+pub struct FlatGenerator {
+    air: u32,
+    /// block states from the bottom up
+    layers: Vec<u32>,
+    biome: u32,
+}
+
+impl FlatGenerator {
+    /// Layers above the top of the world are left out
+    pub fn new(air: u32, mut layers: Vec<u32>, biome: u32) -> FlatGenerator {
+        layers.truncate(MAX_LAYERS);
+        FlatGenerator { air, layers, biome }
+    }
+
+    /// Reads a preset of layers from the bottom up and an optional biome, e.g.
+    /// "minecraft:bedrock,2*minecraft:dirt,minecraft:grass;plains". Empty options use the default.
+    pub fn from_options(options: &str, blocks: &BlockRegistry) -> Result<FlatGenerator, Error> {
+        let options = if options.trim().is_empty() { DEFAULT_PRESET } else { options };
+        let (layer_list, biome_name) = match options.split_once(';') {
+            Some((layer_list, biome_name)) => (layer_list, Some(biome_name)),
+            None => (options, None),
+        };
+        let mut layers = Vec::new();
+        for layer in layer_list.split(',').filter(|layer| !layer.trim().is_empty()) {
+            let (count, name) = match layer.split_once('*') {
+                Some((count, name)) => {
+                    let count: usize = count
+                        .trim()
+                        .parse()
+                        .map_err(|_| Error::new(ErrorKind::InvalidInput, format!("invalid layer count in {}", layer)))?;
+                    (count, name)
+                }
+                None => (1, layer),
+            };
+            let state = block(name, blocks)?;
+            // don't allocate layers above the top of the world
+            let count = count.min(MAX_LAYERS.saturating_sub(layers.len()));
+            layers.extend(std::iter::repeat_n(state, count));
+        }
+        let biome = match biome_name {
+            Some(name) => biome::from_name(name)
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("there is no biome called {}", name)))?,
+            None => biome::PLAINS,
+        };
+        Ok(FlatGenerator::new(blocks.air(), layers, biome))
+    }
+
+    /// y above the highest layer
+    pub fn height(&self) -> i32 {
+        BOTTOM + self.layers.len() as i32
+    }
+}
+
+impl Generator for FlatGenerator {
+    fn generate(&self, x: i32, z: i32) -> Chunk {
+        let mut chunk = Chunk::new(x, z, self.air);
+        for (layer, state) in self.layers.iter().enumerate() {
+            for x in 0..16 {
+                for z in 0..16 {
+                    chunk.set_block(x, BOTTOM + layer as i32, z, *state);
+                }
+            }
+        }
+        chunk.fill_biome(self.biome);
+        chunk
+    }
+
+    fn spawn(&self) -> (i32, i32, i32) {
+        (0, self.height(), 0)
+    }
+}
@@ -0,0 +1,60 @@
+pub mod flat;
+pub mod noise;
+pub mod terrain;
+pub mod void;
+
+use crate::world::block::BlockRegistry;
+use crate::world::chunk::Chunk;
+use crate::world::generator::flat::FlatGenerator;
+use crate::world::generator::terrain::TerrainGenerator;
+use crate::world::generator::void::VoidGenerator;
+use std::io::{Error, ErrorKind};
+
+/// Generates the chunks of a world on demand. Chunks may be generated from several threads at
+/// once, so the same coordinates have to result in the same chunk.
+pub trait Generator: Send + Sync {
+    /// Generates the chunk at the chunk coordinates `x` and `z`
+    fn generate(&self, x: i32, z: i32) -> Chunk;
+
+    /// Block players spawn in if the world settings don't set one
+    fn spawn(&self) -> (i32, i32, i32);
+}
+
+/// Creates the generator called `name`, e.g. "normal", "flat" or "void", configured with
+/// `options` and `seed`. Block names are looked up in `blocks`.
+pub fn create(name: &str, options: &str, seed: i64, blocks: &BlockRegistry) -> Result<Box<dyn Generator>, Error> {
+    match name.to_lowercase().as_str() {
+        "normal" => Ok(Box::new(TerrainGenerator::new(seed, blocks)?)),
+        "flat" => Ok(Box::new(FlatGenerator::from_options(options, blocks)?)),
+        "void" => Ok(Box::new(VoidGenerator::from_options(options, blocks)?)),
+        _ => Err(Error::new(ErrorKind::NotFound, format!("there is no generator called {}", name))),
+    }
+}
+
+/// Generator used if the configured one can't be created: a flat world, a platform in the void if
+/// the blocks of the flat world are missing, or nothing but air if no block states were loaded
+pub fn fallback(blocks: &BlockRegistry) -> Box<dyn Generator> {
+    if let Ok(generator) = FlatGenerator::from_options("", blocks) {
+        warn!("Generating a flat world instead");
+        return Box::new(generator);
+    }
+    if let Ok(generator) = VoidGenerator::from_options("", blocks) {
+        warn!("Generating a platform in the void instead");
+        return Box::new(generator);
+    }
+    warn!("There are no blocks to build a world from, generating nothing but air");
+    Box::new(VoidGenerator::new(blocks.air(), None))
+}
+
+/// Runtime id of the default state of the block called `name`
+fn block(name: &str, blocks: &BlockRegistry) -> Result<u32, Error> {
+    let name = name.trim();
+    let name = if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("minecraft:{}", name)
+    };
+    blocks
+        .get_default(&name)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("there is no block called {}", name)))
+}
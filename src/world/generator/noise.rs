@@ -0,0 +1,125 @@
+/// Perlin gradient noise, the same seed always results in the same noise
+pub struct GradientNoise {
+    permutation: [u8; 512],
+}
+
+/// Several octaves of gradient noise with halved amplitude and doubled frequency each
+pub struct OctaveNoise {
+    octaves: Vec<GradientNoise>,
+}
+
+/// Deterministic generator of the seeds and permutations, independent of any crate version
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> SplitMix64 {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl GradientNoise {
+    pub fn new(seed: u64) -> GradientNoise {
+        let mut random = SplitMix64::new(seed);
+        let mut values: Vec<u8> = (0..=255).collect();
+        for i in (1..values.len()).rev() {
+            values.swap(i, (random.next_u64() % (i as u64 + 1)) as usize);
+        }
+        let mut permutation = [0; 512];
+        for (i, value) in permutation.iter_mut().enumerate() {
+            *value = values[i & 255];
+        }
+        GradientNoise { permutation }
+    }
+
+    /// Noise from -1 to 1 in the plane, 0 at whole coordinates
+    pub fn sample_2d(&self, x: f64, z: f64) -> f64 {
+        self.sample_3d(x, 0.0, z)
+    }
+
+    /// Noise from about -1 to 1, 0 at whole coordinates
+    pub fn sample_3d(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (cell_x, cell_y, cell_z) = (x.floor(), y.floor(), z.floor());
+        let (x, y, z) = (x - cell_x, y - cell_y, z - cell_z);
+        let (cell_x, cell_y, cell_z) = (cell_x as i64 as usize & 255, cell_y as i64 as usize & 255, cell_z as i64 as usize & 255);
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        let p = &self.permutation;
+        let a = p[cell_x] as usize + cell_y;
+        let (aa, ab) = (p[a] as usize + cell_z, p[a + 1] as usize + cell_z);
+        let b = p[cell_x + 1] as usize + cell_y;
+        let (ba, bb) = (p[b] as usize + cell_z, p[b + 1] as usize + cell_z);
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, gradient(p[aa], x, y, z), gradient(p[ba], x - 1.0, y, z)),
+                lerp(u, gradient(p[ab], x, y - 1.0, z), gradient(p[bb], x - 1.0, y - 1.0, z)),
+            ),
+            lerp(
+                v,
+                lerp(u, gradient(p[aa + 1], x, y, z - 1.0), gradient(p[ba + 1], x - 1.0, y, z - 1.0)),
+                lerp(u, gradient(p[ab + 1], x, y - 1.0, z - 1.0), gradient(p[bb + 1], x - 1.0, y - 1.0, z - 1.0)),
+            ),
+        )
+    }
+}
+
+impl OctaveNoise {
+    pub fn new(seed: u64, octaves: usize) -> OctaveNoise {
+        let mut random = SplitMix64::new(seed);
+        OctaveNoise {
+            octaves: (0..octaves).map(|_| GradientNoise::new(random.next_u64())).collect(),
+        }
+    }
+
+    /// Noise from about -1 to 1 in the plane, `scale` being the size of the largest features
+    pub fn sample_2d(&self, x: f64, z: f64, scale: f64) -> f64 {
+        self.sample(|noise, frequency| noise.sample_2d(x * frequency, z * frequency), scale)
+    }
+
+    pub fn sample_3d(&self, x: f64, y: f64, z: f64, scale: f64) -> f64 {
+        self.sample(|noise, frequency| noise.sample_3d(x * frequency, y * frequency, z * frequency), scale)
+    }
+
+    fn sample(&self, octave: impl Fn(&GradientNoise, f64) -> f64, scale: f64) -> f64 {
+        let (mut value, mut amplitude, mut total, mut frequency) = (0.0, 1.0, 0.0, 1.0 / scale);
+        for noise in &self.octaves {
+            value += octave(noise, frequency) * amplitude;
+            total += amplitude;
+            amplitude /= 2.0;
+            frequency *= 2.0;
+        }
+        value / total
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+/// Dot product with one of 12 gradients, chosen by the hash
+fn gradient(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
@@ -0,0 +1,173 @@
+use crate::world::biome;
+use crate::world::block::BlockRegistry;
+use crate::world::chunk::{Chunk, MIN_Y};
+use crate::world::generator::noise::{OctaveNoise, SplitMix64};
+use crate::world::generator::{block, Generator};
+use std::io::Error;
+
+/// Water is filled up to this y
+pub const SEA_LEVEL: i32 = 62;
+/// Blocks below the surface of land which consist of dirt or sand
+const SOIL_DEPTH: i32 = 4;
+/// Caves don't reach closer to the ground of oceans than this
+const CAVE_CEILING: i32 = 8;
+/// Half of the thickness of caves in noise values
+const CAVE_WIDTH: f64 = 0.045;
+
+/// Blocks the terrain consists of
+struct Blocks {
+    air: u32,
+    bedrock: u32,
+    stone: u32,
+    dirt: u32,
+    grass: u32,
+    sand: u32,
+    sandstone: u32,
+    gravel: u32,
+    water: u32,
+    ice: u32,
+    snow: u32,
+}
+
+/// Generates hills, oceans and caves from gradient noise, with biomes chosen by height,
+/// temperature and rainfall
+pub struct TerrainGenerator {
+    blocks: Blocks,
+    continents: OctaveNoise,
+    hills: OctaveNoise,
+    temperature: OctaveNoise,
+    rainfall: OctaveNoise,
+    /// caves are where both noises are close to 0
+    caves: (OctaveNoise, OctaveNoise),
+}
+
+impl TerrainGenerator {
+    /// Generator of the world with `seed`, its block names are looked up in `blocks`
+    pub fn new(seed: i64, blocks: &BlockRegistry) -> Result<TerrainGenerator, Error> {
+        let mut random = SplitMix64::new(seed as u64);
+        Ok(TerrainGenerator {
+            blocks: Blocks {
+                air: blocks.air(),
+                bedrock: block("bedrock", blocks)?,
+                stone: block("stone", blocks)?,
+                dirt: block("dirt", blocks)?,
+                grass: block("grass", blocks)?,
+                sand: block("sand", blocks)?,
+                sandstone: block("sandstone", blocks)?,
+                gravel: block("gravel", blocks)?,
+                water: block("water", blocks)?,
+                ice: block("ice", blocks)?,
+                snow: block("snow_layer", blocks)?,
+            },
+            continents: OctaveNoise::new(random.next_u64(), 4),
+            hills: OctaveNoise::new(random.next_u64(), 5),
+            temperature: OctaveNoise::new(random.next_u64(), 3),
+            rainfall: OctaveNoise::new(random.next_u64(), 3),
+            caves: (OctaveNoise::new(random.next_u64(), 2), OctaveNoise::new(random.next_u64(), 2)),
+        })
+    }
+
+    /// y above the highest terrain block of a column, before caves are carved
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let (x, z) = (x as f64, z as f64);
+        let continent = self.continents.sample_2d(x, z, 512.0);
+        // mountains only rise further inland
+        let hills = (self.hills.sample_2d(x, z, 96.0) + 0.3).max(0.0) * (continent + 0.2).max(0.0);
+        (SEA_LEVEL as f64 + 2.0 + continent * 48.0 + hills * 96.0) as i32
+    }
+
+    /// Biome of a column with the surface at `height`
+    pub fn biome(&self, x: i32, z: i32, height: i32) -> u32 {
+        let temperature = self.temperature.sample_2d(x as f64, z as f64, 384.0);
+        let rainfall = self.rainfall.sample_2d(x as f64, z as f64, 384.0);
+        if height < SEA_LEVEL - 2 {
+            biome::OCEAN
+        } else if height <= SEA_LEVEL + 1 {
+            if temperature < -0.25 {
+                biome::ICE_PLAINS
+            } else {
+                biome::BEACH
+            }
+        } else if height > SEA_LEVEL + 40 {
+            biome::EXTREME_HILLS
+        } else if temperature < -0.25 {
+            if rainfall > 0.0 {
+                biome::TAIGA
+            } else {
+                biome::ICE_PLAINS
+            }
+        } else if temperature > 0.25 && rainfall < 0.0 {
+            biome::DESERT
+        } else if rainfall > 0.2 {
+            biome::FOREST
+        } else {
+            biome::PLAINS
+        }
+    }
+
+    fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        let (x, y, z) = (x as f64, y as f64, z as f64);
+        self.caves.0.sample_3d(x, y * 1.5, z, 64.0).abs() < CAVE_WIDTH
+            && self.caves.1.sample_3d(x, y * 1.5, z, 64.0).abs() < CAVE_WIDTH
+    }
+
+    /// Top and filler block of a biome
+    fn surface(&self, biome: u32, height: i32) -> (u32, u32) {
+        let blocks = &self.blocks;
+        match biome {
+            biome::DESERT | biome::BEACH => (blocks.sand, blocks.sandstone),
+            biome::OCEAN if height < SEA_LEVEL - 12 => (blocks.gravel, blocks.gravel),
+            biome::OCEAN => (blocks.sand, blocks.sand),
+            biome::EXTREME_HILLS if height > SEA_LEVEL + 60 => (blocks.stone, blocks.stone),
+            _ => (blocks.grass, blocks.dirt),
+        }
+    }
+}
+
+impl Generator for TerrainGenerator {
+    fn generate(&self, chunk_x: i32, chunk_z: i32) -> Chunk {
+        let blocks = &self.blocks;
+        let mut chunk = Chunk::new(chunk_x, chunk_z, blocks.air);
+        for x in 0..16 {
+            for z in 0..16 {
+                let (world_x, world_z) = ((chunk_x << 4) + x as i32, (chunk_z << 4) + z as i32);
+                let height = self.height(world_x, world_z);
+                let biome = self.biome(world_x, world_z, height);
+                let (top, filler) = self.surface(biome, height);
+                chunk.set_block(x, MIN_Y, z, blocks.bedrock);
+                // under water caves stay below the ground, so no water floats above them
+                let cave_ceiling = if height <= SEA_LEVEL { height - CAVE_CEILING } else { height };
+                for y in MIN_Y + 1..height {
+                    if y > MIN_Y + 4 && y < cave_ceiling && self.is_cave(world_x, y, world_z) {
+                        continue;
+                    }
+                    let state = if y == height - 1 {
+                        top
+                    } else if y >= height - SOIL_DEPTH {
+                        filler
+                    } else {
+                        blocks.stone
+                    };
+                    chunk.set_block(x, y, z, state);
+                }
+                for y in height..=SEA_LEVEL {
+                    let state = if y == SEA_LEVEL && biome == biome::ICE_PLAINS { blocks.ice } else { blocks.water };
+                    chunk.set_block(x, y, z, state);
+                }
+                if biome == biome::ICE_PLAINS && height > SEA_LEVEL && chunk.get_block(x, height - 1, z) == top {
+                    chunk.set_block(x, height, z, blocks.snow);
+                }
+                chunk.set_biome_column(x, z, biome);
+            }
+        }
+        chunk
+    }
+
+    fn spawn(&self) -> (i32, i32, i32) {
+        // the first dry column along the x axis
+        (0..4096)
+            .map(|x| (x, self.height(x, 0)))
+            .find(|(_, height)| *height > SEA_LEVEL + 1)
+            .map_or((0, SEA_LEVEL + 1, 0), |(x, height)| (x, height, 0))
+    }
+}
@@ -0,0 +1,56 @@
+use crate::world::block::BlockRegistry;
+use crate::world::chunk::Chunk;
+use crate::world::generator::{block, Generator};
+use std::io::Error;
+
+/// Block of the spawn platform if no options are given
+pub const DEFAULT_PLATFORM: &str = "minecraft:stone";
+/// Players spawn above the platform in the middle of the world
+const SPAWN: (i32, i32, i32) = (0, 64, 0);
+/// Blocks the platform reaches from the spawn in each direction
+const PLATFORM_RADIUS: i32 = 2;
+
+/// Generates empty chunks, with a platform to spawn on
+pub struct VoidGenerator {
+    air: u32,
+    platform: Option<u32>,
+}
+
+impl VoidGenerator {
+    /// Generator without a platform if `platform` is None
+    pub fn new(air: u32, platform: Option<u32>) -> VoidGenerator {
+        VoidGenerator { air, platform }
+    }
+
+    /// Reads the block the platform consists of, "none" leaves it out
+    pub fn from_options(options: &str, blocks: &BlockRegistry) -> Result<VoidGenerator, Error> {
+        let platform = match options.trim() {
+            "none" => None,
+            "" => Some(block(DEFAULT_PLATFORM, blocks)?),
+            name => Some(block(name, blocks)?),
+        };
+        Ok(VoidGenerator::new(blocks.air(), platform))
+    }
+}
+
+impl Generator for VoidGenerator {
+    fn generate(&self, x: i32, z: i32) -> Chunk {
+        let mut chunk = Chunk::new(x, z, self.air);
+        let platform = match self.platform {
+            Some(platform) => platform,
+            None => return chunk,
+        };
+        for block_x in SPAWN.0 - PLATFORM_RADIUS..=SPAWN.0 + PLATFORM_RADIUS {
+            for block_z in SPAWN.2 - PLATFORM_RADIUS..=SPAWN.2 + PLATFORM_RADIUS {
+                if block_x >> 4 == x && block_z >> 4 == z {
+                    chunk.set_block((block_x & 15) as usize, SPAWN.1 - 1, (block_z & 15) as usize, platform);
+                }
+            }
+        }
+        chunk
+    }
+
+    fn spawn(&self) -> (i32, i32, i32) {
+        SPAWN
+    }
+}
@@ -0,0 +1,169 @@
+use crate::world::chunk::Chunk;
+use crate::world::generator::Generator;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+
+/// Generated chunks which are kept at most, the least recently requested ones are dropped first
+const MAX_CACHED_CHUNKS: usize = 4096;
+
+/// Generates chunks on a pool of worker threads and keeps them once they are done, so whoever
+/// requests them never waits for the generator
+pub struct ChunkLoader {
+    generator: Arc<dyn Generator>,
+    jobs: Sender<(i32, i32)>,
+    results: Receiver<Chunk>,
+    /// chunks which are being generated
+    pending: HashSet<(i32, i32)>,
+    /// generated chunks and the generation they were last requested in
+    chunks: HashMap<(i32, i32), (Chunk, u64)>,
+    /// generated chunks by the generation they were requested in, least recently requested
+    /// first. Entries whose chunk was requested again since are stale and skipped.
+    order: VecDeque<((i32, i32), u64)>,
+    generation: u64,
+}
+
+impl ChunkLoader {
+    /// Starts `workers` threads generating chunks with `generator`, they stop once the loader is
+    /// dropped. Chunks the generator panics on are replaced by chunks of `air`.
+    pub fn new(generator: Arc<dyn Generator>, air: u32, workers: usize) -> ChunkLoader {
+        let (jobs, job_receiver) = mpsc::channel::<(i32, i32)>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        for worker in 0..workers.max(1) {
+            let generator = generator.clone();
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            thread::Builder::new()
+                .name(format!("Chunk Generator {}", worker))
+                .spawn(move || loop {
+                    let job = job_receiver.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                    let (x, z) = match job {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    let chunk = panic::catch_unwind(AssertUnwindSafe(|| generator.generate(x, z))).unwrap_or_else(|_| {
+                        error!("Failed to generate chunk {} {}, sending an empty one", x, z);
+                        Chunk::new(x, z, air)
+                    });
+                    if result_sender.send(chunk).is_err() {
+                        break;
+                    }
+                })
+                .expect("Could not start chunk generator");
+        }
+        ChunkLoader {
+            generator,
+            jobs,
+            results,
+            pending: HashSet::new(),
+            chunks: HashMap::new(),
+            order: VecDeque::new(),
+            generation: 0,
+        }
+    }
+
+    pub fn generator(&self) -> &dyn Generator {
+        self.generator.as_ref()
+    }
+
+    /// Returns the chunk if it has been generated
+    pub fn get(&self, x: i32, z: i32) -> Option<&Chunk> {
+        self.chunks.get(&(x, z)).map(|(chunk, _)| chunk)
+    }
+
+    /// Queues the chunk for generation unless it is generated or queued already
+    pub fn request(&mut self, x: i32, z: i32) {
+        if self.chunks.contains_key(&(x, z)) {
+            // keep it cached for longer
+            self.touch((x, z));
+            return;
+        }
+        if !self.pending.insert((x, z)) {
+            return;
+        }
+        if self.jobs.send((x, z)).is_err() {
+            error!("Chunk generators have stopped, chunk {} {} won't be generated", x, z);
+        }
+    }
+
+    /// Keeps the chunks generated since the last call and returns their coordinates
+    pub fn poll(&mut self) -> Vec<(i32, i32)> {
+        let mut generated = Vec::new();
+        while let Ok(chunk) = self.results.try_recv() {
+            let position = (chunk.x(), chunk.z());
+            self.pending.remove(&position);
+            self.chunks.insert(position, (chunk, 0));
+            self.touch(position);
+            generated.push(position);
+        }
+        while self.chunks.len() > MAX_CACHED_CHUNKS {
+            let (position, generation) = match self.order.pop_front() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if self.chunks.get(&position).is_some_and(|(_, used)| *used == generation) {
+                self.chunks.remove(&position);
+            }
+        }
+        generated
+    }
+
+    /// Marks the chunk at `position` as the most recently requested one
+    fn touch(&mut self, position: (i32, i32)) {
+        self.generation += 1;
+        if let Some((_, used)) = self.chunks.get_mut(&position) {
+            *used = self.generation;
+        }
+        self.order.push_back((position, self.generation));
+        // drop the stale entries once they outnumber the chunks
+        if self.order.len() > 2 * self.chunks.len() + 64 {
+            let chunks = &self.chunks;
+            self.order
+                .retain(|(position, generation)| chunks.get(position).is_some_and(|(_, used)| used == generation));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::generator::void::VoidGenerator;
+    use std::time::{Duration, Instant};
+
+    /// Polls until all requested chunks have been generated
+    fn wait(loader: &mut ChunkLoader) {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !loader.pending.is_empty() {
+            assert!(Instant::now() < deadline, "chunks were not generated in time");
+            loader.poll();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn least_recently_requested_chunks_are_dropped() {
+        let mut loader = ChunkLoader::new(Arc::new(VoidGenerator::new(0, None)), 0, 1);
+        for x in 0..MAX_CACHED_CHUNKS as i32 {
+            loader.request(x, 0);
+        }
+        wait(&mut loader);
+        assert_eq!(loader.chunks.len(), MAX_CACHED_CHUNKS);
+        loader.request(0, 0);
+        for x in 0..10 {
+            loader.request(x, 1);
+        }
+        wait(&mut loader);
+        assert_eq!(loader.chunks.len(), MAX_CACHED_CHUNKS);
+        assert!(loader.get(0, 0).is_some());
+        assert!((1..=10).all(|x| loader.get(x, 0).is_none()));
+        assert!(loader.get(11, 0).is_some());
+        assert!((0..10).all(|x| loader.get(x, 1).is_some()));
+        // requesting again queues a chunk which was dropped
+        loader.request(1, 0);
+        wait(&mut loader);
+        assert!(loader.get(1, 0).is_some());
+    }
+}
@@ -0,0 +1,160 @@
+pub mod biome;
+pub mod block;
+pub mod chunk;
+pub mod generator;
+pub mod loader;
+pub mod palette;
+
+use rand::random;
+use std::thread;
+
+/// Game mode of players joining the world
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(i32)]
+pub enum GameMode {
+    Survival = 0,
+    Creative = 1,
+    Adventure = 2,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(i32)]
+pub enum Difficulty {
+    Peaceful = 0,
+    Easy = 1,
+    Normal = 2,
+    Hard = 3,
+}
+
+impl GameMode {
+    /// Name shown in the server list
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameMode::Survival => "Survival",
+            GameMode::Creative => "Creative",
+            GameMode::Adventure => "Adventure",
+        }
+    }
+}
+
+/// Settings of the world players spawn in
+pub struct WorldSettings {
+    name: String,
+    seed: i64,
+    gamemode: GameMode,
+    difficulty: Difficulty,
+    spawn: Option<(i32, i32, i32)>,
+    view_distance: u32,
+    time: i32,
+    generator: String,
+    generator_options: String,
+    generator_workers: usize,
+}
+
+impl WorldSettings {
+    pub fn new(name: String) -> WorldSettings {
+        WorldSettings {
+            name,
+            seed: random(),
+            gamemode: GameMode::Survival,
+            difficulty: Difficulty::Normal,
+            spawn: None,
+            view_distance: 8,
+            time: 0,
+            generator: "flat".to_string(),
+            generator_options: String::new(),
+            // the network thread keeps a core for itself
+            generator_workers: thread::available_parallelism().map_or(1, |cores| cores.get().saturating_sub(1).max(1)),
+        }
+    }
+
+    /// Sets the name of the world, also shown in the server list
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the seed of the generator, a random one is used unless it is set
+    pub fn set_seed(&mut self, seed: i64) {
+        self.seed = seed;
+    }
+
+    pub fn get_seed(&self) -> i64 {
+        self.seed
+    }
+
+    pub fn set_gamemode(&mut self, gamemode: GameMode) {
+        self.gamemode = gamemode;
+    }
+
+    pub fn get_gamemode(&self) -> GameMode {
+        self.gamemode
+    }
+
+    pub fn set_difficulty(&mut self, difficulty: Difficulty) {
+        self.difficulty = difficulty;
+    }
+
+    pub fn get_difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    /// Sets the block players spawn at, None lets the generator choose
+    pub fn set_spawn(&mut self, spawn: Option<(i32, i32, i32)>) {
+        self.spawn = spawn;
+    }
+
+    pub fn get_spawn(&self) -> Option<(i32, i32, i32)> {
+        self.spawn
+    }
+
+    /// Sets the radius in chunks which is sent to clients at most
+    pub fn set_view_distance(&mut self, view_distance: u32) {
+        self.view_distance = view_distance.max(1);
+    }
+
+    pub fn get_view_distance(&self) -> u32 {
+        self.view_distance
+    }
+
+    /// Sets the time of day in ticks
+    pub fn set_time(&mut self, time: i32) {
+        self.time = time;
+    }
+
+    pub fn get_time(&self) -> i32 {
+        self.time
+    }
+
+    /// Sets the generator the server creates when it starts, see `generator::create`
+    pub fn set_generator(&mut self, generator: String, generator_options: String) {
+        self.generator = generator;
+        self.generator_options = generator_options;
+    }
+
+    pub fn get_generator(&self) -> &str {
+        &self.generator
+    }
+
+    pub fn get_generator_options(&self) -> &str {
+        &self.generator_options
+    }
+
+    /// Sets the amount of threads chunks are generated on
+    pub fn set_generator_workers(&mut self, generator_workers: usize) {
+        self.generator_workers = generator_workers.max(1);
+    }
+
+    pub fn get_generator_workers(&self) -> usize {
+        self.generator_workers
+    }
+}
+
+impl Default for WorldSettings {
+    fn default() -> Self {
+        WorldSettings::new("world".to_string())
+    }
+}
@@ -0,0 +1,295 @@
+use crate::utils::buffer::{PacketBufferRead, PacketBufferWrite};
+use std::io::{Error, ErrorKind};
+
+/// Amount of values in a 16x16x16 storage
+pub const STORAGE_SIZE: usize = 4096;
+
+/// Bits per value the client understands, a storage with one value needs none
+const BITS_PER_VALUE: [u8; 9] = [0, 1, 2, 3, 4, 5, 6, 8, 16];
+
+/// 16x16x16 values, stored as indices into a palette which are packed into words
+#[derive(Clone, Debug, PartialEq)]
+pub struct PalettedStorage {
+    bits_per_value: u8,
+    words: Vec<u32>,
+    palette: Vec<u32>,
+}
+
+impl PalettedStorage {
+    /// Storage filled with `value`
+    pub fn new(value: u32) -> PalettedStorage {
+        PalettedStorage {
+            bits_per_value: 0,
+            words: Vec::new(),
+            palette: vec![value],
+        }
+    }
+
+    /// Index of a position, the game stores values in XZY order
+    pub fn index(x: usize, y: usize, z: usize) -> usize {
+        (x << 8) | (z << 4) | y
+    }
+
+    pub fn bits_per_value(&self) -> u8 {
+        self.bits_per_value
+    }
+
+    pub fn palette(&self) -> &[u32] {
+        &self.palette
+    }
+
+    /// Whether the storage only holds one value
+    pub fn is_uniform(&self) -> bool {
+        self.palette.len() == 1
+    }
+
+    /// Whether all positions hold `value`, unused palette entries are ignored
+    pub fn contains_only(&self, value: u32) -> bool {
+        match self.palette.iter().position(|entry| *entry == value) {
+            Some(palette_index) => {
+                self.is_uniform() || (0..STORAGE_SIZE).all(|index| self.palette_index(index) == palette_index)
+            }
+            None => false,
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> u32 {
+        self.palette[self.palette_index(PalettedStorage::index(x, y, z))]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, value: u32) {
+        let palette_index = match self.palette.iter().position(|entry| *entry == value) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(value);
+                if self.palette.len() > 1 << self.bits_per_value {
+                    self.resize(self.palette.len());
+                }
+                self.palette.len() - 1
+            }
+        };
+        self.set_palette_index(PalettedStorage::index(x, y, z), palette_index);
+    }
+
+    /// Removes unused palette entries and packs the indices as tight as possible
+    pub fn compact(&mut self) {
+        let indices: Vec<usize> = (0..STORAGE_SIZE).map(|index| self.palette_index(index)).collect();
+        let mut used = vec![false; self.palette.len()];
+        for palette_index in &indices {
+            used[*palette_index] = true;
+        }
+        let mut palette = Vec::new();
+        let mut remapped = vec![0; self.palette.len()];
+        for (palette_index, value) in self.palette.iter().enumerate() {
+            if used[palette_index] {
+                remapped[palette_index] = palette.len();
+                palette.push(*value);
+            }
+        }
+        let mut compacted = PalettedStorage::with_palette(palette);
+        for (index, palette_index) in indices.into_iter().enumerate() {
+            compacted.set_palette_index(index, remapped[palette_index]);
+        }
+        *self = compacted;
+    }
+
+    /// Encodes the storage with runtime ids, as sent to clients. `runtime_id` maps the stored
+    /// values. Clients before 1.18 need at least one bit per value.
+    pub fn encode(&self, mut binary: Vec<u8>, runtime_id: &dyn Fn(u32) -> u32, uniform_allowed: bool) -> Vec<u8> {
+        if !uniform_allowed && self.bits_per_value == 0 {
+            let mut storage = self.clone();
+            storage.resize(2);
+            return storage.encode(binary, runtime_id, false);
+        }
+        binary.push((self.bits_per_value << 1) | 1);
+        if self.bits_per_value == 0 {
+            binary.push_var_i32(runtime_id(self.palette[0]) as i32);
+            return binary;
+        }
+        for word in &self.words {
+            binary.push_u32_le(*word);
+        }
+        binary.push_var_i32(self.palette.len() as i32);
+        for value in &self.palette {
+            binary.push_var_i32(runtime_id(*value) as i32);
+        }
+
+        binary
+    }
+
+    /// Decodes a storage with runtime ids at `offset`, returns it and the amount of bytes read
+    pub fn decode(binary: &[u8], offset: usize) -> Result<(PalettedStorage, usize), Error> {
+        let header = *binary
+            .get(offset)
+            .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "storage is truncated"))?;
+        let bits_per_value = header >> 1;
+        if header & 1 == 0 || !BITS_PER_VALUE.contains(&bits_per_value) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid storage header 0x{:02x}", header)));
+        }
+        let mut position = offset + 1;
+        if bits_per_value == 0 {
+            let (value, len) = binary.read_var_i32(position)?;
+            return Ok((PalettedStorage::new(value as u32), len + 1));
+        }
+        let word_count = word_count(bits_per_value);
+        if binary.len() < position + word_count * 4 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "storage is truncated"));
+        }
+        let words = (0..word_count).map(|word| binary.read_u32_le(position + word * 4)).collect();
+        position += word_count * 4;
+        let (palette_size, len) = binary.read_var_i32(position)?;
+        position += len;
+        if palette_size < 1 || palette_size as usize > STORAGE_SIZE {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid palette size {}", palette_size)));
+        }
+        let mut palette = Vec::with_capacity(palette_size as usize);
+        for _ in 0..palette_size {
+            let (value, len) = binary.read_var_i32(position)?;
+            position += len;
+            palette.push(value as u32);
+        }
+        let storage = PalettedStorage {
+            bits_per_value,
+            words,
+            palette,
+        };
+        if (0..STORAGE_SIZE).any(|index| storage.palette_index(index) >= storage.palette.len()) {
+            return Err(Error::new(ErrorKind::InvalidData, "storage references values outside of its palette"));
+        }
+        Ok((storage, position - offset))
+    }
+
+    fn with_palette(palette: Vec<u32>) -> PalettedStorage {
+        let bits_per_value = bits_for(palette.len());
+        PalettedStorage {
+            bits_per_value,
+            words: vec![0; word_count(bits_per_value)],
+            palette,
+        }
+    }
+
+    /// Repacks the indices with enough bits for `palette_size` values
+    fn resize(&mut self, palette_size: usize) {
+        let indices: Vec<usize> = (0..STORAGE_SIZE).map(|index| self.palette_index(index)).collect();
+        self.bits_per_value = bits_for(palette_size);
+        self.words = vec![0; word_count(self.bits_per_value)];
+        for (index, palette_index) in indices.into_iter().enumerate() {
+            self.set_palette_index(index, palette_index);
+        }
+    }
+
+    fn palette_index(&self, index: usize) -> usize {
+        if self.bits_per_value == 0 {
+            return 0;
+        }
+        let values_per_word = 32 / self.bits_per_value as usize;
+        let word = self.words[index / values_per_word];
+        let shift = (index % values_per_word) * self.bits_per_value as usize;
+        ((word >> shift) & ((1 << self.bits_per_value) - 1)) as usize
+    }
+
+    fn set_palette_index(&mut self, index: usize, palette_index: usize) {
+        if self.bits_per_value == 0 {
+            return;
+        }
+        let values_per_word = 32 / self.bits_per_value as usize;
+        let shift = (index % values_per_word) * self.bits_per_value as usize;
+        let mask = ((1u32 << self.bits_per_value) - 1) << shift;
+        let word = &mut self.words[index / values_per_word];
+        *word = (*word & !mask) | ((palette_index as u32) << shift);
+    }
+}
+
+/// Smallest supported amount of bits to index `palette_size` values
+fn bits_for(palette_size: usize) -> u8 {
+    *BITS_PER_VALUE
+        .iter()
+        .find(|bits| 1usize << **bits >= palette_size)
+        .unwrap_or(&16)
+}
+
+/// Words needed for a storage, values are not split across words so some bits may be unused
+fn word_count(bits_per_value: u8) -> usize {
+    if bits_per_value == 0 {
+        return 0;
+    }
+    STORAGE_SIZE.div_ceil(32 / bits_per_value as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(storage: &PalettedStorage, uniform_allowed: bool) -> PalettedStorage {
+        let binary = storage.encode(Vec::new(), &|value| value, uniform_allowed);
+        let (decoded, len) = PalettedStorage::decode(&binary, 0).unwrap();
+        assert_eq!(len, binary.len());
+        decoded
+    }
+
+    #[test]
+    fn uniform_round_trip() {
+        let storage = PalettedStorage::new(7);
+        assert_eq!(round_trip(&storage, true), storage);
+        // older clients get one bit per value
+        let decoded = round_trip(&storage, false);
+        assert_eq!(decoded.bits_per_value(), 1);
+        assert!(decoded.contains_only(7));
+    }
+
+    #[test]
+    fn round_trip_grows_bits_per_value() {
+        let mut storage = PalettedStorage::new(0);
+        for value in 1..=300u32 {
+            storage.set((value % 16) as usize, (value / 16 % 16) as usize, (value / 256) as usize, value);
+        }
+        assert_eq!(storage.bits_per_value(), 16);
+        let decoded = round_trip(&storage, true);
+        for value in 1..=300u32 {
+            assert_eq!(decoded.get((value % 16) as usize, (value / 16 % 16) as usize, (value / 256) as usize), value);
+        }
+        assert_eq!(decoded.get(15, 15, 15), 0);
+    }
+
+    #[test]
+    fn compact() {
+        let mut storage = PalettedStorage::new(0);
+        for value in 1..20 {
+            storage.set(value % 16, value / 16, 0, value as u32);
+        }
+        for value in 1..20 {
+            storage.set(value % 16, value / 16, 0, 0);
+        }
+        storage.set(1, 1, 1, 5);
+        storage.compact();
+        assert_eq!(storage.palette(), &[0, 5]);
+        assert_eq!(storage.bits_per_value(), 1);
+        assert_eq!(storage.get(1, 1, 1), 5);
+    }
+
+    #[test]
+    fn decode_rejects_malformed_storages() {
+        // network storages have the lowest bit set, 7 bits per value don't exist
+        assert!(PalettedStorage::decode(&[0x00, 0x00], 0).is_err());
+        assert!(PalettedStorage::decode(&[(7 << 1) | 1], 0).is_err());
+        assert!(PalettedStorage::decode(&[], 0).is_err());
+        assert!(PalettedStorage::decode(&[0x01], 0).is_err());
+
+        let mut storage = PalettedStorage::new(0);
+        storage.set(0, 0, 0, 1);
+        let binary = storage.encode(Vec::new(), &|value| value, true);
+        assert!(PalettedStorage::decode(&binary[..binary.len() - 1], 0).is_err());
+        assert!(PalettedStorage::decode(&binary[..100], 0).is_err());
+
+        // one palette entry, but an index pointing to a second one
+        let mut binary = binary[..1 + 512].to_vec();
+        binary.push_var_i32(1);
+        binary.push_var_i32(0);
+        assert!(PalettedStorage::decode(&binary, 0).is_err());
+
+        let mut binary = vec![(1 << 1) | 1];
+        binary.extend(vec![0; 512]);
+        binary.push_var_i32(0);
+        assert!(PalettedStorage::decode(&binary, 0).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Client which connects to a server on the loopback interface the way the game does
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use limonite::bedrock::batch::Batch;
+use limonite::bedrock::compression::Compression;
+use limonite::bedrock::encryption::Encryption;
+use limonite::bedrock::jwt;
+use limonite::bedrock::login::Login;
+use limonite::bedrock::packets::{Packet, RequestNetworkSettings, ServerToClientHandshake};
+use limonite::bedrock::registry::PacketRegistry;
+use limonite::protocol::frame::{FrameReceiver, FrameSender};
+use limonite::protocol::inbound::UnconnectedPong;
+use limonite::protocol::outbound::UnconnectedPing;
+use limonite::protocol::packet::PacketType;
+use limonite::protocol::{PacketId, RakNetSettings, ADDRESS_COUNT, MAGIC};
+use limonite::server::Server;
+use limonite::utils::buffer::PacketBufferWrite;
+use p384::ecdsa::SigningKey;
+use p384::pkcs8::{DecodePublicKey, EncodePublicKey};
+use p384::PublicKey;
+use rand::rngs::OsRng;
+use serde_json::json;
+use std::collections::VecDeque;
+use std::net::{SocketAddr, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MTU_SIZE: i16 = 1400;
+/// Time to wait for an answer of the server
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Starts a server in offline mode on a free loopback port
+pub fn start_server(configure: impl FnOnce(&mut Server) + Send + 'static) -> SocketAddr {
+    let address = UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let mut settings = RakNetSettings::new(10, address.to_string());
+    settings.set_lan_discovery(false);
+    thread::spawn(move || {
+        let mut server = Server::new(settings);
+        server.bedrock_settings.set_offline_mode(true);
+        configure(&mut server);
+        server.start();
+    });
+    address
+}
+
+pub struct TestClient {
+    sock: UdpSocket,
+    server: SocketAddr,
+    guid: u64,
+    key: SigningKey,
+    frames: FrameSender,
+    received_frames: FrameReceiver,
+    received: VecDeque<Vec<u8>>,
+    pub protocol: u32,
+    pub compression: Compression,
+    pub encryption: Option<Encryption>,
+}
+
+impl TestClient {
+    pub fn new(server: SocketAddr) -> TestClient {
+        let sock = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sock.set_read_timeout(Some(Duration::from_millis(100))).unwrap();
+        TestClient {
+            sock,
+            server,
+            guid: rand::random(),
+            key: SigningKey::random(&mut OsRng),
+            frames: FrameSender::default(),
+            received_frames: FrameReceiver::default(),
+            received: VecDeque::new(),
+            protocol: *PacketRegistry::protocols().last().unwrap(),
+            compression: Compression::uncompressed(),
+            encryption: None,
+        }
+    }
+
+    pub fn registry(&self) -> &'static PacketRegistry {
+        PacketRegistry::for_protocol(self.protocol).unwrap()
+    }
+
+    /// Pings the server until it answers, it may still be starting
+    pub fn ping(&self) -> UnconnectedPong {
+        let deadline = Instant::now() + TIMEOUT;
+        while Instant::now() < deadline {
+            self.send(&UnconnectedPing::create(0, self.guid).encode(Vec::new()));
+            if let Some(pong) = self.recv().filter(|pong| pong[0] == PacketId::UnconnectedPong as u8) {
+                return UnconnectedPong::decode(&pong).unwrap();
+            }
+        }
+        panic!("server did not answer the ping");
+    }
+
+    /// Completes the offline handshake and the connection request
+    pub fn connect(&mut self) {
+        let mut request_1 = vec![PacketId::ConnectionRequest1 as u8];
+        request_1.extend_from_slice(&MAGIC);
+        request_1.push(10);
+        request_1.resize(MTU_SIZE as usize, 0);
+        self.send(&request_1);
+        assert_eq!(self.recv_raw()[0], PacketId::ConnectionReply1 as u8);
+
+        let mut request_2 = vec![PacketId::ConnectionRequest2 as u8];
+        request_2.extend_from_slice(&MAGIC);
+        request_2.push_address(self.server);
+        request_2.push_i16(MTU_SIZE);
+        request_2.push_u64(self.guid);
+        self.send(&request_2);
+        assert_eq!(self.recv_raw()[0], PacketId::ConnectionReply2 as u8);
+
+        let mut request = vec![PacketId::ConnectionRequest as u8];
+        request.push_u64(self.guid);
+        request.push_u64(now());
+        request.push(0);
+        self.send_packet(&request);
+        assert_eq!(self.recv_packet()[0], PacketId::ConnectionRequestAccepted as u8);
+
+        let mut new_connection = vec![PacketId::NewIncomingConnection as u8];
+        new_connection.push_address(self.server);
+        for _ in 0..ADDRESS_COUNT {
+            new_connection.push_address(self.sock.local_addr().unwrap());
+        }
+        new_connection.push_u64(now());
+        new_connection.push_u64(now());
+        self.send_packet(&new_connection);
+    }
+
+    /// Negotiates the compression of the session
+    pub fn request_network_settings(&mut self) {
+        let request = Packet::RequestNetworkSettings(RequestNetworkSettings::create(self.protocol as i32));
+        self.send_game_packets(vec![request]);
+        let settings = match self.recv_game_packets().remove(0) {
+            Packet::NetworkSettings(settings) => settings,
+            _ => panic!("expected NetworkSettings"),
+        };
+        let mut compression = Compression::new(settings.compression_algorithm());
+        compression.set_threshold(settings.compression_threshold() as usize);
+        compression.set_algorithm_header(self.registry().algorithm_header());
+        self.compression = compression;
+    }
+
+    /// Logs in with a chain signed by the client itself, which needs offline mode
+    pub fn login(&mut self) {
+        let public_key = STANDARD.encode(self.key.verifying_key().to_public_key_der().unwrap().as_bytes());
+        let header = json!({ "alg": "ES384", "x5u": public_key });
+        let claims = json!({
+            "identityPublicKey": public_key,
+            "exp": now() / 1000 + 3600,
+            "extraData": { "displayName": "Steve", "XUID": "", "identity": "id" },
+        });
+        let chain = vec![jwt::encode(&header, &claims, &self.key)];
+        let client_data = jwt::encode(&header, &json!({ "SkinData": "" }), &self.key);
+        let login = Login::create(self.protocol as i32, chain, client_data);
+        self.send_game_packets(vec![Packet::Login(login)]);
+    }
+
+    /// Derives the session key from the handshake of the server, everything after it is encrypted
+    pub fn start_encryption(&mut self, handshake: &ServerToClientHandshake) {
+        let (header, claims) = jwt::decode(handshake.token()).unwrap();
+        let server_key = STANDARD.decode(header["x5u"].as_str().unwrap()).unwrap();
+        let server_key = PublicKey::from_public_key_der(&server_key).unwrap();
+        let salt = STANDARD.decode(claims["salt"].as_str().unwrap()).unwrap();
+        let shared_secret = p384::ecdh::diffie_hellman(self.key.as_nonzero_scalar(), server_key.as_affine());
+        let mode = self.registry().cipher_mode();
+        self.encryption = Some(Encryption::from_shared_secret(&salt, shared_secret.raw_secret_bytes(), mode));
+    }
+
+    /// Compresses and encrypts `packets` into a game packet and sends it
+    pub fn send_game_packets(&mut self, packets: Vec<Packet>) {
+        let registry = self.registry();
+        let packets = packets.iter().map(|packet| registry.encode(packet).unwrap()).collect();
+        let payload = self.compression.compress(&Batch::new(packets).encode(Vec::new())).unwrap();
+        let payload = match &mut self.encryption {
+            Some(encryption) => encryption.encrypt(&payload),
+            None => payload,
+        };
+        self.send_game_packet(&payload);
+    }
+
+    /// Sends a game packet with `payload` as it is
+    pub fn send_game_packet(&mut self, payload: &[u8]) {
+        let mut game_packet = vec![PacketId::GamePacket as u8];
+        game_packet.extend_from_slice(payload);
+        self.send_packet(&game_packet);
+    }
+
+    /// Waits for the next game packet and decodes the packets in it
+    pub fn recv_game_packets(&mut self) -> Vec<Packet> {
+        loop {
+            let packet = self.recv_packet();
+            if packet[0] != PacketId::GamePacket as u8 {
+                continue;
+            }
+            let payload = match &mut self.encryption {
+                Some(encryption) => encryption.decrypt(&packet[1..]).unwrap(),
+                None => packet[1..].to_vec(),
+            };
+            let batch = Batch::decode(&self.compression.decompress(&payload).unwrap()).unwrap();
+            let registry = self.registry();
+            return batch.packets.iter().map(|packet| registry.decode(packet).unwrap()).collect();
+        }
+    }
+
+    /// Sends `packet` in reliable ordered frames
+    pub fn send_packet(&mut self, packet: &[u8]) {
+        for datagram in self.frames.encapsulate(packet, MTU_SIZE as usize) {
+            self.send(&datagram);
+        }
+    }
+
+    /// Waits for the next packet in the frames sent by the server and acknowledges them
+    pub fn recv_packet(&mut self) -> Vec<u8> {
+        let deadline = Instant::now() + TIMEOUT;
+        while Instant::now() < deadline {
+            if let Some(packet) = self.received.pop_front() {
+                return packet;
+            }
+            let datagram = match self.recv() {
+                Some(datagram) => datagram,
+                None => continue,
+            };
+            let packet_type = PacketType::from_u8(datagram[0]);
+            if !packet_type.is_connected_to_peer || packet_type.is_ack || packet_type.is_nak {
+                continue;
+            }
+            self.received.extend(self.received_frames.receive(&datagram).unwrap());
+            for acknowledgement in self.received_frames.take_acknowledgements() {
+                self.send(&acknowledgement);
+            }
+        }
+        panic!("server did not send a packet in time");
+    }
+
+    fn send(&self, datagram: &[u8]) {
+        self.sock.send_to(datagram, self.server).unwrap();
+    }
+
+    fn recv(&self) -> Option<Vec<u8>> {
+        let mut buff = [0; 2048];
+        let (len, _) = self.sock.recv_from(&mut buff).ok()?;
+        Some(buff[..len].to_vec())
+    }
+
+    fn recv_raw(&self) -> Vec<u8> {
+        let deadline = Instant::now() + TIMEOUT;
+        while Instant::now() < deadline {
+            if let Some(datagram) = self.recv() {
+                return datagram;
+            }
+        }
+        panic!("server did not answer in time");
+    }
+}
+
+/// Milliseconds since the unix epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+}
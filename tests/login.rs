@@ -0,0 +1,47 @@
+mod common;
+
+use common::{start_server, TestClient};
+use limonite::bedrock::batch::Batch;
+use limonite::bedrock::packets::{ClientToServerHandshake, Packet, PlayStatusType};
+
+#[test]
+fn offline_ping_to_login() {
+    let server = start_server(|server| server.bedrock_settings.set_encryption(false));
+    let mut client = TestClient::new(server);
+    let pong = client.ping();
+    assert!(pong.advertisement().starts_with("MCPE;Limonite;"), "{}", pong.advertisement());
+
+    client.connect();
+    client.request_network_settings();
+    client.login();
+    match &client.recv_game_packets()[..] {
+        [Packet::PlayStatus(status)] => assert_eq!(status.status(), PlayStatusType::LoginSuccess),
+        _ => panic!("expected PlayStatus"),
+    }
+    assert!(matches!(client.recv_game_packets()[..], [Packet::ResourcePacksInfo(_)]));
+}
+
+#[test]
+fn tampered_packets_close_the_session() {
+    let server = start_server(|server| server.bedrock_settings.set_encryption(true));
+    let mut client = TestClient::new(server);
+    client.ping();
+    client.connect();
+    client.request_network_settings();
+    client.login();
+    match client.recv_game_packets().remove(0) {
+        Packet::ServerToClientHandshake(handshake) => client.start_encryption(&handshake),
+        _ => panic!("expected ServerToClientHandshake"),
+    }
+
+    let registry = client.registry();
+    let handshake = registry.encode(&Packet::ClientToServerHandshake(ClientToServerHandshake::create())).unwrap();
+    let payload = client.compression.compress(&Batch::new(vec![handshake]).encode(Vec::new())).unwrap();
+    let mut payload = client.encryption.as_mut().unwrap().encrypt(&payload);
+    payload[0] ^= 1;
+    client.send_game_packet(&payload);
+    match &client.recv_game_packets()[..] {
+        [Packet::Disconnect(disconnect)] => assert_eq!(disconnect.message(), "disconnectionScreen.badPacket"),
+        _ => panic!("expected Disconnect"),
+    }
+}